@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS payment_addresses_expires_at;
+
+ALTER TABLE payment_addresses DROP COLUMN expires_at;
@@ -0,0 +1,4 @@
+-- Optional expiry for payment addresses, NULL means the address never expires
+ALTER TABLE payment_addresses ADD COLUMN expires_at TIMESTAMP;
+
+CREATE INDEX payment_addresses_expires_at ON payment_addresses (expires_at);
@@ -0,0 +1,15 @@
+-- Audit log of changes to payment addresses, kept even after the address itself is removed
+CREATE TABLE IF NOT EXISTS address_events (
+    id SERIAL PRIMARY KEY,
+    domain VARCHAR(255) NOT NULL,
+    username VARCHAR(255) NOT NULL,
+    kind VARCHAR(64) NOT NULL,
+    client_ip VARCHAR(64),
+    user_agent TEXT,
+    old_destination TEXT,
+    new_destination TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX address_events_domain_users ON address_events (domain, username);
+CREATE INDEX address_events_client_ip ON address_events (client_ip);
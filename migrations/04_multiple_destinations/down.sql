@@ -0,0 +1,13 @@
+ALTER TABLE payment_addresses ADD COLUMN lnurl TEXT;
+
+UPDATE payment_addresses
+SET lnurl = d.destination
+FROM payment_address_destinations d
+WHERE d.payment_address_id = payment_addresses.id
+  AND d.position = (
+    SELECT MIN(position) FROM payment_address_destinations WHERE payment_address_id = payment_addresses.id
+  );
+
+ALTER TABLE payment_addresses ALTER COLUMN lnurl SET NOT NULL;
+
+DROP TABLE IF EXISTS payment_address_destinations;
@@ -0,0 +1,13 @@
+-- Ordered list of destinations per payment address, the lowest position is tried first
+CREATE TABLE IF NOT EXISTS payment_address_destinations (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER NOT NULL REFERENCES payment_addresses (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    destination TEXT NOT NULL,
+    UNIQUE (payment_address_id, position)
+);
+
+INSERT INTO payment_address_destinations (payment_address_id, position, destination)
+SELECT id, 0, lnurl FROM payment_addresses;
+
+ALTER TABLE payment_addresses DROP COLUMN lnurl;
@@ -0,0 +1,5 @@
+ALTER TABLE payment_address_destinations DROP COLUMN last_selected_at;
+ALTER TABLE payment_address_destinations DROP COLUMN selection_count;
+ALTER TABLE payment_address_destinations DROP COLUMN weight;
+
+ALTER TABLE payment_addresses DROP COLUMN routing_policy;
@@ -0,0 +1,7 @@
+-- How a destination is picked when the manifest of an address is requested
+ALTER TABLE payment_addresses ADD COLUMN routing_policy VARCHAR(32) NOT NULL DEFAULT 'failover';
+
+-- Relative weight for weighted routing and statistics on how often a destination was chosen
+ALTER TABLE payment_address_destinations ADD COLUMN weight INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE payment_address_destinations ADD COLUMN selection_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE payment_address_destinations ADD COLUMN last_selected_at TIMESTAMP;
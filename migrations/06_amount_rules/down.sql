@@ -0,0 +1 @@
+DROP TABLE IF EXISTS amount_rules;
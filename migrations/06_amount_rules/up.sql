@@ -0,0 +1,11 @@
+-- Rules routing payments to a specific destination depending on the amount, evaluated in order.
+-- A rule matches amounts in [min_msat, max_msat), NULL bounds are unbounded.
+CREATE TABLE IF NOT EXISTS amount_rules (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER NOT NULL REFERENCES payment_addresses (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    min_msat BIGINT,
+    max_msat BIGINT,
+    destination_position INTEGER NOT NULL,
+    UNIQUE (payment_address_id, position)
+);
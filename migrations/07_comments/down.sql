@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS payment_comments;
+
+ALTER TABLE payment_addresses DROP COLUMN store_comments;
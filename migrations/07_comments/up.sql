@@ -0,0 +1,12 @@
+-- Whether LUD-12 comments sent with payments are stored for the owner of the address
+ALTER TABLE payment_addresses ADD COLUMN store_comments BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS payment_comments (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER NOT NULL REFERENCES payment_addresses (id) ON DELETE CASCADE,
+    amount_msat BIGINT NOT NULL,
+    comment TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX payment_comments_address ON payment_comments (payment_address_id);
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS payment_payer_data;
+
+ALTER TABLE payment_addresses DROP COLUMN payer_data;
@@ -0,0 +1,13 @@
+-- LUD-18 payer data requested by the owner of the address, as the JSON `payerData` object of
+-- the manifest
+ALTER TABLE payment_addresses ADD COLUMN payer_data TEXT;
+
+CREATE TABLE IF NOT EXISTS payment_payer_data (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER NOT NULL REFERENCES payment_addresses (id) ON DELETE CASCADE,
+    amount_msat BIGINT NOT NULL,
+    payer_data TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX payment_payer_data_address ON payment_payer_data (payment_address_id);
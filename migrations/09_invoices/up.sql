@@ -0,0 +1,14 @@
+-- Invoices issued through proxied callbacks, tracked to answer LUD-21 verify requests
+CREATE TABLE IF NOT EXISTS invoices (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER NOT NULL REFERENCES payment_addresses (id) ON DELETE CASCADE,
+    verify_id VARCHAR NOT NULL UNIQUE,
+    pr TEXT NOT NULL,
+    amount_msat BIGINT NOT NULL,
+    upstream_verify TEXT,
+    settled BOOLEAN NOT NULL DEFAULT FALSE,
+    preimage VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX invoices_address ON invoices (payment_address_id);
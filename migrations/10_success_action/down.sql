@@ -0,0 +1 @@
+ALTER TABLE payment_addresses DROP COLUMN success_action;
@@ -0,0 +1,2 @@
+-- LUD-09 success action injected into proxied invoice responses, as JSON
+ALTER TABLE payment_addresses ADD COLUMN success_action TEXT;
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS invoices_pending_zaps;
+
+ALTER TABLE invoices DROP COLUMN zap_receipt_published_at;
+ALTER TABLE invoices DROP COLUMN zap_request;
@@ -0,0 +1,6 @@
+-- NIP-57 zap request sent along with the invoice request, and when its zap receipt was published
+ALTER TABLE invoices ADD COLUMN zap_request TEXT;
+ALTER TABLE invoices ADD COLUMN zap_receipt_published_at TIMESTAMP;
+
+CREATE INDEX invoices_pending_zaps ON invoices (created_at)
+    WHERE zap_request IS NOT NULL AND zap_receipt_published_at IS NULL;
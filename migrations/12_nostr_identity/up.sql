@@ -0,0 +1,2 @@
+-- NIP-05 identity served for the username, as JSON
+ALTER TABLE payment_addresses ADD COLUMN nostr TEXT;
@@ -0,0 +1,2 @@
+-- BIP-353 payment instructions published via DNS, as JSON
+ALTER TABLE payment_addresses ADD COLUMN bip353 TEXT;
@@ -0,0 +1,5 @@
+DROP INDEX invoices_pending_forwards;
+ALTER TABLE invoices DROP COLUMN forwarded_at;
+ALTER TABLE invoices DROP COLUMN payer_note;
+ALTER TABLE invoices DROP COLUMN payment_hash;
+ALTER TABLE invoices DROP COLUMN bolt12_offer;
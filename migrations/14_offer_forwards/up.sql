@@ -0,0 +1,9 @@
+-- Invoices issued by the node backend on behalf of BOLT12 offer destinations, forwarded to the
+-- offer once paid
+ALTER TABLE invoices ADD COLUMN bolt12_offer TEXT;
+ALTER TABLE invoices ADD COLUMN payment_hash TEXT;
+ALTER TABLE invoices ADD COLUMN payer_note TEXT;
+ALTER TABLE invoices ADD COLUMN forwarded_at TIMESTAMP;
+
+CREATE INDEX invoices_pending_forwards ON invoices (created_at)
+    WHERE bolt12_offer IS NOT NULL AND forwarded_at IS NULL;
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS owner_sessions;
+DROP TABLE IF EXISTS auth_challenges;
+DROP INDEX IF EXISTS payment_addresses_linking_key;
+ALTER TABLE payment_addresses DROP COLUMN IF EXISTS linking_key;
@@ -0,0 +1,20 @@
+-- LNURL-auth (LUD-04) linking key an address is bound to, allowing its owner to manage it
+-- without the authentication token
+ALTER TABLE payment_addresses ADD COLUMN linking_key VARCHAR;
+
+CREATE INDEX payment_addresses_linking_key ON payment_addresses (linking_key);
+
+-- Outstanding LNURL-auth challenges, the linking key is set once a wallet signed the challenge
+CREATE TABLE IF NOT EXISTS auth_challenges (
+    k1 VARCHAR PRIMARY KEY,
+    linking_key VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Management sessions of owners logged in with LNURL-auth
+CREATE TABLE IF NOT EXISTS owner_sessions (
+    token VARCHAR PRIMARY KEY,
+    linking_key VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL
+);
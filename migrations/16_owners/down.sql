@@ -0,0 +1,17 @@
+-- Sessions of owners without linking key can't be kept
+DELETE FROM owner_sessions
+    WHERE owner_id IN (SELECT id FROM owners WHERE linking_key IS NULL);
+ALTER TABLE owner_sessions ADD COLUMN linking_key VARCHAR;
+UPDATE owner_sessions SET linking_key = owners.linking_key
+    FROM owners WHERE owner_sessions.owner_id = owners.id;
+ALTER TABLE owner_sessions ALTER COLUMN linking_key SET NOT NULL;
+ALTER TABLE owner_sessions DROP COLUMN owner_id;
+
+ALTER TABLE payment_addresses ADD COLUMN linking_key VARCHAR;
+UPDATE payment_addresses SET linking_key = owners.linking_key
+    FROM owners WHERE payment_addresses.owner_id = owners.id;
+DROP INDEX IF EXISTS payment_addresses_owner;
+ALTER TABLE payment_addresses DROP COLUMN owner_id;
+CREATE INDEX payment_addresses_linking_key ON payment_addresses (linking_key);
+
+DROP TABLE IF EXISTS owners;
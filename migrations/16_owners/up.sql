@@ -0,0 +1,26 @@
+-- Owner accounts grouping addresses, logged into with LNURL-auth or an address' token
+CREATE TABLE IF NOT EXISTS owners (
+    id SERIAL PRIMARY KEY,
+    linking_key VARCHAR UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO owners (linking_key)
+    SELECT linking_key FROM payment_addresses WHERE linking_key IS NOT NULL
+    UNION
+    SELECT linking_key FROM owner_sessions;
+
+ALTER TABLE payment_addresses
+    ADD COLUMN owner_id INTEGER REFERENCES owners (id) ON DELETE SET NULL;
+UPDATE payment_addresses SET owner_id = owners.id
+    FROM owners WHERE payment_addresses.linking_key = owners.linking_key;
+DROP INDEX IF EXISTS payment_addresses_linking_key;
+ALTER TABLE payment_addresses DROP COLUMN linking_key;
+CREATE INDEX payment_addresses_owner ON payment_addresses (owner_id);
+
+ALTER TABLE owner_sessions
+    ADD COLUMN owner_id INTEGER REFERENCES owners (id) ON DELETE CASCADE;
+UPDATE owner_sessions SET owner_id = owners.id
+    FROM owners WHERE owner_sessions.linking_key = owners.linking_key;
+ALTER TABLE owner_sessions ALTER COLUMN owner_id SET NOT NULL;
+ALTER TABLE owner_sessions DROP COLUMN linking_key;
@@ -0,0 +1,2 @@
+-- Owners authenticating API requests with NIP-98 signed by their Nostr key
+ALTER TABLE owners ADD COLUMN nostr_pubkey VARCHAR UNIQUE;
@@ -0,0 +1,8 @@
+-- Usernames operators keep from being registered
+CREATE TABLE IF NOT EXISTS reserved_names (
+    domain VARCHAR NOT NULL,
+    username VARCHAR NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (domain, username)
+);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS address_reports;
+ALTER TABLE payment_addresses DROP COLUMN status;
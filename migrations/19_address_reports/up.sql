@@ -0,0 +1,15 @@
+-- Whether an address is served, operators suspend addresses that were reported for abuse
+ALTER TABLE payment_addresses ADD COLUMN status VARCHAR NOT NULL DEFAULT 'active';
+
+-- Abuse reports of addresses, open until an operator resolves them
+CREATE TABLE IF NOT EXISTS address_reports (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER NOT NULL REFERENCES payment_addresses (id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    contact VARCHAR,
+    client_ip VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMP
+);
+
+CREATE INDEX address_reports_address ON address_reports (payment_address_id);
@@ -0,0 +1,5 @@
+-- Outstanding proof-of-work challenges of registrations, removed once solved
+CREATE TABLE IF NOT EXISTS pow_challenges (
+    nonce VARCHAR PRIMARY KEY,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TABLE IF EXISTS webhook_outbox;
+ALTER TABLE payment_addresses DROP COLUMN IF EXISTS webhook_secret;
+ALTER TABLE payment_addresses DROP COLUMN IF EXISTS webhook_url;
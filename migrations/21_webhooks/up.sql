@@ -0,0 +1,38 @@
+-- Endpoint the owner of an address receives its webhooks at, signed with the secret
+ALTER TABLE payment_addresses ADD COLUMN webhook_url TEXT;
+ALTER TABLE payment_addresses ADD COLUMN webhook_secret VARCHAR;
+
+-- Webhooks waiting to be delivered. The address is only set for endpoints configured by its
+-- owner, which are signed with the stored secret instead of the operator's. It isn't a foreign
+-- key so that events of removed addresses are still delivered.
+CREATE TABLE IF NOT EXISTS webhook_outbox (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER,
+    url TEXT NOT NULL,
+    secret VARCHAR,
+    event_id VARCHAR NOT NULL,
+    event VARCHAR NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX webhook_outbox_due ON webhook_outbox (next_attempt_at);
+
+-- Log of webhook delivery attempts
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id SERIAL PRIMARY KEY,
+    payment_address_id INTEGER,
+    url TEXT NOT NULL,
+    event_id VARCHAR NOT NULL,
+    event VARCHAR NOT NULL,
+    attempt INTEGER NOT NULL,
+    status_code INTEGER,
+    error TEXT,
+    delivered BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX webhook_deliveries_address ON webhook_deliveries (payment_address_id);
+CREATE INDEX webhook_deliveries_created_at ON webhook_deliveries (created_at);
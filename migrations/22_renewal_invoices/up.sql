@@ -0,0 +1,10 @@
+-- Invoices of the node backend paying for the renewal of an address, redeemed once renewed
+CREATE TABLE IF NOT EXISTS renewal_invoices (
+    payment_hash VARCHAR PRIMARY KEY,
+    payment_address_id INTEGER NOT NULL REFERENCES payment_addresses (id) ON DELETE CASCADE,
+    amount_msat BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    redeemed_at TIMESTAMP
+);
+
+CREATE INDEX renewal_invoices_address ON renewal_invoices (payment_address_id);
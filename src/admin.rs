@@ -0,0 +1,731 @@
+use std::{
+    fs::File, io::BufReader, net::SocketAddr, path::Path as FsPath, sync::Arc, time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use axum::{
+    Extension, Json, Router,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{self, RootCertStore, server::WebPkiClientVerifier},
+};
+use tracing::{debug, warn};
+
+use crate::AppState;
+use crate::admin_ui::admin_ui_router;
+use crate::api::{AddressEventResponse, WebhookDeliveryResponse, destination_inputs};
+use crate::client::ClientInfo;
+use crate::config::Config;
+use crate::repository::{
+    AddressEventFilter, AddressStatus, AmountRule, PaymentAddressFilter, PaymentAddressStats,
+    RoutingPolicy,
+};
+use crate::service::{
+    AmountRuleInput, Credentials, LnaddrDetails, RotateTokenResponse, RoutingInput,
+};
+
+/// Default number of entries returned by paginated admin endpoints
+const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Maximum number of entries returned by paginated admin endpoints
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Routes of the operator API, all of which require the admin API key or a client certificate,
+/// and of the operator dashboard
+pub fn admin_router(state: AppState) -> Router<AppState> {
+    let api = Router::new()
+        .route("/events", get(list_events_handler))
+        .route("/lnaddresses", get(list_lnaddrs_handler))
+        .route(
+            "/lnaddress/:domain/:username",
+            get(get_lnaddr_handler).delete(remove_lnaddr_handler),
+        )
+        .route(
+            "/lnaddress/:domain/:username/update",
+            post(update_lnaddr_handler),
+        )
+        .route(
+            "/lnaddress/:domain/:username/owner",
+            post(reassign_lnaddr_handler),
+        )
+        .route(
+            "/lnaddress/:domain/:username/rotate-token",
+            post(rotate_token_handler),
+        )
+        .route(
+            "/lnaddress/:domain/:username/stats",
+            get(lnaddr_stats_handler),
+        )
+        .route(
+            "/lnaddress/:domain/:username/status",
+            post(update_status_handler),
+        )
+        .route(
+            "/lnaddress/:domain/:username/reports/resolve",
+            post(resolve_reports_handler),
+        )
+        .route("/reports", get(list_reports_handler))
+        .route("/webhooks/deliveries", get(list_webhook_deliveries_handler))
+        .route(
+            "/reserved-names",
+            get(list_reserved_names_handler).post(reserve_name_handler),
+        )
+        .route(
+            "/reserved-names/:domain/:username",
+            delete(release_name_handler),
+        )
+        .route("/bip353/:domain/zone", get(bip353_zone_handler))
+        .route("/bip353/:domain/sync", post(bip353_sync_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            require_admin,
+        ));
+
+    api.merge(admin_ui_router(state))
+}
+
+/// Marks requests received over a TLS connection whose client certificate was verified against
+/// the admin client CA
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct VerifiedClientCertificate;
+
+/// TLS acceptor of the admin listener if a certificate is configured
+pub fn admin_tls_acceptor(config: &Config) -> Result<Option<TlsAcceptor>> {
+    let Some(cert) = &config.admin_tls_cert else {
+        return Ok(None);
+    };
+    let key = config
+        .admin_tls_key
+        .as_deref()
+        .context("Admin TLS key missing")?;
+    let tls_config = admin_tls_config(cert, key, config.admin_tls_client_ca.as_deref())?;
+
+    Ok(Some(TlsAcceptor::from(Arc::new(tls_config))))
+}
+
+/// Serves the admin API on its own listener. With `mutual_tls` the acceptor must verify client
+/// certificates, which then authenticate all requests of the connection.
+pub async fn serve_admin(
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+    mutual_tls: bool,
+    app: Router,
+) {
+    let Some(acceptor) = acceptor else {
+        if let Err(e) = axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        {
+            warn!(error=%e, "Admin server failed");
+        }
+        return;
+    };
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!(error=%e, "Failed to accept admin connection");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let mut app = app.clone().layer(Extension(ConnectInfo(peer)));
+        if mutual_tls {
+            // The handshake only succeeds with a certificate signed by the client CA
+            app = app.layer(Extension(VerifiedClientCertificate));
+        }
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!(error=%e, %peer, "Admin TLS handshake failed");
+                    return;
+                }
+            };
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app))
+                .await
+            {
+                debug!(error=%e, %peer, "Admin connection failed");
+            }
+        });
+    }
+}
+
+fn admin_tls_config(
+    cert: &FsPath,
+    key: &FsPath,
+    client_ca: Option<&FsPath>,
+) -> Result<rustls::ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid admin TLS certificate")?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?
+        .context("No private key in admin TLS key file")?;
+
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for ca in rustls_pemfile::certs(&mut BufReader::new(File::open(client_ca)?)) {
+                roots
+                    .add(ca?)
+                    .context("Invalid admin client CA certificate")?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("Invalid admin client CA")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    builder
+        .with_single_cert(certs, key)
+        .context("Invalid admin TLS certificate or key")
+}
+
+/// Rejects requests that neither came with a verified client certificate nor carry the admin API
+/// key as bearer token
+async fn require_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if request
+        .extensions()
+        .get::<VerifiedClientCertificate>()
+        .is_some()
+    {
+        return Ok(next.run(request).await);
+    }
+
+    let Some(api_key) = state.config.admin_api_key.as_deref() else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let authorized = request
+        .headers()
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(api_key.as_bytes())));
+
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventsQuery {
+    pub domain: Option<String>,
+    pub username: Option<String>,
+    pub client_ip: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+async fn list_events_handler(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<Vec<AddressEventResponse>>, StatusCode> {
+    let filter = AddressEventFilter {
+        domain: query.domain,
+        username: query.username,
+        client_ip: query.client_ip,
+        limit: query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+        offset: query.offset.unwrap_or(0).max(0),
+    };
+
+    state
+        .service
+        .admin_list_events(filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map(|events| Json(events.into_iter().map(Into::into).collect()))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeliveriesQuery {
+    pub limit: Option<i64>,
+}
+
+/// Most recent webhook delivery attempts to the operator's and owners' endpoints
+async fn list_webhook_deliveries_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Result<Json<Vec<WebhookDeliveryResponse>>, StatusCode> {
+    state
+        .service
+        .admin_list_webhook_deliveries(
+            query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map(|deliveries| Json(deliveries.into_iter().map(Into::into).collect()))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LnaddrsQuery {
+    pub domain: Option<String>,
+    /// Case-insensitive substring of the username or a destination
+    pub search: Option<String>,
+    pub owner_id: Option<i32>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LnaddrsResponse {
+    /// Number of matching addresses, of which `lnaddrs` is the requested page
+    pub total: i64,
+    pub lnaddrs: Vec<AdminLnaddrResponse>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminLnaddrResponse {
+    pub lnaddr: String,
+    pub destinations: Vec<AdminDestinationResponse>,
+    pub routing_policy: RoutingPolicy,
+    pub amount_rules: Vec<AmountRule>,
+    pub owner_id: Option<i32>,
+    pub status: AddressStatus,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminDestinationResponse {
+    pub destination: String,
+    pub weight: u32,
+    pub selection_count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_selected_at: Option<String>,
+}
+
+impl From<LnaddrDetails> for AdminLnaddrResponse {
+    fn from(details: LnaddrDetails) -> Self {
+        Self {
+            lnaddr: details.lnaddr,
+            destinations: details
+                .destinations
+                .into_iter()
+                .map(|destination| AdminDestinationResponse {
+                    destination: destination.destination.to_string(),
+                    weight: destination.weight,
+                    selection_count: destination.selection_count,
+                    last_selected_at: destination.last_selected_at.map(format_time),
+                })
+                .collect(),
+            routing_policy: details.routing_policy,
+            amount_rules: details.amount_rules,
+            owner_id: details.owner_id,
+            status: details.status,
+            created_at: format_time(details.created_at),
+            expires_at: details.expires_at.map(format_time),
+        }
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+async fn list_lnaddrs_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LnaddrsQuery>,
+) -> Result<Json<LnaddrsResponse>, StatusCode> {
+    let filter = PaymentAddressFilter {
+        domain: query.domain,
+        search: query.search.filter(|search| !search.is_empty()),
+        owner_id: query.owner_id,
+        limit: query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+        offset: query.offset.unwrap_or(0).max(0),
+    };
+
+    let page = state
+        .service
+        .admin_list_lnaddrs(filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(LnaddrsResponse {
+        total: page.total,
+        lnaddrs: page.lnaddrs.into_iter().map(Into::into).collect(),
+    }))
+}
+
+async fn get_lnaddr_handler(
+    State(state): State<AppState>,
+    Path((domain, username)): Path<(String, String)>,
+) -> Result<Json<AdminLnaddrResponse>, StatusCode> {
+    state
+        .service
+        .get_lnaddr_details(&domain, &username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+        .map(|details| Json(details.into()))
+}
+
+/// Destinations replacing those of an address, as in the owner's update request
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminUpdateRequest {
+    pub lnurl: String,
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+    #[serde(default)]
+    pub routing_policy: RoutingPolicy,
+    #[serde(default)]
+    pub weights: Vec<u32>,
+    #[serde(default)]
+    pub amount_rules: Vec<AmountRuleInput>,
+}
+
+async fn update_lnaddr_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    Json(payload): Json<AdminUpdateRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let routing = RoutingInput {
+        destinations: destination_inputs(&payload.lnurl, &payload.fallbacks, &payload.weights),
+        policy: payload.routing_policy,
+        amount_rules: payload.amount_rules,
+    };
+    state
+        .service
+        .update_lnaddr(&domain, &username, &Credentials::Admin, &routing, &client)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReassignRequest {
+    /// Owner account to assign the address to, `null` detaches it from its owner
+    pub owner_id: Option<i32>,
+}
+
+async fn reassign_lnaddr_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    Json(payload): Json<ReassignRequest>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .service
+        .link_lnaddr(
+            &domain,
+            &username,
+            &Credentials::Admin,
+            payload.owner_id,
+            &client,
+        )
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusRequest {
+    pub status: AddressStatus,
+}
+
+/// Suspends an address during an investigation or serves it again
+async fn update_status_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    Json(payload): Json<StatusRequest>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .service
+        .set_lnaddr_status(
+            &domain,
+            &username,
+            &Credentials::Admin,
+            payload.status,
+            &client,
+        )
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Issues a new authentication token, e.g. to hand an address over to a new owner
+async fn rotate_token_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+) -> Result<Json<RotateTokenResponse>, StatusCode> {
+    state
+        .service
+        .rotate_token(&domain, &username, &Credentials::Admin, &client)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)
+        .map(Json)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LnaddrStatsResponse {
+    pub lnaddr: String,
+    pub invoices: i64,
+    pub settled_invoices: i64,
+    pub settled_msat: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_invoice_at: Option<String>,
+    pub comments: i64,
+    pub events: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_event_at: Option<String>,
+}
+
+impl LnaddrStatsResponse {
+    fn new(lnaddr: String, stats: PaymentAddressStats) -> Self {
+        Self {
+            lnaddr,
+            invoices: stats.invoices,
+            settled_invoices: stats.settled_invoices,
+            settled_msat: stats.settled_msat,
+            last_invoice_at: stats.last_invoice_at.map(format_time),
+            comments: stats.comments,
+            events: stats.events,
+            last_event_at: stats.last_event_at.map(format_time),
+        }
+    }
+}
+
+/// Usage of an address. Selection counts per destination are part of the address itself.
+async fn lnaddr_stats_handler(
+    State(state): State<AppState>,
+    Path((domain, username)): Path<(String, String)>,
+) -> Result<Json<LnaddrStatsResponse>, StatusCode> {
+    state
+        .service
+        .admin_lnaddr_stats(&domain, &username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+        .map(|stats| {
+            Json(LnaddrStatsResponse::new(
+                format!("{username}@{domain}"),
+                stats,
+            ))
+        })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportResponse {
+    pub id: i32,
+    pub lnaddr: String,
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    pub created_at: String,
+}
+
+/// Open abuse reports, oldest first
+async fn list_reports_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ReportResponse>>, StatusCode> {
+    let reports = state
+        .service
+        .admin_list_reports()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        reports
+            .into_iter()
+            .map(|report| ReportResponse {
+                id: report.id,
+                lnaddr: format!("{}@{}", report.username, report.domain),
+                reason: report.reason,
+                contact: report.contact,
+                client_ip: report.client_ip,
+                created_at: format_time(report.created_at),
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResolveReportsRequest {
+    /// Keeps the address suspended, or suspends it, instead of serving it again
+    pub suspend: bool,
+}
+
+async fn resolve_reports_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    Json(payload): Json<ResolveReportsRequest>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .service
+        .admin_resolve_reports(&domain, &username, payload.suspend, &client)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReservedNamesQuery {
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReservedNameResponse {
+    pub domain: String,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+async fn list_reserved_names_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ReservedNamesQuery>,
+) -> Result<Json<Vec<ReservedNameResponse>>, StatusCode> {
+    let names = state
+        .service
+        .admin_list_reserved_names(query.domain.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        names
+            .into_iter()
+            .map(|name| ReservedNameResponse {
+                domain: name.domain,
+                username: name.username,
+                reason: name.reason,
+                created_at: format_time(name.created_at),
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReserveNameRequest {
+    pub domain: String,
+    pub username: String,
+    pub reason: Option<String>,
+}
+
+async fn reserve_name_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<ReserveNameRequest>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .service
+        .admin_reserve_name(
+            &payload.domain,
+            &payload.username,
+            payload.reason.as_deref(),
+        )
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn release_name_handler(
+    State(state): State<AppState>,
+    Path((domain, username)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    match state.service.admin_release_name(&domain, &username).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn remove_lnaddr_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .service
+        .admin_remove_lnaddr(&domain, &username, &client)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// BIP-353 records of a domain as zone file, to be included in the zone of the domain
+async fn bip353_zone_handler(
+    State(state): State<AppState>,
+    Path(domain): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let zone = state
+        .service
+        .admin_bip353_zone(&domain)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok((
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; charset=utf-8",
+        )],
+        zone,
+    ))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bip353SyncResponse {
+    pub pushed: usize,
+}
+
+/// Pushes all BIP-353 records of a domain to the DNS server
+async fn bip353_sync_handler(
+    State(state): State<AppState>,
+    Path(domain): Path<String>,
+) -> Result<Json<Bip353SyncResponse>, StatusCode> {
+    state
+        .service
+        .admin_sync_bip353(&domain)
+        .await
+        .map_err(|e| {
+            warn!(error=%e, %domain, "Failed to push BIP-353 records");
+            StatusCode::BAD_GATEWAY
+        })
+        .map(|pushed| Json(Bip353SyncResponse { pushed }))
+}
@@ -0,0 +1,845 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Form, Router,
+    extract::{FromRequestParts, Host, OriginalUri, Path, Query, Request, State},
+    http::{HeaderMap, Method, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use bitcoin::hashes::{Hash, HashEngine, hmac, sha256};
+use maud::{DOCTYPE, Markup, html};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::AppState;
+use crate::admin::VerifiedClientCertificate;
+use crate::client::ClientInfo;
+use crate::repository::{AddressEventFilter, AddressReport, AddressStatus, PaymentAddressFilter};
+use crate::service::{BulkResult, Credentials, LnaddrDetails};
+use crate::ui::common_head;
+
+/// Cookie holding the session token of a logged in operator
+const OPERATOR_COOKIE: &str = "lnaddrd_admin";
+/// Lifetime of operator sessions, which can't be revoked before they expire except by changing
+/// the admin API key
+const OPERATOR_SESSION_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+/// Number of addresses per page of the address table
+const PAGE_SIZE: i64 = 50;
+/// Number of events shown on the details page of an address
+const RECENT_EVENTS: i64 = 20;
+
+const BUTTON_CLASS: &str = "text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center";
+const INPUT_CLASS: &str = "block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500";
+
+/// Pages of the operator dashboard. Operators log in with the admin API key, on the admin
+/// listener a verified client certificate suffices.
+pub fn admin_ui_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/ui", get(addresses_page))
+        .route("/ui/lnaddress/:domain/:username", get(address_page))
+        .route("/ui/bulk", post(bulk_submit))
+        .route("/ui/reports", get(reports_page))
+        .route("/ui/reports/resolve", post(resolve_reports_submit))
+        .route("/ui/logout", post(logout_submit))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            require_operator,
+        ))
+        .route("/ui/login", get(login_page).post(login_submit))
+        .route_layer(axum::middleware::from_fn(require_same_origin))
+}
+
+/// Path the admin routes are served under, empty on the admin listener and `/admin` when nested
+/// into the public server
+struct BasePath(String);
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for BasePath {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let path = parts.uri.path();
+        let original = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map_or(path, |OriginalUri(uri)| uri.path());
+        Ok(BasePath(
+            original.strip_suffix(path).unwrap_or_default().to_owned(),
+        ))
+    }
+}
+
+/// Stateless session token, the expiry time authenticated with the admin API key
+fn operator_token(api_key: &str, expires_at: u64) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(api_key.as_bytes());
+    engine.input(format!("lnaddrd-admin:{expires_at}").as_bytes());
+    let mac = hmac::Hmac::<sha256::Hash>::from_engine(engine);
+    format!("{expires_at}.{mac}")
+}
+
+fn verify_operator_token(api_key: &str, token: &str, now: SystemTime) -> bool {
+    let Some(expires_at) = token
+        .split_once('.')
+        .and_then(|(expires_at, _)| expires_at.parse::<u64>().ok())
+    else {
+        return false;
+    };
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    expires_at > now
+        && bool::from(
+            operator_token(api_key, expires_at)
+                .as_bytes()
+                .ct_eq(token.as_bytes()),
+        )
+}
+
+/// Whether the `Origin` or `Referer` of a request is on the requested host
+fn is_same_origin(host: &str, origin: &str) -> bool {
+    url::Url::parse(origin).is_ok_and(|origin| {
+        origin.has_host()
+            && origin[url::Position::BeforeHost..url::Position::AfterPort]
+                .eq_ignore_ascii_case(host)
+    })
+}
+
+/// Rejects form submissions from other sites. Browsers send cookies and client certificates
+/// along with cross-site requests, so they can't tell whether the operator submitted a form.
+async fn require_same_origin(Host(host): Host, request: Request, next: Next) -> Response {
+    if request.method() == Method::POST {
+        // Browsers omitting the origin still send the referer of same-origin forms
+        let origin = request
+            .headers()
+            .get(header::ORIGIN)
+            .or_else(|| request.headers().get(header::REFERER))
+            .and_then(|origin| origin.to_str().ok());
+        if !origin.is_some_and(|origin| is_same_origin(&host, origin)) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| {
+            cookie
+                .trim()
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+}
+
+/// Sends operators without session to the login page
+async fn require_operator(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request
+        .extensions()
+        .get::<VerifiedClientCertificate>()
+        .is_some()
+    {
+        return next.run(request).await;
+    }
+    let Some(api_key) = state.config.admin_api_key.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let logged_in = cookie(request.headers(), OPERATOR_COOKIE)
+        .is_some_and(|token| verify_operator_token(api_key, token, SystemTime::now()));
+    if !logged_in {
+        return Redirect::to(&format!("{base}/ui/login")).into_response();
+    }
+
+    next.run(request).await
+}
+
+fn page(base: &str, title: &str, content: Markup) -> Markup {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head(title))
+            body class="bg-gray-50 min-h-screen" {
+                nav class="bg-white border-b border-gray-200 mb-6" {
+                    div class="max-w-6xl mx-auto px-6 py-3 flex justify-between items-center" {
+                        div class="flex items-center gap-6" {
+                            a href=(format!("{base}/ui")) class="text-xl font-bold text-gray-900" { "lnaddrd operator" }
+                            a href=(format!("{base}/ui")) class="text-blue-600 hover:underline font-medium" { "Addresses" }
+                            a href=(format!("{base}/ui/reports")) class="text-blue-600 hover:underline font-medium" { "Reports" }
+                        }
+                        form method="post" action=(format!("{base}/ui/logout")) {
+                            button type="submit" class="text-gray-900 bg-white border border-gray-300 hover:bg-gray-100 font-medium rounded-lg text-sm px-4 py-2" { "Log out" }
+                        }
+                    }
+                }
+                main class="max-w-6xl mx-auto px-6 pb-6" { (content) }
+            }
+        }
+    }
+}
+
+fn error_page(base: &str, message: &str) -> Markup {
+    page(
+        base,
+        "Error",
+        html! {
+            div class="p-6 bg-white rounded-lg shadow-lg text-center" {
+                h1 class="text-2xl font-bold mb-4 text-red-700" { "Error" }
+                p class="mb-6 text-red-600 font-mono break-all" { (message) }
+                a href=(format!("{base}/ui")) class="text-blue-600 hover:underline font-medium" { "Back to addresses" }
+            }
+        },
+    )
+}
+
+fn status_badge(status: AddressStatus) -> Markup {
+    let class = match status {
+        AddressStatus::Active => "text-green-800 bg-green-100",
+        AddressStatus::Paused => "text-yellow-800 bg-yellow-100",
+        AddressStatus::Suspended => "text-red-800 bg-red-100",
+    };
+    html! {
+        span class={"text-xs font-medium px-2.5 py-0.5 rounded " (class)} { (status) }
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    api_key: String,
+}
+
+fn login_markup(base: &str, error: Option<&str>) -> Markup {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("Operator login"))
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
+                    h1 class="text-3xl font-bold mb-6 text-center text-gray-900" { "Operator login" }
+                    @if let Some(error) = error {
+                        p class="mb-4 text-center text-red-600" { (error) }
+                    }
+                    form method="post" action=(format!("{base}/ui/login")) class="space-y-6" {
+                        div {
+                            label for="api_key" class="block mb-2 text-sm font-medium text-gray-900" { "Admin API key" }
+                            input type="password" name="api_key" id="api_key" required class=(INPUT_CLASS) {}
+                        }
+                        button type="submit" class={"w-full " (BUTTON_CLASS)} { "Log in" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn login_page(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+    request: Request,
+) -> Response {
+    if request
+        .extensions()
+        .get::<VerifiedClientCertificate>()
+        .is_some()
+    {
+        return Redirect::to(&format!("{base}/ui")).into_response();
+    }
+    if state.config.admin_api_key.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    Html(login_markup(&base, None).into_string()).into_response()
+}
+
+async fn login_submit(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+    Form(form): Form<LoginForm>,
+) -> Response {
+    let Some(api_key) = state.config.admin_api_key.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !bool::from(form.api_key.as_bytes().ct_eq(api_key.as_bytes())) {
+        return Html(login_markup(&base, Some("Invalid API key")).into_string()).into_response();
+    }
+
+    let expires_at = (SystemTime::now() + OPERATOR_SESSION_LIFETIME)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let cookie = format!(
+        "{OPERATOR_COOKIE}={}; Path={base}/ui; Max-Age={}; HttpOnly; Secure; SameSite=Strict",
+        operator_token(api_key, expires_at),
+        OPERATOR_SESSION_LIFETIME.as_secs()
+    );
+    (
+        [(header::SET_COOKIE, cookie)],
+        Redirect::to(&format!("{base}/ui")),
+    )
+        .into_response()
+}
+
+async fn logout_submit(BasePath(base): BasePath) -> impl IntoResponse {
+    let cookie =
+        format!("{OPERATOR_COOKIE}=; Path={base}/ui; Max-Age=0; HttpOnly; Secure; SameSite=Strict");
+    (
+        [(header::SET_COOKIE, cookie)],
+        Redirect::to(&format!("{base}/ui/login")),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct AddressesQuery {
+    domain: Option<String>,
+    /// Case-insensitive substring of the username or a destination
+    search: Option<String>,
+    /// Host serving a destination, matched exactly
+    host: Option<String>,
+    offset: Option<i64>,
+}
+
+/// Addresses of a domain, either a page of those matching the search or all addresses with a
+/// destination at the given host
+async fn addresses_page(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+    Query(query): Query<AddressesQuery>,
+) -> impl IntoResponse {
+    let domains = &state.config.domains;
+    let domain = query
+        .domain
+        .filter(|domain| domains.contains(domain))
+        .or_else(|| domains.first().cloned())
+        .unwrap_or_default();
+    let search = query.search.unwrap_or_default();
+    let host = query.host.unwrap_or_default();
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let (addresses, total) = if host.trim().is_empty() {
+        let filter = PaymentAddressFilter {
+            domain: Some(domain.clone()),
+            search: Some(search.clone()).filter(|search| !search.is_empty()),
+            owner_id: None,
+            limit: PAGE_SIZE,
+            offset,
+        };
+        match state.service.admin_list_lnaddrs(filter).await {
+            Ok(page) => (page.lnaddrs, page.total),
+            Err(e) => return Html(error_page(&base, &e.to_string()).into_string()),
+        }
+    } else {
+        match state
+            .service
+            .admin_list_lnaddrs_by_host(&host, Some(&domain))
+            .await
+        {
+            Ok(addresses) => {
+                let total = addresses.len() as i64;
+                (addresses, total)
+            }
+            Err(e) => return Html(error_page(&base, &e.to_string()).into_string()),
+        }
+    };
+
+    let page_url = |offset: i64| {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("domain", &domain)
+            .append_pair("search", &search)
+            .append_pair("offset", &offset.to_string())
+            .finish();
+        format!("{base}/ui?{query}")
+    };
+    let paginated = host.trim().is_empty();
+
+    let content = html! {
+        div class="mb-4 border-b border-gray-200" {
+            ul class="flex flex-wrap -mb-px text-sm font-medium text-center" {
+                @for tab in domains {
+                    li class="mr-2" {
+                        @let href = format!("{base}/ui?{}", url::form_urlencoded::Serializer::new(String::new()).append_pair("domain", tab).finish());
+                        @if *tab == domain {
+                            a href=(href) class="inline-block p-4 text-blue-600 border-b-2 border-blue-600 rounded-t-lg" { (tab) }
+                        } @else {
+                            a href=(href) class="inline-block p-4 border-b-2 border-transparent rounded-t-lg hover:text-gray-600 hover:border-gray-300" { (tab) }
+                        }
+                    }
+                }
+            }
+        }
+        form method="get" action=(format!("{base}/ui")) class="mb-6 flex flex-wrap gap-4 items-end" {
+            input type="hidden" name="domain" value=(domain);
+            div class="grow" {
+                label for="search" class="block mb-2 text-sm font-medium text-gray-900" { "Username or destination" }
+                input name="search" id="search" value=(search) class=(INPUT_CLASS) {}
+            }
+            div class="grow" {
+                label for="host" class="block mb-2 text-sm font-medium text-gray-900" { "Upstream host" }
+                input name="host" id="host" value=(host) placeholder="wallet.example.com" class=(INPUT_CLASS) {}
+            }
+            button type="submit" class=(BUTTON_CLASS) { "Search" }
+        }
+        form method="post" action=(format!("{base}/ui/bulk")) class="bg-white rounded-lg shadow-lg p-6" {
+            p class="mb-4 text-sm text-gray-700" {
+                (total) " addresses"
+                @if !host.trim().is_empty() { " with a destination at " b { (host.trim()) } }
+            }
+            div class="overflow-x-auto mb-4" {
+                table class="w-full text-sm text-left text-gray-700" {
+                    thead class="text-xs text-gray-900 uppercase bg-gray-50" {
+                        tr {
+                            th class="px-3 py-2" {
+                                input type="checkbox" title="Select all" onclick="document.querySelectorAll('input[name=lnaddr]').forEach(c => c.checked = this.checked)" class="w-4 h-4 border border-gray-300 rounded bg-gray-50" {}
+                            }
+                            th class="px-3 py-2" { "Address" }
+                            th class="px-3 py-2" { "Destinations" }
+                            th class="px-3 py-2" { "Owner" }
+                            th class="px-3 py-2" { "Status" }
+                            th class="px-3 py-2" { "Created" }
+                            th class="px-3 py-2" { "Expires" }
+                        }
+                    }
+                    tbody {
+                        @for address in &addresses {
+                            (address_row(&base, address))
+                        }
+                    }
+                }
+            }
+            div class="flex flex-wrap gap-4 items-end" {
+                div {
+                    label for="action" class="block mb-2 text-sm font-medium text-gray-900" { "With selected addresses" }
+                    select name="action" id="action" class="block p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900" {
+                        @for (value, text) in BULK_ACTIONS {
+                            option value=(value) { (text) }
+                        }
+                    }
+                }
+                button type="submit" class=(BUTTON_CLASS) onclick="return confirm('Apply to all selected addresses?')" { "Apply" }
+            }
+        }
+        @if paginated {
+            div class="flex justify-between mt-4" {
+                @if offset > 0 {
+                    a href=(page_url((offset - PAGE_SIZE).max(0))) class="text-blue-600 hover:underline font-medium" { "Previous" }
+                } @else { span {} }
+                @if offset + PAGE_SIZE < total {
+                    a href=(page_url(offset + PAGE_SIZE)) class="text-blue-600 hover:underline font-medium" { "Next" }
+                }
+            }
+        }
+    };
+    Html(page(&base, "Addresses", content).into_string())
+}
+
+fn address_row(base: &str, address: &LnaddrDetails) -> Markup {
+    let (username, domain) = address.lnaddr.split_once('@').unwrap_or_default();
+    html! {
+        tr class="border-b border-gray-200" {
+            td class="px-3 py-2" {
+                input type="checkbox" name="lnaddr" value=(address.lnaddr) class="w-4 h-4 border border-gray-300 rounded bg-gray-50" {}
+            }
+            td class="px-3 py-2" {
+                a href=(format!("{base}/ui/lnaddress/{domain}/{username}")) class="text-blue-600 hover:underline font-medium" { (address.lnaddr) }
+            }
+            td class="px-3 py-2 font-mono text-xs break-all" {
+                @if let Some(destination) = address.destinations.first() {
+                    (destination.destination)
+                }
+                @if address.destinations.len() > 1 {
+                    span class="text-gray-500" { " +" (address.destinations.len() - 1) " more" }
+                }
+            }
+            td class="px-3 py-2" {
+                @if let Some(owner_id) = address.owner_id { (owner_id) }
+            }
+            td class="px-3 py-2" { (status_badge(address.status)) }
+            td class="px-3 py-2 whitespace-nowrap" { (format_time(address.created_at)) }
+            td class="px-3 py-2 whitespace-nowrap" {
+                @if let Some(expires_at) = address.expires_at { (format_time(expires_at)) }
+            }
+        }
+    }
+}
+
+/// Details of an address with the live health of its destinations and its recent events
+async fn address_page(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+    Path((domain, username)): Path<(String, String)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let service = &state.service;
+    let details = service
+        .get_lnaddr_details(&domain, &username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let events_filter = AddressEventFilter {
+        domain: Some(domain.clone()),
+        username: Some(username.clone()),
+        limit: RECENT_EVENTS,
+        ..Default::default()
+    };
+    let (health, stats, events) = tokio::join!(
+        service.admin_check_destinations(&domain, &username),
+        service.admin_lnaddr_stats(&domain, &username),
+        service.admin_list_events(events_filter),
+    );
+    let health = health
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .unwrap_or_default();
+    let stats = stats
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .unwrap_or_default();
+    let events = events.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let content = html! {
+        div class="bg-white rounded-lg shadow-lg p-6 mb-6" {
+            div class="flex justify-between items-start mb-4" {
+                h1 class="text-2xl font-bold text-gray-900 break-all" { (details.lnaddr) }
+                form method="post" action=(format!("{base}/ui/bulk")) class="flex gap-2" {
+                    input type="hidden" name="lnaddr" value=(details.lnaddr);
+                    @if details.status == AddressStatus::Suspended {
+                        button type="submit" name="action" value="activate" class="text-gray-900 bg-white border border-gray-300 hover:bg-gray-100 font-medium rounded-lg text-sm px-4 py-2" { "Serve again" }
+                    } @else {
+                        button type="submit" name="action" value="suspend" class="text-white bg-yellow-600 hover:bg-yellow-700 font-medium rounded-lg text-sm px-4 py-2" { "Suspend" }
+                    }
+                    button type="submit" name="action" value="remove" onclick="return confirm('Remove this address?')" class="text-white bg-red-700 hover:bg-red-800 font-medium rounded-lg text-sm px-4 py-2" { "Remove" }
+                }
+            }
+            dl class="grid grid-cols-2 gap-2 text-sm text-gray-700" {
+                dt class="font-medium" { "Status" }
+                dd { (status_badge(details.status)) }
+                dt class="font-medium" { "Owner account" }
+                dd { @if let Some(owner_id) = details.owner_id { (owner_id) } @else { "None" } }
+                dt class="font-medium" { "Routing policy" }
+                dd { (format!("{:?}", details.routing_policy)) }
+                dt class="font-medium" { "Created" }
+                dd { (format_time(details.created_at)) }
+                dt class="font-medium" { "Expires" }
+                dd { @if let Some(expires_at) = details.expires_at { (format_time(expires_at)) } @else { "Never" } }
+                dt class="font-medium" { "Invoices" }
+                dd { (stats.invoices) " requested, " (stats.settled_invoices) " settled (" (stats.settled_msat / 1000) " sats)" }
+                dt class="font-medium" { "Stored comments" }
+                dd { (stats.comments) }
+            }
+        }
+        div class="bg-white rounded-lg shadow-lg p-6 mb-6" {
+            h2 class="text-xl font-bold mb-4 text-gray-900" { "Destinations" }
+            div class="overflow-x-auto" {
+                table class="w-full text-sm text-left text-gray-700" {
+                    thead class="text-xs text-gray-900 uppercase bg-gray-50" {
+                        tr {
+                            th class="px-3 py-2" { "Destination" }
+                            th class="px-3 py-2" { "Weight" }
+                            th class="px-3 py-2" { "Selected" }
+                            th class="px-3 py-2" { "Health" }
+                        }
+                    }
+                    tbody {
+                        @for (destination, check) in details.destinations.iter().zip(&health) {
+                            tr class="border-b border-gray-200" {
+                                td class="px-3 py-2 font-mono text-xs break-all" { (destination.destination) }
+                                td class="px-3 py-2" { (destination.weight) }
+                                td class="px-3 py-2 whitespace-nowrap" {
+                                    (destination.selection_count)
+                                    @if let Some(last_selected_at) = destination.last_selected_at {
+                                        br; span class="text-xs text-gray-500" { "last " (format_time(last_selected_at)) }
+                                    }
+                                }
+                                td class="px-3 py-2" {
+                                    @match (&check.error, check.sendable_msat) {
+                                        (Some(error), _) => span class="text-red-600 break-all" { (error) },
+                                        (None, Some((min, max))) => span class="text-green-700" { "Reachable, " (min / 1000) "–" (max / 1000) " sats" },
+                                        (None, None) => span class="text-gray-500" { "Unknown" },
+                                    }
+                                    br; span class="text-xs text-gray-500" { (check.elapsed.as_millis()) " ms" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        div class="bg-white rounded-lg shadow-lg p-6" {
+            h2 class="text-xl font-bold mb-4 text-gray-900" { "Recent events" }
+            @if events.is_empty() {
+                p class="text-sm text-gray-700" { "No events recorded." }
+            } @else {
+                div class="overflow-x-auto" {
+                    table class="w-full text-sm text-left text-gray-700" {
+                        thead class="text-xs text-gray-900 uppercase bg-gray-50" {
+                            tr {
+                                th class="px-3 py-2" { "Time" }
+                                th class="px-3 py-2" { "Event" }
+                                th class="px-3 py-2" { "Client" }
+                                th class="px-3 py-2" { "Destinations" }
+                            }
+                        }
+                        tbody {
+                            @for event in &events {
+                                tr class="border-b border-gray-200" {
+                                    td class="px-3 py-2 whitespace-nowrap" { (format_time(event.created_at)) }
+                                    td class="px-3 py-2" { (event.kind.as_str()) }
+                                    td class="px-3 py-2 text-xs" {
+                                        (event.client_ip.as_deref().unwrap_or("-"))
+                                        @if let Some(user_agent) = &event.user_agent {
+                                            br; span class="text-gray-500 break-all" { (user_agent) }
+                                        }
+                                    }
+                                    td class="px-3 py-2 font-mono text-xs break-all" {
+                                        @if let Some(new_destination) = &event.new_destination { (new_destination) }
+                                        @else if let Some(old_destination) = &event.old_destination { del { (old_destination) } }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Ok(Html(page(&base, &details.lnaddr, content).into_string()))
+}
+
+/// Actions offered for the selected addresses, as form value and label
+const BULK_ACTIONS: [(&str, &str); 3] = [
+    ("suspend", "Suspend"),
+    ("activate", "Serve again"),
+    ("remove", "Remove"),
+];
+
+/// Applies an action to the selected addresses. The form is taken as list of pairs as the
+/// `lnaddr` checkboxes repeat.
+async fn bulk_submit(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+    client: ClientInfo,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Response {
+    let action = fields
+        .iter()
+        .find(|(key, _)| key == "action")
+        .map(|(_, value)| value.as_str())
+        .unwrap_or_default();
+    let lnaddrs = fields
+        .iter()
+        .filter(|(key, _)| key == "lnaddr")
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>();
+    if lnaddrs.is_empty() {
+        return Html(error_page(&base, "No addresses selected").into_string()).into_response();
+    }
+    let status = match action {
+        "suspend" => Some(AddressStatus::Suspended),
+        "activate" => Some(AddressStatus::Active),
+        "remove" => None,
+        action => {
+            return Html(error_page(&base, &format!("Unknown action {action}")).into_string())
+                .into_response();
+        }
+    };
+
+    let service = &state.service;
+    let mut results = Vec::with_capacity(lnaddrs.len());
+    for lnaddr in lnaddrs {
+        let result = match (lnaddr.split_once('@'), status) {
+            (Some((username, domain)), Some(status)) => service
+                .set_lnaddr_status(domain, username, &Credentials::Admin, status, &client)
+                .await
+                .map_err(|e| e.to_string()),
+            (Some((username, domain)), None) => service
+                .admin_remove_lnaddr(domain, username, &client)
+                .await
+                .map_err(|e| e.to_string()),
+            (None, _) => Err("Invalid lightning address".to_owned()),
+        };
+        results.push(BulkResult {
+            lnaddr: lnaddr.to_owned(),
+            error: result.err(),
+        });
+    }
+    if results.iter().all(|result| result.error.is_none()) {
+        return Redirect::to(&format!("{base}/ui")).into_response();
+    }
+
+    let content = html! {
+        div class="bg-white rounded-lg shadow-lg p-6" {
+            h1 class="text-2xl font-bold mb-4 text-gray-900" { "Not all addresses were updated" }
+            ul class="mb-6 divide-y divide-gray-200" {
+                @for result in &results {
+                    li class="py-3" {
+                        p class="font-medium text-gray-900" { (result.lnaddr) }
+                        @match &result.error {
+                            Some(error) => p class="text-sm text-red-600 break-all" { (error) },
+                            None => p class="text-sm text-green-700" { "Done" },
+                        }
+                    }
+                }
+            }
+            a href=(format!("{base}/ui")) class="text-blue-600 hover:underline font-medium" { "Back to addresses" }
+        }
+    };
+    Html(page(&base, "Bulk action", content).into_string()).into_response()
+}
+
+/// Moderation queue of open abuse reports, grouped by address
+async fn reports_page(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+) -> impl IntoResponse {
+    let reports = match state.service.admin_list_reports().await {
+        Ok(reports) => reports,
+        Err(e) => return Html(error_page(&base, &e.to_string()).into_string()),
+    };
+
+    let mut groups: Vec<(String, Vec<AddressReport>)> = Vec::new();
+    for report in reports {
+        let lnaddr = format!("{}@{}", report.username, report.domain);
+        match groups.iter_mut().find(|(group, _)| *group == lnaddr) {
+            Some((_, group)) => group.push(report),
+            None => groups.push((lnaddr, vec![report])),
+        }
+    }
+    let mut queue = Vec::with_capacity(groups.len());
+    for (lnaddr, reports) in groups {
+        let (domain, username) = (&reports[0].domain, &reports[0].username);
+        let status = match state.service.get_lnaddr_details(domain, username).await {
+            Ok(details) => details.map(|details| details.status),
+            Err(e) => return Html(error_page(&base, &e.to_string()).into_string()),
+        };
+        queue.push((lnaddr, status, reports));
+    }
+
+    let content = html! {
+        h1 class="text-2xl font-bold mb-4 text-gray-900" { "Reports" }
+        @if queue.is_empty() {
+            p class="p-6 bg-white rounded-lg shadow-lg text-gray-700" { "No open reports." }
+        }
+        @for (lnaddr, status, reports) in &queue {
+            @let (username, domain) = lnaddr.split_once('@').unwrap_or_default();
+            div class="bg-white rounded-lg shadow-lg p-6 mb-6" {
+                div class="flex flex-wrap justify-between items-start gap-4 mb-4" {
+                    div {
+                        a href=(format!("{base}/ui/lnaddress/{domain}/{username}")) class="text-xl text-blue-600 hover:underline font-bold break-all" { (lnaddr) }
+                        " "
+                        @if let Some(status) = status { (status_badge(*status)) }
+                    }
+                    form method="post" action=(format!("{base}/ui/reports/resolve")) class="flex gap-2" {
+                        input type="hidden" name="lnaddr" value=(lnaddr);
+                        button type="submit" name="action" value="dismiss" class="text-gray-900 bg-white border border-gray-300 hover:bg-gray-100 font-medium rounded-lg text-sm px-4 py-2" { "Dismiss" }
+                        button type="submit" name="action" value="suspend" class="text-white bg-yellow-600 hover:bg-yellow-700 font-medium rounded-lg text-sm px-4 py-2" { "Suspend" }
+                        button type="submit" name="action" value="remove" onclick="return confirm('Remove this address?')" class="text-white bg-red-700 hover:bg-red-800 font-medium rounded-lg text-sm px-4 py-2" { "Remove" }
+                    }
+                }
+                ul class="divide-y divide-gray-200" {
+                    @for report in reports {
+                        li class="py-3 text-sm" {
+                            p class="text-gray-900 whitespace-pre-wrap break-words" { (report.reason) }
+                            p class="text-xs text-gray-500" {
+                                (format_time(report.created_at))
+                                " · " (report.client_ip.as_deref().unwrap_or("unknown client"))
+                                @if let Some(contact) = &report.contact { " · " (contact) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Html(page(&base, "Reports", content).into_string())
+}
+
+#[derive(Deserialize)]
+pub struct ResolveReportsForm {
+    lnaddr: String,
+    action: String,
+}
+
+/// Closes the reports of an address by serving it again, suspending or removing it
+async fn resolve_reports_submit(
+    State(state): State<AppState>,
+    BasePath(base): BasePath,
+    client: ClientInfo,
+    Form(form): Form<ResolveReportsForm>,
+) -> Response {
+    let Some((username, domain)) = form.lnaddr.split_once('@') else {
+        return Html(error_page(&base, "Invalid lightning address").into_string()).into_response();
+    };
+    let service = &state.service;
+    let result = match form.action.as_str() {
+        "dismiss" => {
+            service
+                .admin_resolve_reports(domain, username, false, &client)
+                .await
+        }
+        "suspend" => {
+            service
+                .admin_resolve_reports(domain, username, true, &client)
+                .await
+        }
+        "remove" => service.admin_remove_lnaddr(domain, username, &client).await,
+        action => {
+            return Html(error_page(&base, &format!("Unknown action {action}")).into_string())
+                .into_response();
+        }
+    };
+    match result {
+        Ok(()) => Redirect::to(&format!("{base}/ui/reports")).into_response(),
+        Err(e) => Html(error_page(&base, &e.to_string()).into_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operator_tokens_expire_and_are_bound_to_the_key() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let token = operator_token("key", 1_001);
+
+        assert!(verify_operator_token("key", &token, now));
+        assert!(!verify_operator_token("other key", &token, now));
+        assert!(!verify_operator_token(
+            "key",
+            &token,
+            now + Duration::from_secs(1)
+        ));
+        // The expiry can't be extended without the key
+        let (_, mac) = token.split_once('.').unwrap();
+        assert!(!verify_operator_token("key", &format!("2000.{mac}"), now));
+        assert!(!verify_operator_token("key", "1001", now));
+    }
+
+    #[test]
+    fn same_origin_requires_matching_host_and_port() {
+        assert!(is_same_origin(
+            "admin.example.com",
+            "https://admin.example.com"
+        ));
+        assert!(is_same_origin(
+            "admin.example.com",
+            "https://Admin.Example.com/admin/ui?x=1"
+        ));
+        assert!(is_same_origin("127.0.0.1:8443", "https://127.0.0.1:8443"));
+        assert!(!is_same_origin("127.0.0.1:8443", "https://127.0.0.1:9443"));
+        assert!(!is_same_origin("admin.example.com", "https://evil.example"));
+        assert!(!is_same_origin(
+            "admin.example.com",
+            "https://admin.example.com.evil.example"
+        ));
+        assert!(!is_same_origin("admin.example.com", "null"));
+    }
+}
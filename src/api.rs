@@ -1,13 +1,104 @@
+use std::time::SystemTime;
+
 use anyhow::Result;
 use axum::{
     Json,
-    extract::{Host, Path, State},
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Host, Path, Query, Request, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::{Value, json};
 
 use crate::AppState;
-use crate::service::RegisterResponse;
+use crate::client::ClientInfo;
+use crate::nostr;
+use crate::repository::{
+    AddressEvent, AddressSettings, AddressStatus, PayerData, PaymentComment, RoutingPolicy,
+    WebhookDelivery,
+};
+use crate::service::{
+    AmountRuleInput, Credentials, DestinationInput, InvoiceRequest, InvoiceResponse, PayManifest,
+    PowChallenge, RegisterResponse, RenewResponse, RotateTokenResponse, RoutingInput,
+    SettingsUpdate,
+};
+
+/// JSON request body, optionally authenticated with a NIP-98 `Authorization: Nostr` header
+pub struct NostrAuthJson<T> {
+    /// Hex pubkey that signed the request, if it carried a valid authorization
+    pub pubkey: Option<String>,
+    pub payload: T,
+}
+
+#[axum::async_trait]
+impl<T: DeserializeOwned> FromRequest<AppState> for NostrAuthJson<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = request.into_parts();
+        let Host(host) = Host::from_request_parts(&mut parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let authorization = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Nostr "))
+            .map(ToOwned::to_owned);
+        let method = parts.method.clone();
+        let path_and_query = parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str().to_owned())
+            .unwrap_or_default();
+
+        let body = Bytes::from_request(Request::from_parts(parts, body), state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let pubkey = authorization
+            .map(|token| {
+                let url = url::Url::parse(&format!("https://{host}{path_and_query}"))?;
+                nostr::verify_http_auth(&token, &url, method.as_str(), &body, SystemTime::now())
+            })
+            .transpose()
+            .map_err(|_| StatusCode::UNAUTHORIZED.into_response())?;
+        let Json(payload) = Json::<T>::from_bytes(&body).map_err(IntoResponse::into_response)?;
+
+        Ok(NostrAuthJson { pubkey, payload })
+    }
+}
+
+/// Owner account of the Nostr pubkey that authenticated a request
+async fn nostr_owner(state: &AppState, pubkey: Option<&str>) -> Result<Option<i32>, StatusCode> {
+    match pubkey {
+        Some(pubkey) => state
+            .service
+            .get_nostr_owner(pubkey)
+            .await
+            .map(|owner| Some(owner.id))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+        None => Ok(None),
+    }
+}
+
+/// Credentials of a request, the authentication token takes precedence over NIP-98. Pubkeys
+/// only get an owner account once they register an address, so none is created here.
+async fn request_credentials(
+    state: &AppState,
+    authentication_token: Option<String>,
+    pubkey: Option<&str>,
+) -> Result<Credentials, StatusCode> {
+    if let Some(token) = authentication_token {
+        return Ok(Credentials::Token(token));
+    }
+    let pubkey = pubkey.ok_or(StatusCode::UNAUTHORIZED)?;
+    match state.service.find_nostr_owner(pubkey).await {
+        Ok(Some(owner)) => Ok(Credentials::Owner(owner.id)),
+        Ok(None) => Err(StatusCode::UNAUTHORIZED),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
 
 pub async fn list_domains_handler(
     State(state): State<AppState>,
@@ -20,72 +111,728 @@ pub async fn list_domains_handler(
         .map(Json)
 }
 
+/// LNURL-pay manifest of an address. Errors, e.g. for suspended addresses or unreachable
+/// upstreams, are reported as LUD-06 error responses so wallets can display them.
 pub async fn get_lnaddr_manifest_handler(
     State(state): State<AppState>,
     Host(domain): Host,
     Path(username): Path<String>,
-) -> Result<Json<lnurl::pay::PayResponse>, axum::http::StatusCode> {
-    state
+) -> Result<Json<Value>, axum::http::StatusCode> {
+    match state
         .service
-        .get_lnaddr_manifest(&domain, &username)
+        .get_lnaddr_manifest(&domain, &username, true)
         .await
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(axum::http::StatusCode::NOT_FOUND)
-        .map(Json)
+    {
+        Ok(Some(manifest)) => Ok(Json(
+            serde_json::to_value::<PayManifest>(manifest)
+                .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?,
+        )),
+        Ok(None) => Err(axum::http::StatusCode::NOT_FOUND),
+        Err(e) => Ok(Json(lnurl_error(&e.to_string()))),
+    }
+}
+
+/// LNURL-pay callback for addresses whose callbacks are proxied. Errors are reported as LUD-06
+/// error responses so wallets can display them.
+pub async fn lnurlp_callback_handler(
+    State(state): State<AppState>,
+    Host(domain): Host,
+    Path(username): Path<String>,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Json<Value>, axum::http::StatusCode> {
+    let request = InvoiceRequest {
+        amount_msat: query.amount,
+        destination_id: query.d,
+        comment: query.comment,
+        payer_data: query.payerdata,
+        zap_request: query.nostr,
+    };
+
+    match state.service.get_invoice(&domain, &username, request).await {
+        Ok(Some(invoice)) => Ok(Json(
+            serde_json::to_value::<InvoiceResponse>(invoice)
+                .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?,
+        )),
+        Ok(None) => Err(axum::http::StatusCode::NOT_FOUND),
+        Err(e) => Ok(Json(lnurl_error(&e.to_string()))),
+    }
+}
+
+/// LUD-21 verify endpoint for invoices issued through proxied callbacks
+pub async fn lnurlp_verify_handler(
+    State(state): State<AppState>,
+    Host(domain): Host,
+    Path((username, verify_id)): Path<(String, String)>,
+) -> Result<Json<Value>, axum::http::StatusCode> {
+    match state
+        .service
+        .verify_invoice(&domain, &username, &verify_id)
+        .await
+    {
+        Ok(Some(verify)) => Ok(Json(json!({
+            "status": "OK",
+            "settled": verify.settled,
+            "preimage": verify.preimage,
+            "pr": verify.pr,
+        }))),
+        Ok(None) => Ok(Json(lnurl_error("Not found"))),
+        Err(e) => Ok(Json(lnurl_error(&e.to_string()))),
+    }
 }
 
 pub async fn get_lnaddr_handler(
     State(state): State<AppState>,
     Path((domain, username)): Path<(String, String)>,
 ) -> Result<Json<Value>, axum::http::StatusCode> {
+    let details = state
+        .service
+        .get_lnaddr_details(&domain, &username)
+        .await
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
+
+    let destinations = details
+        .destinations
+        .iter()
+        .map(|d| {
+            json!({
+                "destination": d.destination.to_string(),
+                "url": d.destination.url(),
+                "weight": d.weight,
+                "selection_count": d.selection_count,
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(json!({
+        "lnaddr": details.lnaddr,
+        "lnurl": details.lnurl,
+        "lud17": details.lud17,
+        "url": details.destinations.first().map(|d| d.destination.url()),
+        "routing_policy": details.routing_policy,
+        "amount_rules": details.amount_rules,
+        "settings": details.settings,
+        "destinations": destinations,
+    })))
+}
+
+/// Proof-of-work challenge to solve before registering, 404 if registrations don't require one
+pub async fn register_challenge_handler(
+    State(state): State<AppState>,
+) -> Result<Json<PowChallenge>, axum::http::StatusCode> {
     state
         .service
-        .get_destination(&domain, &username)
+        .create_pow_challenge()
         .await
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
         .ok_or(axum::http::StatusCode::NOT_FOUND)
-        .map(|d| Json(json!({ "url": d.url() })))
 }
 
 pub async fn register_lnaddr_handler(
     State(state): State<AppState>,
-    Json(payload): Json<RegisterRequest>,
+    client: ClientInfo,
+    NostrAuthJson { pubkey, payload }: NostrAuthJson<RegisterRequest>,
 ) -> Result<Json<RegisterResponse>, axum::http::StatusCode> {
     state
         .service
-        .register_lnaddr(&payload.domain, &payload.username, &payload.lnurl)
+        .verify_pow(
+            payload.pow_nonce.as_deref(),
+            payload.pow_solution.as_deref(),
+        )
+        .await
+        .map_err(|_| axum::http::StatusCode::FORBIDDEN)?;
+    // Addresses registered with NIP-98 are owned by the signing pubkey
+    let owner_id = nostr_owner(&state, pubkey.as_deref()).await?;
+    state
+        .service
+        .register_lnaddr(
+            &payload.domain,
+            &payload.username,
+            &payload.routing(),
+            payload.settings.clone(),
+            owner_id,
+            &client,
+        )
         .await
         .map_err(|_| axum::http::StatusCode::BAD_REQUEST)
         .map(Json)
 }
 
+/// BOLT12 offer of an address with its decoded description, for wallets paying offers directly
+pub async fn get_offer_handler(
+    State(state): State<AppState>,
+    Path((domain, username)): Path<(String, String)>,
+) -> Result<impl IntoResponse, axum::http::StatusCode> {
+    let offer = state
+        .service
+        .get_offer(&domain, &username)
+        .await
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
+
+    Ok((
+        [(axum::http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")],
+        Json(offer),
+    ))
+}
+
+/// NIP-05 lookup, answered per domain using the `Host` header like the LNURL-pay manifest
+pub async fn nostr_json_handler(
+    State(state): State<AppState>,
+    Host(domain): Host,
+    Query(query): Query<NostrJsonQuery>,
+) -> Result<impl IntoResponse, axum::http::StatusCode> {
+    let identity = match &query.name {
+        Some(name) => state
+            .service
+            .get_nostr_identity(&domain, name)
+            .await
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?,
+        None => None,
+    };
+
+    let mut names = serde_json::Map::new();
+    let mut relays = serde_json::Map::new();
+    if let (Some(name), Some(identity)) = (query.name, identity) {
+        names.insert(name, Value::String(identity.pubkey.clone()));
+        if !identity.relays.is_empty() {
+            relays.insert(identity.pubkey, json!(identity.relays));
+        }
+    }
+
+    Ok((
+        [(axum::http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")],
+        Json(json!({ "names": names, "relays": relays })),
+    ))
+}
+
 pub async fn remove_lnaddr_handler(
     State(state): State<AppState>,
-    Json(payload): Json<RemoveRequest>,
+    client: ClientInfo,
+    NostrAuthJson { pubkey, payload }: NostrAuthJson<RemoveRequest>,
 ) -> Result<axum::http::StatusCode, axum::http::StatusCode> {
+    let credentials =
+        request_credentials(&state, payload.authentication_token, pubkey.as_deref()).await?;
     state
         .service
-        .remove_lnaddr(
+        .remove_lnaddr(&payload.domain, &payload.username, &credentials, &client)
+        .await
+        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)?;
+
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+pub async fn update_lnaddr_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    NostrAuthJson { pubkey, payload }: NostrAuthJson<UpdateRequest>,
+) -> Result<axum::http::StatusCode, axum::http::StatusCode> {
+    let credentials = request_credentials(
+        &state,
+        payload.authentication_token.clone(),
+        pubkey.as_deref(),
+    )
+    .await?;
+    state
+        .service
+        .update_lnaddr(
             &payload.domain,
             &payload.username,
-            &payload.authentication_token,
+            &credentials,
+            &payload.routing(),
+            &client,
         )
         .await
-        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)?;
+        .map_err(|_| axum::http::StatusCode::BAD_REQUEST)?;
 
     Ok(axum::http::StatusCode::NO_CONTENT)
 }
 
+/// Pauses or resumes an address
+pub async fn update_lnaddr_status_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    NostrAuthJson { pubkey, payload }: NostrAuthJson<StatusRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let credentials = request_credentials(
+        &state,
+        payload.authentication_token.clone(),
+        pubkey.as_deref(),
+    )
+    .await?;
+    state
+        .service
+        .set_lnaddr_status(
+            &payload.domain,
+            &payload.username,
+            &credentials,
+            payload.status,
+            &client,
+        )
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets or removes the webhook endpoint of an address, returning the secret its webhooks are
+/// signed with
+pub async fn update_lnaddr_webhook_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    NostrAuthJson { pubkey, payload }: NostrAuthJson<WebhookRequest>,
+) -> Result<Json<WebhookResponse>, StatusCode> {
+    let credentials = request_credentials(
+        &state,
+        payload.authentication_token.clone(),
+        pubkey.as_deref(),
+    )
+    .await?;
+    let secret = state
+        .service
+        .set_lnaddr_webhook(
+            &payload.domain,
+            &payload.username,
+            &credentials,
+            payload.url.as_deref(),
+            &client,
+        )
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(Json(WebhookResponse {
+        url: payload.url,
+        secret,
+    }))
+}
+
+pub async fn list_lnaddr_webhook_deliveries_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<AuthenticatedRequest>,
+) -> Result<Json<Vec<WebhookDeliveryResponse>>, StatusCode> {
+    state
+        .service
+        .list_lnaddr_webhook_deliveries(
+            &payload.domain,
+            &payload.username,
+            &Credentials::Token(payload.authentication_token),
+        )
+        .await
+        .map_err(|_| StatusCode::UNAUTHORIZED)
+        .map(|deliveries| Json(deliveries.into_iter().map(Into::into).collect()))
+}
+
+pub async fn rotate_token_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<AuthenticatedRequest>,
+) -> Result<Json<RotateTokenResponse>, axum::http::StatusCode> {
+    state
+        .service
+        .rotate_token(
+            &payload.domain,
+            &payload.username,
+            &Credentials::Token(payload.authentication_token),
+            &client,
+        )
+        .await
+        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)
+        .map(Json)
+}
+
+/// Files an abuse report of an address, which anyone may do
+pub async fn report_lnaddr_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<ReportRequest>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .service
+        .report_lnaddr(
+            &payload.domain,
+            &payload.username,
+            &payload.reason,
+            payload.contact.as_deref(),
+            &client,
+        )
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn renew_lnaddr_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<RenewRequest>,
+) -> Result<Json<RenewResponse>, axum::http::StatusCode> {
+    state
+        .service
+        .renew_lnaddr(
+            &payload.domain,
+            &payload.username,
+            &Credentials::Token(payload.authentication_token),
+            payload.payment_hash.as_deref(),
+            &client,
+        )
+        .await
+        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)
+        .map(Json)
+}
+
+pub async fn list_lnaddr_events_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<AuthenticatedRequest>,
+) -> Result<Json<Vec<AddressEventResponse>>, axum::http::StatusCode> {
+    state
+        .service
+        .list_lnaddr_events(
+            &payload.domain,
+            &payload.username,
+            &Credentials::Token(payload.authentication_token),
+        )
+        .await
+        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)
+        .map(|events| Json(events.into_iter().map(Into::into).collect()))
+}
+
+pub async fn update_lnaddr_settings_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<SettingsRequest>,
+) -> Result<Json<AddressSettings>, axum::http::StatusCode> {
+    state
+        .service
+        .update_lnaddr_settings(
+            &payload.domain,
+            &payload.username,
+            &Credentials::Token(payload.authentication_token),
+            payload.settings,
+            &client,
+        )
+        .await
+        .map_err(|_| axum::http::StatusCode::BAD_REQUEST)
+        .map(Json)
+}
+
+pub async fn list_lnaddr_comments_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<AuthenticatedRequest>,
+) -> Result<Json<Vec<PaymentCommentResponse>>, axum::http::StatusCode> {
+    state
+        .service
+        .list_lnaddr_comments(
+            &payload.domain,
+            &payload.username,
+            &Credentials::Token(payload.authentication_token),
+        )
+        .await
+        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)
+        .map(|comments| Json(comments.into_iter().map(Into::into).collect()))
+}
+
+pub async fn list_lnaddr_payer_data_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<AuthenticatedRequest>,
+) -> Result<Json<Vec<PayerDataResponse>>, axum::http::StatusCode> {
+    state
+        .service
+        .list_lnaddr_payer_data(
+            &payload.domain,
+            &payload.username,
+            &Credentials::Token(payload.authentication_token),
+        )
+        .await
+        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)
+        .map(|payer_data| Json(payer_data.into_iter().map(Into::into).collect()))
+}
+
+/// LUD-04 callback of wallets signing a login challenge
+pub async fn lnurl_auth_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LnurlAuthQuery>,
+) -> Json<Value> {
+    match state
+        .service
+        .verify_auth_challenge(&query.k1, &query.sig, &query.key)
+        .await
+    {
+        Ok(()) => Json(json!({ "status": "OK" })),
+        Err(e) => Json(lnurl_error(&e.to_string())),
+    }
+}
+
+/// LUD-06 error response
+fn lnurl_error(reason: &str) -> Value {
+    json!({ "status": "ERROR", "reason": reason })
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallbackQuery {
+    /// Amount in msat
+    pub amount: u64,
+    /// Destination the callback is bound to
+    pub d: Option<i32>,
+    /// LUD-12 comment of the payer
+    pub comment: Option<String>,
+    /// LUD-18 payer data, a JSON object
+    pub payerdata: Option<String>,
+    /// NIP-57 zap request event
+    pub nostr: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LnurlAuthQuery {
+    pub k1: String,
+    /// DER encoded signature of `k1`
+    pub sig: String,
+    /// Linking key of the wallet
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NostrJsonQuery {
+    pub name: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisterRequest {
     pub domain: String,
     pub username: String,
     pub lnurl: String,
+    /// Additional destinations, tried in order if `lnurl` is unreachable when using the failover
+    /// routing policy
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+    #[serde(default)]
+    pub routing_policy: RoutingPolicy,
+    /// Routing weights of `lnurl` followed by the `fallbacks`, defaulting to 1
+    #[serde(default)]
+    pub weights: Vec<u32>,
+    #[serde(default)]
+    pub amount_rules: Vec<AmountRuleInput>,
+    /// Challenge from `/lnaddress/challenge` and its solution, if registrations require a proof
+    /// of work
+    pub pow_nonce: Option<String>,
+    pub pow_solution: Option<String>,
+    /// Initial settings of the address
+    #[serde(flatten)]
+    pub settings: SettingsUpdate,
+}
+
+impl RegisterRequest {
+    pub fn routing(&self) -> RoutingInput {
+        RoutingInput {
+            destinations: destination_inputs(&self.lnurl, &self.fallbacks, &self.weights),
+            policy: self.routing_policy,
+            amount_rules: self.amount_rules.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RemoveRequest {
+    pub domain: String,
+    pub username: String,
+    /// May be omitted if the request is authenticated with NIP-98 by the owning pubkey
+    pub authentication_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateRequest {
+    pub domain: String,
+    pub username: String,
+    /// May be omitted if the request is authenticated with NIP-98 by the owning pubkey
+    pub authentication_token: Option<String>,
+    pub lnurl: String,
+    /// Additional destinations, tried in order if `lnurl` is unreachable when using the failover
+    /// routing policy
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+    #[serde(default)]
+    pub routing_policy: RoutingPolicy,
+    /// Routing weights of `lnurl` followed by the `fallbacks`, defaulting to 1
+    #[serde(default)]
+    pub weights: Vec<u32>,
+    #[serde(default)]
+    pub amount_rules: Vec<AmountRuleInput>,
+}
+
+impl UpdateRequest {
+    pub fn routing(&self) -> RoutingInput {
+        RoutingInput {
+            destinations: destination_inputs(&self.lnurl, &self.fallbacks, &self.weights),
+            policy: self.routing_policy,
+            amount_rules: self.amount_rules.clone(),
+        }
+    }
+}
+
+pub(crate) fn destination_inputs(
+    lnurl: &str,
+    fallbacks: &[String],
+    weights: &[u32],
+) -> Vec<DestinationInput> {
+    std::iter::once(lnurl)
+        .chain(fallbacks.iter().map(String::as_str))
+        .enumerate()
+        .map(|(idx, destination)| DestinationInput {
+            destination: destination.to_owned(),
+            weight: weights.get(idx).copied().unwrap_or(1),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusRequest {
+    pub domain: String,
+    pub username: String,
+    /// May be omitted if the request is authenticated with NIP-98 by the owning pubkey
+    pub authentication_token: Option<String>,
+    /// `active` or `paused`
+    pub status: AddressStatus,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookRequest {
+    pub domain: String,
+    pub username: String,
+    /// May be omitted if the request is authenticated with NIP-98 by the owning pubkey
+    pub authentication_token: Option<String>,
+    /// Endpoint to send the address's webhooks to, removes the endpoint if omitted
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookResponse {
+    pub url: Option<String>,
+    /// Secret the webhooks are signed with, only shown once
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReportRequest {
+    pub domain: String,
+    pub username: String,
+    pub reason: String,
+    /// How the reporter may be contacted about the report
+    pub contact: Option<String>,
+}
+
+/// Request body of owner actions that only need to identify and authenticate the address
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthenticatedRequest {
+    pub domain: String,
+    pub username: String,
+    pub authentication_token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RenewRequest {
     pub domain: String,
     pub username: String,
     pub authentication_token: String,
+    /// Payment hash of the paid renewal invoice, if renewals are paid. Omitting it requests a
+    /// new invoice.
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SettingsRequest {
+    pub domain: String,
+    pub username: String,
+    pub authentication_token: String,
+    #[serde(flatten)]
+    pub settings: SettingsUpdate,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PaymentCommentResponse {
+    pub amount_msat: u64,
+    pub comment: String,
+    pub created_at: String,
+}
+
+impl From<PaymentComment> for PaymentCommentResponse {
+    fn from(comment: PaymentComment) -> Self {
+        Self {
+            amount_msat: comment.amount_msat,
+            comment: comment.comment,
+            created_at: humantime::format_rfc3339_seconds(comment.created_at).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PayerDataResponse {
+    pub amount_msat: u64,
+    pub payer_data: Value,
+    pub created_at: String,
+}
+
+impl From<PayerData> for PayerDataResponse {
+    fn from(payer_data: PayerData) -> Self {
+        Self {
+            amount_msat: payer_data.amount_msat,
+            payer_data: serde_json::from_str(&payer_data.payer_data)
+                .unwrap_or(Value::String(payer_data.payer_data)),
+            created_at: humantime::format_rfc3339_seconds(payer_data.created_at).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressEventResponse {
+    pub id: i32,
+    pub lnaddr: String,
+    pub kind: String,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub old_destination: Option<String>,
+    pub new_destination: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDeliveryResponse {
+    pub id: i32,
+    pub url: String,
+    pub event_id: String,
+    pub event: String,
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+    pub created_at: String,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            url: delivery.url,
+            event_id: delivery.event_id,
+            event: delivery.event,
+            attempt: delivery.attempt,
+            status_code: delivery.status_code,
+            error: delivery.error,
+            delivered: delivery.delivered,
+            created_at: humantime::format_rfc3339_seconds(delivery.created_at).to_string(),
+        }
+    }
+}
+
+impl From<AddressEvent> for AddressEventResponse {
+    fn from(event: AddressEvent) -> Self {
+        Self {
+            id: event.id,
+            lnaddr: format!("{}@{}", event.username, event.domain),
+            kind: event.kind.to_string(),
+            client_ip: event.client_ip,
+            user_agent: event.user_agent,
+            old_destination: event.old_destination,
+            new_destination: event.new_destination,
+            created_at: humantime::format_rfc3339_seconds(event.created_at).to_string(),
+        }
+    }
 }
@@ -0,0 +1,328 @@
+use std::{net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use base64::Engine;
+use hickory_client::{
+    client::{AsyncClient, Signer},
+    op::{Message, MessageType, OpCode, Query, ResponseCode, UpdateMessage},
+    proto::{
+        iocompat::AsyncIoTokioAsStd,
+        rr::dnssec::{rdata::tsig::TsigAlgorithm, tsig::TSigner},
+        xfer::{DnsHandle, DnsResponse, FirstAnswer},
+    },
+    rr::{DNSClass, Name, RData, Record, RecordType, rdata::TXT},
+    tcp::TcpClientStream,
+};
+use tokio::net::TcpStream;
+
+use crate::repository::PaymentInstructions;
+
+/// Maximum length of a single character-string of a TXT record
+const MAX_TXT_STRING_LENGTH: usize = 255;
+/// Accepted difference between the clocks of lnaddrd and the DNS server when using TSIG
+const TSIG_FUDGE: u16 = 300;
+/// Timeout for a dynamic update, including connecting to the DNS server
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Name of the TXT record holding the payment instructions of `username@domain`
+pub fn record_name(username: &str, domain: &str) -> Result<String> {
+    ensure!(
+        !username.is_empty()
+            && username.len() <= 63
+            && username
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'),
+        "Username {username} can't be used in a DNS name, only lowercase letters, digits, - and _ \
+         are allowed"
+    );
+
+    Ok(format!("{username}.user._bitcoin-payment.{domain}."))
+}
+
+/// Checks that the address is a valid mainnet on-chain address
+pub fn check_onchain_address(address: &str) -> Result<()> {
+    bitcoin::Address::from_str(address)
+        .context("Invalid on-chain address")?
+        .require_network(bitcoin::Network::Bitcoin)
+        .context("On-chain address is not a mainnet address")?;
+    Ok(())
+}
+
+/// BIP-21 URI published in the TXT record
+pub fn payment_uri(instructions: &PaymentInstructions) -> String {
+    let address = instructions.onchain.as_deref().unwrap_or_default();
+    match &instructions.offer {
+        Some(offer) => format!("bitcoin:{address}?lno={offer}"),
+        None => format!("bitcoin:{address}"),
+    }
+}
+
+/// BIP-353 TXT record of an address
+#[derive(Debug, Clone)]
+pub struct Bip353Record {
+    /// Fully qualified record name
+    pub name: String,
+    pub uri: String,
+}
+
+impl Bip353Record {
+    /// Character-strings of the TXT record, long URIs are split as they may be at most 255 bytes
+    fn txt_strings(&self) -> Vec<String> {
+        // URIs are ASCII, so splitting at arbitrary bytes is fine
+        self.uri
+            .as_bytes()
+            .chunks(MAX_TXT_STRING_LENGTH)
+            .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+            .collect()
+    }
+
+    /// The record as a line of a zone file
+    pub fn zone_line(&self, ttl: u32) -> String {
+        let strings = self
+            .txt_strings()
+            .iter()
+            .map(|string| format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("{} {ttl} IN TXT {strings}", self.name)
+    }
+}
+
+/// Renders the records of a domain as zone file to be included in its zone
+pub fn zone_file(domain: &str, ttl: u32, records: &[Bip353Record]) -> String {
+    let mut zone = format!("; BIP-353 payment instructions for {domain}\n");
+    for record in records {
+        zone.push_str(&record.zone_line(ttl));
+        zone.push('\n');
+    }
+    zone
+}
+
+/// Pushes BIP-353 records to an authoritative DNS server using RFC 2136 dynamic updates
+pub struct DnsUpdater {
+    server: SocketAddr,
+    zone: Option<Name>,
+    ttl: u32,
+    signer: Option<Arc<Signer>>,
+}
+
+impl DnsUpdater {
+    /// `zone` defaults to the domain of the updated address, `tsig_key` is given as
+    /// `algorithm:name:base64-secret`
+    pub fn new(
+        server: SocketAddr,
+        zone: Option<&str>,
+        ttl: u32,
+        tsig_key: Option<&str>,
+    ) -> Result<Self> {
+        let zone = zone
+            .map(|zone| Name::from_str(zone).context("Invalid DNS update zone"))
+            .transpose()?;
+        let signer = tsig_key
+            .map(|key| {
+                let mut parts = key.splitn(3, ':');
+                let (Some(algorithm), Some(name), Some(secret)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    bail!("TSIG key must be given as algorithm:name:secret");
+                };
+                let algorithm = TsigAlgorithm::from_name(Name::from_ascii(algorithm)?);
+                ensure!(
+                    algorithm.output_len().is_ok(),
+                    "Unsupported TSIG algorithm {algorithm}"
+                );
+                let secret = base64::engine::general_purpose::STANDARD
+                    .decode(secret)
+                    .context("TSIG secret is not valid base64")?;
+                let signer = TSigner::new(secret, algorithm, Name::from_ascii(name)?, TSIG_FUDGE)?;
+                Ok(Arc::new(Signer::from(signer)))
+            })
+            .transpose()
+            .context("Invalid TSIG key")?;
+
+        Ok(Self {
+            server,
+            zone,
+            ttl,
+            signer,
+        })
+    }
+
+    /// Replaces the TXT records at `name` with the given record, or deletes them if `None`
+    pub async fn update(
+        &self,
+        domain: &str,
+        name: &str,
+        record: Option<&Bip353Record>,
+    ) -> Result<()> {
+        let zone = match &self.zone {
+            Some(zone) => zone.clone(),
+            None => Name::from_str(domain)?,
+        };
+        let mut name = Name::from_str(name)?;
+        name.set_fqdn(true);
+        ensure!(zone.zone_of(&name), "{name} is not part of the zone {zone}");
+
+        let mut message = Message::new();
+        message
+            .set_id(rand::random())
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Update)
+            .set_recursion_desired(false);
+        let mut zone_query = Query::new();
+        zone_query
+            .set_name(zone)
+            .set_query_class(DNSClass::IN)
+            .set_query_type(RecordType::SOA);
+        message.add_zone(zone_query);
+
+        // Deleting the whole RRset first makes the update replace any previous record
+        let mut delete = Record::with(name.clone(), RecordType::TXT, 0);
+        delete.set_dns_class(DNSClass::ANY);
+        message.add_update(delete);
+        if let Some(record) = record {
+            let txt = RData::TXT(TXT::new(record.txt_strings()));
+            message.add_update(Record::from_rdata(name, self.ttl, txt));
+        }
+
+        let response = tokio::time::timeout(UPDATE_TIMEOUT, self.send(message))
+            .await
+            .map_err(|_| anyhow!("DNS server did not respond in time"))??;
+        ensure!(
+            response.response_code() == ResponseCode::NoError,
+            "DNS server rejected the update: {}",
+            response.response_code()
+        );
+
+        Ok(())
+    }
+
+    async fn send(&self, message: Message) -> Result<DnsResponse> {
+        let (stream, handle) = TcpClientStream::<AsyncIoTokioAsStd<TcpStream>>::with_timeout(
+            self.server,
+            UPDATE_TIMEOUT,
+        );
+        let (client, background) =
+            AsyncClient::with_timeout(stream, handle, UPDATE_TIMEOUT, self.signer.clone()).await?;
+        let background = tokio::spawn(background);
+
+        let response = client.send(message).first_answer().await;
+        background.abort();
+
+        Ok(response?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFER: &str =
+        "lno1pgx9getnwss8vetrw3hhyuckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg";
+
+    fn record(uri: &str) -> Bip353Record {
+        Bip353Record {
+            name: "alice.user._bitcoin-payment.example.com.".to_owned(),
+            uri: uri.to_owned(),
+        }
+    }
+
+    #[test]
+    fn record_names_are_dns_labels() {
+        assert_eq!(
+            record_name("alice_1-x", "example.com").unwrap(),
+            "alice_1-x.user._bitcoin-payment.example.com."
+        );
+        assert!(record_name("", "example.com").is_err());
+        assert!(record_name("Alice", "example.com").is_err());
+        assert!(record_name("alice.bob", "example.com").is_err());
+        assert!(record_name(&"a".repeat(63), "example.com").is_ok());
+        assert!(record_name(&"a".repeat(64), "example.com").is_err());
+    }
+
+    #[test]
+    fn onchain_addresses_must_be_mainnet() {
+        assert!(check_onchain_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").is_ok());
+        assert!(check_onchain_address("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").is_err());
+        assert!(check_onchain_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5").is_err());
+    }
+
+    #[test]
+    fn payment_uri_combines_address_and_offer() {
+        let instructions = |onchain: Option<&str>, offer: Option<&str>| PaymentInstructions {
+            onchain: onchain.map(ToOwned::to_owned),
+            offer: offer.map(ToOwned::to_owned),
+        };
+
+        assert_eq!(
+            payment_uri(&instructions(Some("bc1qexample"), Some(OFFER))),
+            format!("bitcoin:bc1qexample?lno={OFFER}")
+        );
+        assert_eq!(
+            payment_uri(&instructions(Some("bc1qexample"), None)),
+            "bitcoin:bc1qexample"
+        );
+        assert_eq!(
+            payment_uri(&instructions(None, Some(OFFER))),
+            format!("bitcoin:?lno={OFFER}")
+        );
+    }
+
+    #[test]
+    fn txt_strings_are_split_at_255_bytes() {
+        assert_eq!(record("bitcoin:?lno=x").txt_strings(), ["bitcoin:?lno=x"]);
+        assert_eq!(record(&"a".repeat(255)).txt_strings(), ["a".repeat(255)]);
+
+        let uri = format!("{}{}{}", "a".repeat(255), "b".repeat(255), "c");
+        let strings = record(&uri).txt_strings();
+        assert_eq!(strings, ["a".repeat(255), "b".repeat(255), "c".to_owned()]);
+        assert_eq!(strings.concat(), uri);
+    }
+
+    #[test]
+    fn zone_line_quotes_and_escapes_strings() {
+        assert_eq!(
+            record(&format!("bitcoin:?lno={OFFER}")).zone_line(300),
+            format!("alice.user._bitcoin-payment.example.com. 300 IN TXT \"bitcoin:?lno={OFFER}\"")
+        );
+        assert_eq!(
+            record(r#"a"b\c"#).zone_line(60),
+            r#"alice.user._bitcoin-payment.example.com. 60 IN TXT "a\"b\\c""#
+        );
+        assert_eq!(
+            record(&"a".repeat(256)).zone_line(60),
+            format!(
+                "alice.user._bitcoin-payment.example.com. 60 IN TXT \"{}\" \"a\"",
+                "a".repeat(255)
+            )
+        );
+    }
+
+    #[test]
+    fn zone_file_lists_one_record_per_line() {
+        let records = [record("bitcoin:bc1qone"), record("bitcoin:bc1qtwo")];
+
+        assert_eq!(
+            zone_file("example.com", 3600, &records),
+            "; BIP-353 payment instructions for example.com\n\
+             alice.user._bitcoin-payment.example.com. 3600 IN TXT \"bitcoin:bc1qone\"\n\
+             alice.user._bitcoin-payment.example.com. 3600 IN TXT \"bitcoin:bc1qtwo\"\n"
+        );
+        assert_eq!(
+            zone_file("example.com", 3600, &[]),
+            "; BIP-353 payment instructions for example.com\n"
+        );
+    }
+
+    #[test]
+    fn parses_tsig_keys() {
+        let server = "127.0.0.1:53".parse().unwrap();
+
+        assert!(DnsUpdater::new(server, None, 300, Some("hmac-sha256:lnaddrd:c2VjcmV0")).is_ok());
+        assert!(DnsUpdater::new(server, None, 300, Some("hmac-sha256:lnaddrd")).is_err());
+        assert!(
+            DnsUpdater::new(server, None, 300, Some("hmac-sha256:lnaddrd:not base64")).is_err()
+        );
+        assert!(DnsUpdater::new(server, None, 300, Some("hmac-foo:lnaddrd:c2VjcmV0")).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::{Context, Result, ensure};
+use bech32::{Checksum, Fe32, primitives::decode::CheckedHrpstring};
+use bitcoin::hashes::{Hash, sha256};
+
+/// Tagged field holding the payment hash
+const PAYMENT_HASH: u8 = 1;
+/// Tagged field holding the SHA-256 hash of the description
+const DESCRIPTION_HASH: u8 = 23;
+/// Words of the timestamp preceding the tagged fields
+const TIMESTAMP_WORDS: usize = 7;
+/// Words of the signature and recovery id following the tagged fields
+const SIGNATURE_WORDS: usize = 104;
+/// Words of a tagged field holding 256 bits
+const HASH_WORDS: usize = 52;
+
+/// Bech32 checksum of BOLT11 invoices, which may be longer than the 90 characters of BIP-173
+enum Bolt11Checksum {}
+
+impl Checksum for Bolt11Checksum {
+    type MidstateRepr = u32;
+    const CODE_LENGTH: usize = 7089;
+    const CHECKSUM_LENGTH: usize = 6;
+    const GENERATOR_SH: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    const TARGET_RESIDUE: u32 = 1;
+}
+
+/// BOLT11 invoice, decoded far enough to check what an upstream's invoice commits to. The
+/// signature isn't verified, wallets do that before paying.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invoice {
+    pub payment_hash: [u8; 32],
+    pub description_hash: Option<[u8; 32]>,
+}
+
+impl Invoice {
+    pub fn parse(invoice: &str) -> Result<Invoice> {
+        let invoice = invoice.trim().to_lowercase();
+        let invoice = invoice.strip_prefix("lightning:").unwrap_or(&invoice);
+        let decoded = CheckedHrpstring::new::<Bolt11Checksum>(invoice)
+            .context("Invalid BOLT11 invoice encoding")?;
+        ensure!(
+            decoded.hrp().as_str().starts_with("ln"),
+            "Not a BOLT11 invoice"
+        );
+        let words = decoded
+            .fe32_iter::<std::iter::Empty<u8>>()
+            .map(Fe32::to_u8)
+            .collect::<Vec<_>>();
+        ensure!(
+            words.len() >= TIMESTAMP_WORDS + SIGNATURE_WORDS,
+            "Truncated BOLT11 invoice"
+        );
+
+        let mut payment_hash = None;
+        let mut description_hash = None;
+        let mut fields = &words[TIMESTAMP_WORDS..words.len() - SIGNATURE_WORDS];
+        while !fields.is_empty() {
+            ensure!(fields.len() >= 3, "Truncated BOLT11 tagged field");
+            let tag = fields[0];
+            let length = usize::from(fields[1]) * 32 + usize::from(fields[2]);
+            ensure!(fields.len() >= 3 + length, "Truncated BOLT11 tagged field");
+            let data = &fields[3..3 + length];
+            fields = &fields[3 + length..];
+
+            // Readers skip hashes of the wrong length and use the first of each
+            match tag {
+                PAYMENT_HASH if length == HASH_WORDS && payment_hash.is_none() => {
+                    payment_hash = Some(hash_from_words(data))
+                }
+                DESCRIPTION_HASH if length == HASH_WORDS && description_hash.is_none() => {
+                    description_hash = Some(hash_from_words(data))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Invoice {
+            payment_hash: payment_hash.context("BOLT11 invoice has no payment hash")?,
+            description_hash,
+        })
+    }
+
+    /// Whether the invoice commits to the description by its hash, as LUD-06 and NIP-57 require
+    pub fn commits_to(&self, description: &str) -> bool {
+        self.description_hash == Some(sha256::Hash::hash(description.as_bytes()).to_byte_array())
+    }
+}
+
+/// 256 bit hash from 52 five bit words, dropping the padding
+fn hash_from_words(words: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    let mut accumulator = 0u32;
+    let mut bits = 0;
+    let mut bytes = hash.iter_mut();
+    for word in words {
+        accumulator = (accumulator << 5) | u32::from(*word);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            if let Some(byte) = bytes.next() {
+                *byte = (accumulator >> bits) as u8;
+            }
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bech32::{ByteIterExt, Fe32IterExt, Hrp};
+
+    const PAYMENT: [u8; 32] = [0x01; 32];
+    const DESCRIPTION: [u8; 32] = [0x02; 32];
+
+    fn hash_words(hash: [u8; 32]) -> Vec<u8> {
+        hash.into_iter().bytes_to_fes().map(Fe32::to_u8).collect()
+    }
+
+    /// Invoice with the given tagged fields and an empty signature
+    fn encode(fields: &[(u8, Vec<u8>)]) -> String {
+        let mut words = Vec::new();
+        for (tag, data) in fields {
+            words.extend([*tag, (data.len() / 32) as u8, (data.len() % 32) as u8]);
+            words.extend(data);
+        }
+        encode_fields(words)
+    }
+
+    fn encode_fields(fields: Vec<u8>) -> String {
+        let mut words = vec![0; TIMESTAMP_WORDS];
+        words.extend(fields);
+        words.extend([0; SIGNATURE_WORDS]);
+        words
+            .into_iter()
+            .map(|word| Fe32::try_from(word).unwrap())
+            .with_checksum::<Bolt11Checksum>(&Hrp::parse("lnbc").unwrap())
+            .chars()
+            .collect()
+    }
+
+    #[test]
+    fn parses_committed_hashes() {
+        let invoice = encode(&[
+            (PAYMENT_HASH, hash_words(PAYMENT)),
+            (DESCRIPTION_HASH, hash_words(DESCRIPTION)),
+        ]);
+        let expected = Invoice {
+            payment_hash: PAYMENT,
+            description_hash: Some(DESCRIPTION),
+        };
+        assert_eq!(Invoice::parse(&invoice).unwrap(), expected);
+        assert_eq!(Invoice::parse(&invoice.to_uppercase()).unwrap(), expected);
+        assert_eq!(
+            Invoice::parse(&format!("lightning:{invoice}")).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn commits_to_the_hashed_description() {
+        let description = r#"{"kind":9734}"#;
+        let hash = sha256::Hash::hash(description.as_bytes()).to_byte_array();
+        let invoice = encode(&[
+            (PAYMENT_HASH, hash_words(PAYMENT)),
+            (DESCRIPTION_HASH, hash_words(hash)),
+        ]);
+        let invoice = Invoice::parse(&invoice).unwrap();
+        assert!(invoice.commits_to(description));
+        assert!(!invoice.commits_to(r#"{"kind":9735}"#));
+
+        let invoice = Invoice::parse(&encode(&[(PAYMENT_HASH, hash_words(PAYMENT))])).unwrap();
+        assert!(!invoice.commits_to(description));
+    }
+
+    #[test]
+    fn skips_hashes_of_the_wrong_length() {
+        let invoice = encode(&[
+            (PAYMENT_HASH, vec![0; 51]),
+            (DESCRIPTION_HASH, vec![0; 53]),
+            (PAYMENT_HASH, hash_words(PAYMENT)),
+        ]);
+        let invoice = Invoice::parse(&invoice).unwrap();
+        assert_eq!(invoice.payment_hash, PAYMENT);
+        assert_eq!(invoice.description_hash, None);
+    }
+
+    #[test]
+    fn uses_the_first_hash_of_each_kind() {
+        let invoice = encode(&[
+            (DESCRIPTION_HASH, hash_words(DESCRIPTION)),
+            (PAYMENT_HASH, hash_words(PAYMENT)),
+            (DESCRIPTION_HASH, hash_words(PAYMENT)),
+            (PAYMENT_HASH, hash_words(DESCRIPTION)),
+        ]);
+        let invoice = Invoice::parse(&invoice).unwrap();
+        assert_eq!(invoice.payment_hash, PAYMENT);
+        assert_eq!(invoice.description_hash, Some(DESCRIPTION));
+    }
+
+    #[test]
+    fn rejects_invalid_invoices() {
+        // Description only
+        assert!(Invoice::parse(&encode(&[(DESCRIPTION_HASH, hash_words(DESCRIPTION))])).is_err());
+        // Tagged field claiming more words than there are
+        let mut truncated = vec![PAYMENT_HASH, 2, 0];
+        truncated.extend(hash_words(PAYMENT));
+        assert!(Invoice::parse(&encode_fields(truncated)).is_err());
+
+        let invoice = encode(&[(PAYMENT_HASH, hash_words(PAYMENT))]);
+        let mut typo = invoice.clone().into_bytes();
+        let position = typo.len() - 10;
+        typo[position] = if typo[position] == b'q' { b'p' } else { b'q' };
+        assert!(Invoice::parse(std::str::from_utf8(&typo).unwrap()).is_err());
+        assert!(Invoice::parse(&invoice.replacen("lnbc", "tbbc", 1)).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use bech32::{NoChecksum, primitives::decode::CheckedHrpstring};
+use serde::{Deserialize, Serialize};
+
+/// Chain hash of the bitcoin mainnet as used in `offer_chains`
+const BITCOIN_CHAIN_HASH: [u8; 32] = [
+    0x6f, 0xe2, 0x8c, 0x0a, 0xb6, 0xf1, 0xb3, 0x72, 0xc1, 0xa6, 0xa2, 0x46, 0xae, 0x63, 0xf7, 0x4f,
+    0x93, 0x1e, 0x83, 0x65, 0xe1, 0x5a, 0x08, 0x9c, 0x68, 0xd6, 0x19, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+const OFFER_CHAINS: u64 = 2;
+const OFFER_CURRENCY: u64 = 6;
+const OFFER_AMOUNT: u64 = 8;
+const OFFER_DESCRIPTION: u64 = 10;
+const OFFER_ABSOLUTE_EXPIRY: u64 = 14;
+const OFFER_PATHS: u64 = 16;
+const OFFER_ISSUER: u64 = 18;
+const OFFER_ISSUER_ID: u64 = 22;
+
+/// BOLT12 offer, decoded far enough to validate it and to describe it to payers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Offer {
+    /// The offer as `lno1…` string, lowercase and without `+` line continuations
+    #[serde(rename = "offer")]
+    pub encoded: String,
+    /// Fixed amount to pay, any amount can be paid if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_msat: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Seconds since the epoch after which the offer can't be paid anymore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub absolute_expiry: Option<u64>,
+}
+
+impl Offer {
+    /// Parses and validates an offer following the reader requirements of BOLT12
+    pub fn parse(offer: &str) -> Result<Offer> {
+        // Long offers may be split with `+` followed by whitespace
+        let encoded = offer
+            .split('+')
+            .map(str::trim)
+            .collect::<String>()
+            .to_lowercase();
+        ensure!(encoded.starts_with("lno1"), "Not a BOLT12 offer");
+
+        let decoded = CheckedHrpstring::new::<NoChecksum>(&encoded)
+            .context("Invalid BOLT12 offer encoding")?;
+        ensure!(decoded.hrp().as_str() == "lno", "Not a BOLT12 offer");
+        let data = decoded.byte_iter().collect::<Vec<u8>>();
+
+        let mut parsed = Offer {
+            encoded: encoded.clone(),
+            amount_msat: None,
+            description: None,
+            issuer: None,
+            absolute_expiry: None,
+        };
+        let mut has_currency = false;
+        let mut has_paths = false;
+        let mut has_issuer_id = false;
+        let mut last_type = None;
+        let mut reader = data.as_slice();
+        while !reader.is_empty() {
+            let tlv_type = read_bigsize(&mut reader)?;
+            let length = usize::try_from(read_bigsize(&mut reader)?)?;
+            ensure!(length <= reader.len(), "Truncated BOLT12 offer");
+            let (value, rest) = reader.split_at(length);
+            reader = rest;
+
+            ensure!(
+                last_type.is_none_or(|last| tlv_type > last),
+                "BOLT12 offer fields are not in ascending order"
+            );
+            last_type = Some(tlv_type);
+            ensure!(
+                (1..80).contains(&tlv_type) || (1_000_000_000..2_000_000_000).contains(&tlv_type),
+                "BOLT12 offer contains a non-offer field"
+            );
+
+            match tlv_type {
+                OFFER_CHAINS => {
+                    ensure!(
+                        !value.is_empty() && value.len() % 32 == 0,
+                        "Invalid offer_chains"
+                    );
+                    ensure!(
+                        value.chunks(32).any(|chain| chain == BITCOIN_CHAIN_HASH),
+                        "BOLT12 offer is not for the bitcoin mainnet"
+                    );
+                }
+                OFFER_CURRENCY => has_currency = true,
+                OFFER_AMOUNT => parsed.amount_msat = Some(read_tu64(value)?),
+                OFFER_DESCRIPTION => {
+                    parsed.description = Some(
+                        String::from_utf8(value.to_vec()).context("Invalid offer_description")?,
+                    )
+                }
+                OFFER_ABSOLUTE_EXPIRY => parsed.absolute_expiry = Some(read_tu64(value)?),
+                OFFER_PATHS => {
+                    ensure!(!value.is_empty(), "Invalid offer_paths");
+                    has_paths = true;
+                }
+                OFFER_ISSUER => {
+                    parsed.issuer =
+                        Some(String::from_utf8(value.to_vec()).context("Invalid offer_issuer")?)
+                }
+                OFFER_ISSUER_ID => {
+                    secp256k1::PublicKey::from_slice(value).context("Invalid offer_issuer_id")?;
+                    has_issuer_id = true;
+                }
+                // Unknown odd fields are fine to ignore, unknown even ones must be understood
+                tlv_type if tlv_type % 2 == 0 => {
+                    // Fields lnaddrd doesn't need to interpret
+                    ensure!(
+                        matches!(tlv_type, 4 | 12 | 20),
+                        "BOLT12 offer contains unknown required field {tlv_type}"
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if has_currency {
+            bail!("BOLT12 offers denominated in a currency are not supported");
+        }
+        ensure!(
+            parsed.amount_msat.is_none() || parsed.description.is_some(),
+            "BOLT12 offer with amount has no description"
+        );
+        ensure!(
+            has_paths || has_issuer_id,
+            "BOLT12 offer has neither issuer id nor blinded paths"
+        );
+
+        Ok(parsed)
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.absolute_expiry
+            .is_some_and(|expiry| SystemTime::UNIX_EPOCH + Duration::from_secs(expiry) <= now)
+    }
+}
+
+impl Display for Offer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.encoded)
+    }
+}
+
+/// Reads a BOLT1 `bigsize` integer
+fn read_bigsize(reader: &mut &[u8]) -> Result<u64> {
+    let (&first, rest) = reader.split_first().context("Truncated BOLT12 offer")?;
+    let (length, min) = match first {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x1_0000),
+        0xff => (8, 0x1_0000_0000),
+        value => {
+            *reader = rest;
+            return Ok(value.into());
+        }
+    };
+    ensure!(rest.len() >= length, "Truncated BOLT12 offer");
+    let (bytes, rest) = rest.split_at(length);
+    let value = bytes
+        .iter()
+        .fold(0u64, |value, &byte| value << 8 | u64::from(byte));
+    ensure!(value >= min, "Non-minimal bigsize in BOLT12 offer");
+    *reader = rest;
+    Ok(value)
+}
+
+/// Reads a BOLT1 truncated `tu64`, which must be minimally encoded
+fn read_tu64(value: &[u8]) -> Result<u64> {
+    ensure!(
+        value.len() <= 8 && value.first() != Some(&0),
+        "Invalid integer in BOLT12 offer"
+    );
+    Ok(value
+        .iter()
+        .fold(0u64, |value, &byte| value << 8 | u64::from(byte)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bech32::Hrp;
+
+    const OFFER: &str =
+        "lno1pgx9getnwss8vetrw3hhyuckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg";
+    /// Compressed secp256k1 generator point, a valid issuer id
+    const ISSUER_ID: [u8; 33] = [
+        0x02, 0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87,
+        0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b, 0x16,
+        0xf8, 0x17, 0x98,
+    ];
+
+    /// Encodes TLV records with single byte types and lengths as offer
+    fn encode(records: &[(u8, &[u8])]) -> String {
+        let data = records
+            .iter()
+            .flat_map(|(tlv_type, value)| {
+                [*tlv_type, u8::try_from(value.len()).unwrap()]
+                    .into_iter()
+                    .chain(value.iter().copied())
+            })
+            .collect::<Vec<_>>();
+        bech32::encode::<NoChecksum>(Hrp::parse("lno").unwrap(), &data).unwrap()
+    }
+
+    #[test]
+    fn parses_offer() {
+        let offer = Offer::parse(OFFER).unwrap();
+        assert_eq!(offer.encoded, OFFER);
+        assert_eq!(offer.amount_msat, None);
+        assert_eq!(offer.description.as_deref(), Some("Test vectors"));
+        assert_eq!(offer.to_string(), OFFER);
+    }
+
+    #[test]
+    fn joins_continuations_and_lowercases() {
+        let (head, tail) = OFFER.split_at(20);
+        let split = format!("{}+\n  {}", head.to_uppercase(), tail.to_uppercase());
+
+        assert_eq!(Offer::parse(&split).unwrap().encoded, OFFER);
+    }
+
+    #[test]
+    fn parses_amount_issuer_and_expiry() {
+        let offer = Offer::parse(&encode(&[
+            (OFFER_CHAINS as u8, &BITCOIN_CHAIN_HASH),
+            (OFFER_AMOUNT as u8, &[0x01, 0x86, 0xa0]),
+            (OFFER_DESCRIPTION as u8, b"Coffee"),
+            (OFFER_ABSOLUTE_EXPIRY as u8, &[0x01, 0x00]),
+            (OFFER_ISSUER as u8, b"Alice"),
+            (OFFER_ISSUER_ID as u8, &ISSUER_ID),
+        ]))
+        .unwrap();
+
+        assert_eq!(offer.amount_msat, Some(100_000));
+        assert_eq!(offer.description.as_deref(), Some("Coffee"));
+        assert_eq!(offer.issuer.as_deref(), Some("Alice"));
+        assert_eq!(offer.absolute_expiry, Some(256));
+        assert!(!offer.is_expired(SystemTime::UNIX_EPOCH + Duration::from_secs(255)));
+        assert!(offer.is_expired(SystemTime::UNIX_EPOCH + Duration::from_secs(256)));
+    }
+
+    #[test]
+    fn ignores_unknown_odd_fields() {
+        assert!(Offer::parse(&encode(&[(OFFER_ISSUER_ID as u8, &ISSUER_ID), (25, b"x")])).is_ok());
+        assert!(Offer::parse(&encode(&[(OFFER_ISSUER_ID as u8, &ISSUER_ID), (24, b"x")])).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_offers() {
+        let issuer_id = (OFFER_ISSUER_ID as u8, ISSUER_ID.as_slice());
+        let invalid = [
+            // Neither issuer id nor paths
+            encode(&[(OFFER_DESCRIPTION as u8, b"Coffee")]),
+            // Amount without description
+            encode(&[(OFFER_AMOUNT as u8, &[0x64]), issuer_id]),
+            // Currency denominated
+            encode(&[
+                (OFFER_CURRENCY as u8, b"USD"),
+                (OFFER_AMOUNT as u8, &[0x64]),
+                (OFFER_DESCRIPTION as u8, b"Coffee"),
+                issuer_id,
+            ]),
+            // Testnet only
+            encode(&[(OFFER_CHAINS as u8, &[0x43; 32]), issuer_id]),
+            // Fields out of order
+            encode(&[issuer_id, (OFFER_DESCRIPTION as u8, b"Coffee")]),
+            // Duplicate field
+            encode(&[
+                (OFFER_DESCRIPTION as u8, b"Coffee"),
+                (OFFER_DESCRIPTION as u8, b"Tea"),
+                issuer_id,
+            ]),
+            // Non-minimal amount
+            encode(&[
+                (OFFER_AMOUNT as u8, &[0x00, 0x64]),
+                (OFFER_DESCRIPTION as u8, b"Coffee"),
+                issuer_id,
+            ]),
+            // Invalid issuer id
+            encode(&[(OFFER_ISSUER_ID as u8, &[0x05; 33])]),
+            // Invoice request field
+            encode(&[issuer_id, (82, b"x")]),
+            // Truncated value
+            format!("{}q", &encode(&[issuer_id])[..20]),
+            // Invoice request instead of offer
+            encode(&[issuer_id]).replacen("lno", "lnr", 1),
+        ];
+
+        for offer in invalid {
+            assert!(Offer::parse(&offer).is_err(), "{offer} should be invalid");
+        }
+    }
+
+    #[test]
+    fn reads_minimal_bigsize_only() {
+        assert_eq!(read_bigsize(&mut [0xfc].as_slice()).unwrap(), 0xfc);
+        assert_eq!(
+            read_bigsize(&mut [0xfd, 0x00, 0xfd].as_slice()).unwrap(),
+            0xfd
+        );
+        assert_eq!(
+            read_bigsize(&mut [0xfe, 0x00, 0x01, 0x00, 0x00].as_slice()).unwrap(),
+            0x1_0000
+        );
+        assert!(read_bigsize(&mut [0xfd, 0x00, 0xfc].as_slice()).is_err());
+        assert!(read_bigsize(&mut [0xfe, 0x00, 0x00, 0xff, 0xff].as_slice()).is_err());
+        assert!(read_bigsize(&mut [0xfd, 0x01].as_slice()).is_err());
+        assert!(read_bigsize(&mut [].as_slice()).is_err());
+    }
+}
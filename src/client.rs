@@ -0,0 +1,73 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{HeaderMap, request::Parts},
+};
+use serde::Serialize;
+
+use crate::AppState;
+
+/// Information about the HTTP client performing a request, recorded in the address event log
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// Client info for actions not triggered by an HTTP request, e.g. background tasks
+    pub fn system() -> Self {
+        Self::default()
+    }
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for ClientInfo {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let peer_ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        let ip = peer_ip
+            .map(|peer_ip| client_ip(peer_ip, &parts.headers, &state.config.trusted_proxies));
+        let user_agent = parts
+            .headers
+            .get(axum::http::header::USER_AGENT)
+            .and_then(|ua| ua.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        Ok(ClientInfo { ip, user_agent })
+    }
+}
+
+/// Determines the client IP, only trusting `X-Forwarded-For` if the request came through one of
+/// the trusted proxies. The header is walked from the right so that clients can't spoof their IP
+/// by sending their own `X-Forwarded-For` header.
+fn client_ip(peer_ip: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer_ip) {
+        return peer_ip;
+    }
+
+    let forwarded_ips = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+
+    forwarded_ips
+        .iter()
+        .rev()
+        .find(|ip| !trusted_proxies.contains(ip))
+        .or(forwarded_ips.first())
+        .copied()
+        .unwrap_or(peer_ip)
+}
@@ -1,7 +1,13 @@
-use std::net::SocketAddr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
 
 use clap::Parser;
 
+use crate::rate_limit::RateLimit;
+
 #[derive(Debug, Clone, Parser)]
 pub struct Config {
     /// One or more domain names to serve. Specify multiple times for multiple domains.
@@ -28,4 +34,212 @@ pub struct Config {
     /// Warning displayed on registration page
     #[clap(long, env = "LNADDRD_WARNING")]
     pub warning: Option<String>,
+
+    /// Number of days newly registered or renewed addresses stay valid. Addresses never expire if
+    /// unset.
+    #[clap(long, env = "LNADDRD_ADDRESS_LIFETIME_DAYS")]
+    pub address_lifetime_days: Option<u64>,
+
+    /// Interval in seconds at which expired addresses are removed from the database
+    #[clap(long, default_value = "3600", env = "LNADDRD_EXPIRY_SWEEP_INTERVAL")]
+    pub expiry_sweep_interval: u64,
+
+    /// Price in sats of renewing an address, paid to an invoice of the node backend. Renewals
+    /// are free if unset.
+    #[clap(long, env = "LNADDRD_RENEWAL_PRICE")]
+    pub renewal_price: Option<u64>,
+
+    /// IP addresses of reverse proxies whose `X-Forwarded-For` header is trusted to contain the
+    /// real client IP
+    #[clap(
+        long,
+        num_args = 1..,
+        env = "LNADDRD_TRUSTED_PROXIES",
+        value_delimiter = ',',
+    )]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Timeout in seconds for requests to upstream LNURL servers before falling back to the next
+    /// destination
+    #[clap(long, default_value = "5", env = "LNADDRD_UPSTREAM_TIMEOUT")]
+    pub upstream_timeout: u64,
+
+    /// Serve manifests with lnaddrd's own callback URL and forward invoice requests to the
+    /// upstream. Addresses with amount routing rules are always proxied.
+    #[clap(long, env = "LNADDRD_PROXY_CALLBACKS")]
+    pub proxy_callbacks: bool,
+
+    /// Maximum length of LUD-12 comments accepted on proxied callbacks, 0 disables comments
+    #[clap(long, default_value = "255", env = "LNADDRD_MAX_COMMENT_LENGTH")]
+    pub max_comment_length: u32,
+
+    /// Domains besides the served ones that LUD-09 url success actions may point to. Upstream
+    /// url success actions pointing elsewhere are dropped from proxied invoice responses.
+    #[clap(
+        long,
+        num_args = 1..,
+        env = "LNADDRD_TRUSTED_SUCCESS_ACTION_DOMAINS",
+        value_delimiter = ',',
+    )]
+    pub trusted_success_action_domains: Vec<String>,
+
+    /// Hex encoded Nostr secret key used to sign NIP-57 zap receipts. Invoices of the node backend
+    /// always support zaps, upstream invoices only if the upstream supports zaps and reports
+    /// payments through a LUD-21 verify URL. Without a key upstreams publish their own receipts.
+    #[clap(long, env = "LNADDRD_NOSTR_SECRET_KEY", hide_env_values = true)]
+    pub nostr_secret_key: Option<String>,
+
+    /// Relays zap receipts are published to instead of the relays listed in the zap request
+    #[clap(
+        long,
+        num_args = 1..,
+        env = "LNADDRD_NOSTR_RELAYS",
+        value_delimiter = ',',
+    )]
+    pub nostr_relays: Vec<String>,
+
+    /// Interval in seconds at which unpaid zapped invoices are checked for payment
+    #[clap(long, default_value = "15", env = "LNADDRD_ZAP_CHECK_INTERVAL")]
+    pub zap_check_interval: u64,
+
+    /// Core Lightning REST URL of the node that pays BOLT12 offer destinations on behalf of
+    /// LNURL-pay wallets. Offer destinations can't be paid via LNURL if unset.
+    #[clap(long, env = "LNADDRD_CLN_REST_URL")]
+    pub cln_rest_url: Option<String>,
+
+    /// Rune authorizing the `invoice`, `listinvoices`, `fetchinvoice` and `pay` calls
+    #[clap(long, env = "LNADDRD_CLN_RUNE", hide_env_values = true)]
+    pub cln_rune: Option<String>,
+
+    /// Maximum amount in sats accepted for BOLT12 offer destinations without fixed amount
+    #[clap(long, default_value = "1000000", env = "LNADDRD_BOLT12_MAX_SENDABLE")]
+    pub bolt12_max_sendable: u64,
+
+    /// Interval in seconds at which paid invoices are forwarded to their BOLT12 offer
+    #[clap(long, default_value = "15", env = "LNADDRD_OFFER_FORWARD_INTERVAL")]
+    pub offer_forward_interval: u64,
+
+    /// TTL in seconds of the BIP-353 TXT records
+    #[clap(long, default_value = "3600", env = "LNADDRD_BIP353_TTL")]
+    pub bip353_ttl: u32,
+
+    /// Authoritative DNS server that BIP-353 records are pushed to via RFC 2136 dynamic updates.
+    /// Records are only available through the zone file export if unset.
+    #[clap(long, env = "LNADDRD_DNS_UPDATE_SERVER")]
+    pub dns_update_server: Option<SocketAddr>,
+
+    /// Zone updated on the DNS server, defaults to the domain of the address
+    #[clap(long, env = "LNADDRD_DNS_UPDATE_ZONE")]
+    pub dns_update_zone: Option<String>,
+
+    /// TSIG key authenticating dynamic updates as `algorithm:name:base64-secret`, e.g.
+    /// `hmac-sha256:lnaddrd:c2VjcmV0`
+    #[clap(long, env = "LNADDRD_DNS_TSIG_KEY", hide_env_values = true)]
+    pub dns_tsig_key: Option<String>,
+
+    /// Number of hours owners stay logged in after signing in with LNURL-auth
+    #[clap(
+        long,
+        default_value = "168",
+        env = "LNADDRD_OWNER_SESSION_LIFETIME_HOURS"
+    )]
+    pub owner_session_lifetime_hours: u64,
+
+    /// API key granting access to the admin API, which is disabled if neither it nor
+    /// `--admin-tls-client-ca` is set
+    #[clap(long, env = "LNADDRD_ADMIN_API_KEY", hide_env_values = true)]
+    pub admin_api_key: Option<String>,
+
+    /// Separate address to serve the admin API on, so that it can be kept off the public
+    /// internet. Required with an admin API key unless `--admin-public` is set.
+    #[clap(long, env = "LNADDRD_ADMIN_BIND")]
+    pub admin_bind: Option<SocketAddr>,
+
+    /// Serve the admin API under `/admin` of the public server, rate limited like management
+    /// calls, if `--admin-bind` is unset
+    #[clap(long, env = "LNADDRD_ADMIN_PUBLIC", conflicts_with = "admin_bind")]
+    pub admin_public: bool,
+
+    /// PEM certificate chain to serve the admin API with over TLS, requires `--admin-bind`
+    #[clap(long, env = "LNADDRD_ADMIN_TLS_CERT", requires_all = ["admin_bind", "admin_tls_key"])]
+    pub admin_tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the admin TLS certificate
+    #[clap(long, env = "LNADDRD_ADMIN_TLS_KEY", requires = "admin_tls_cert")]
+    pub admin_tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates admin clients must present a certificate of. Clients authenticated
+    /// this way don't need the admin API key.
+    #[clap(long, env = "LNADDRD_ADMIN_TLS_CLIENT_CA", requires = "admin_tls_cert")]
+    pub admin_tls_client_ca: Option<PathBuf>,
+
+    /// Number of distinct clients whose open abuse reports suspend an address until an operator
+    /// reviews it, 0 disables automatic suspension
+    #[clap(long, default_value = "3", env = "LNADDRD_REPORT_SUSPEND_THRESHOLD")]
+    pub report_suspend_threshold: u32,
+
+    /// URL abuse reports are posted to as JSON to notify operators
+    #[clap(long, env = "LNADDRD_REPORT_WEBHOOK_URL")]
+    pub report_webhook_url: Option<String>,
+
+    /// Endpoints receiving webhooks of the lifecycle and payment events of all addresses
+    #[clap(
+        long,
+        num_args = 1..,
+        env = "LNADDRD_WEBHOOK_URLS",
+        value_delimiter = ',',
+    )]
+    pub webhook_urls: Vec<String>,
+
+    /// Secret webhooks to `--webhook-urls` are signed with (HMAC-SHA256), required if any are set.
+    /// Owners' endpoints are signed with a secret generated for each address.
+    #[clap(long, env = "LNADDRD_WEBHOOK_SECRET", hide_env_values = true)]
+    pub webhook_secret: Option<String>,
+
+    /// Interval in seconds at which due webhooks are delivered
+    #[clap(long, default_value = "5", env = "LNADDRD_WEBHOOK_INTERVAL")]
+    pub webhook_interval: u64,
+
+    /// Interval in seconds at which unpaid invoices of proxied callbacks and the node backend are
+    /// checked for payment, to send webhooks of paid invoices
+    #[clap(long, default_value = "30", env = "LNADDRD_INVOICE_CHECK_INTERVAL")]
+    pub invoice_check_interval: u64,
+
+    /// Leading zero bits of the SHA-256 proof of work required to register, 0 disables the
+    /// challenge. Each bit doubles the expected work, 18 takes a few seconds in a browser.
+    #[clap(
+        long,
+        default_value = "0",
+        value_parser = clap::value_parser!(u8).range(0..=32),
+        env = "LNADDRD_REGISTER_POW_DIFFICULTY"
+    )]
+    pub register_pow_difficulty: u8,
+
+    /// Registrations allowed per client IP as `count/period`, e.g. `10/1h`. Unlimited if unset.
+    /// Fetching a proof-of-work challenge counts as a registration too.
+    #[clap(long, env = "LNADDRD_REGISTER_RATE_LIMIT")]
+    pub register_rate_limit: Option<RateLimit>,
+
+    /// Manifest, callback and details lookups allowed per client IP, e.g. `120/1m`
+    #[clap(long, env = "LNADDRD_LOOKUP_RATE_LIMIT")]
+    pub lookup_rate_limit: Option<RateLimit>,
+
+    /// Lookups allowed per address across all clients, limiting the load put on its upstreams
+    #[clap(long, env = "LNADDRD_LOOKUP_ADDRESS_RATE_LIMIT")]
+    pub lookup_address_rate_limit: Option<RateLimit>,
+
+    /// Management calls allowed per client IP, e.g. `30/1m`
+    #[clap(long, env = "LNADDRD_MANAGE_RATE_LIMIT")]
+    pub manage_rate_limit: Option<RateLimit>,
+}
+
+impl Config {
+    pub fn address_lifetime(&self) -> Option<Duration> {
+        self.address_lifetime_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+    }
+
+    pub fn owner_session_lifetime(&self) -> Duration {
+        Duration::from_secs(self.owner_session_lifetime_hours * 60 * 60)
+    }
 }
@@ -1,93 +1,350 @@
-use anyhow::Result;
+use admin::{admin_router, admin_tls_acceptor, serve_admin};
+use anyhow::{Result, ensure};
 use api::{
-    get_lnaddr_handler, get_lnaddr_manifest_handler, list_domains_handler, register_lnaddr_handler,
-    remove_lnaddr_handler,
+    get_lnaddr_handler, get_lnaddr_manifest_handler, get_offer_handler, list_domains_handler,
+    list_lnaddr_comments_handler, list_lnaddr_events_handler, list_lnaddr_payer_data_handler,
+    list_lnaddr_webhook_deliveries_handler, lnurl_auth_handler, lnurlp_callback_handler,
+    lnurlp_verify_handler, nostr_json_handler, register_challenge_handler, register_lnaddr_handler,
+    remove_lnaddr_handler, renew_lnaddr_handler, report_lnaddr_handler, rotate_token_handler,
+    update_lnaddr_handler, update_lnaddr_settings_handler, update_lnaddr_status_handler,
+    update_lnaddr_webhook_handler,
 };
 use axum::{
     Router,
+    middleware::from_fn_with_state,
     response::IntoResponse,
-    routing::{get, post, delete},
+    routing::{delete, get, post},
 };
 use config::Config;
+use node::ClnRestBackend;
+use rate_limit::{RateLimits, limit_lookups, limit_management, limit_registrations};
 use repository::pg::PgPaymentAddressRepository;
 use service::LnaddrService;
 use service::direct::DirectLnaddrService;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
-use tracing::{debug, info};
-use ui::{lnaddress_details, register_form, register_form_submit};
+use tracing::{debug, info, warn};
+use ui::{
+    bulk_form_submit, link_form_submit, lnaddress_details, login_page, logout_submit,
+    owner_dashboard, register_form, register_form_submit, remove_form_submit, report_form,
+    report_form_submit, settings_form_submit, status_form_submit, token_login_page,
+    token_login_submit, update_form_submit,
+};
 
+pub mod admin;
+pub mod admin_ui;
 pub mod api;
+pub mod bip353;
+pub mod bolt11;
+pub mod bolt12;
+pub mod client;
 pub mod config;
+pub mod lnurl_auth;
+pub mod node;
+pub mod nostr;
+pub mod pow;
+pub mod rate_limit;
 pub mod repository;
 pub mod service;
 pub mod ui;
+pub mod webhook;
 
 #[derive(Clone)]
 pub struct AppState {
     pub service: LnaddrService,
     pub config: Arc<Config>,
+    pub rate_limits: Arc<RateLimits>,
 }
 
+/// Interval at which rate limit buckets that refilled completely are dropped
+const RATE_LIMIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
 pub async fn serve(config: &Config) -> Result<()> {
     debug!(db=%config.database, "Opening database connection");
     let lnaddr_repo = PgPaymentAddressRepository::new(&config.database)?.into_dyn();
 
     debug!(domains=?config.domains, "Starting LN address service");
-    let lnaddr_service = DirectLnaddrService::new(lnaddr_repo, config.domains.clone()).into_dyn();
+    let node = match (&config.cln_rest_url, &config.cln_rune) {
+        (Some(url), Some(rune)) => Some(ClnRestBackend::new(url, rune)?.into_dyn()),
+        (None, None) => None,
+        _ => anyhow::bail!("Both the Core Lightning REST URL and rune are required"),
+    };
+    let lnaddr_service = DirectLnaddrService::new(lnaddr_repo, node.clone(), config)?.into_dyn();
+
+    if config.address_lifetime().is_some() {
+        tokio::spawn(sweep_expired_addresses(
+            lnaddr_service.clone(),
+            Duration::from_secs(config.expiry_sweep_interval),
+        ));
+    }
+
+    if config.nostr_secret_key.is_some() {
+        tokio::spawn(publish_zap_receipts(
+            lnaddr_service.clone(),
+            Duration::from_secs(config.zap_check_interval),
+        ));
+    }
+
+    if node.is_some() {
+        tokio::spawn(forward_offer_payments(
+            lnaddr_service.clone(),
+            Duration::from_secs(config.offer_forward_interval),
+        ));
+    }
+
+    tokio::spawn(deliver_webhooks(
+        lnaddr_service.clone(),
+        Duration::from_secs(config.webhook_interval),
+    ));
+    tokio::spawn(check_unsettled_invoices(
+        lnaddr_service.clone(),
+        Duration::from_secs(config.invoice_check_interval),
+    ));
+
+    let rate_limits = Arc::new(RateLimits::new(config));
+    tokio::spawn(prune_rate_limits(
+        rate_limits.clone(),
+        RATE_LIMIT_PRUNE_INTERVAL,
+    ));
 
     let app_state = AppState {
         service: lnaddr_service.clone(),
         config: Arc::new(config.clone()),
+        rate_limits,
     };
 
-    let app = Router::new()
-        .route("/domains", get(list_domains_handler))
-        .route("/lnaddress/:domain/:username", get(get_lnaddr_handler))
+    let register_routes = Router::new()
         .route("/lnaddress/register", post(register_lnaddr_handler))
-        .route("/lnaddress/remove", delete(remove_lnaddr_handler))
+        .route("/ui/register", post(register_form_submit))
+        .route("/lnaddress/challenge", get(register_challenge_handler))
+        .route_layer(from_fn_with_state(app_state.clone(), limit_registrations));
+    let lookup_routes = Router::new()
+        .route("/lnaddress/:domain/:username", get(get_lnaddr_handler))
+        .route("/lnaddress/:domain/:username/offer", get(get_offer_handler))
         .route(
             "/.well-known/lnurlp/:username",
             get(get_lnaddr_manifest_handler),
         )
-        .route("/", get(register_form))
-        .route("/ui/register", post(register_form_submit))
+        .route("/.well-known/nostr.json", get(nostr_json_handler))
+        .route("/lnurlp/:username/callback", get(lnurlp_callback_handler))
+        .route(
+            "/lnurlp/:username/verify/:verify_id",
+            get(lnurlp_verify_handler),
+        )
         .route("/ui/lnaddress/:domain/:username", get(lnaddress_details))
-        .with_state(app_state)
+        .route_layer(from_fn_with_state(app_state.clone(), limit_lookups));
+    let manage_routes = Router::new()
+        .route("/lnaddress/remove", delete(remove_lnaddr_handler))
+        .route("/lnaddress/update", post(update_lnaddr_handler))
+        .route("/lnaddress/rotate-token", post(rotate_token_handler))
+        .route("/lnaddress/renew", post(renew_lnaddr_handler))
+        .route("/lnaddress/report", post(report_lnaddr_handler))
+        .route("/lnaddress/events", post(list_lnaddr_events_handler))
+        .route("/lnaddress/settings", post(update_lnaddr_settings_handler))
+        .route("/lnaddress/status", post(update_lnaddr_status_handler))
+        .route("/lnaddress/webhook", post(update_lnaddr_webhook_handler))
+        .route(
+            "/lnaddress/webhook/deliveries",
+            post(list_lnaddr_webhook_deliveries_handler),
+        )
+        .route("/lnaddress/comments", post(list_lnaddr_comments_handler))
+        .route(
+            "/lnaddress/payer-data",
+            post(list_lnaddr_payer_data_handler),
+        )
+        .route("/lnurl-auth", get(lnurl_auth_handler))
+        .route("/ui/login", get(login_page))
+        .route("/ui/owner", get(owner_dashboard))
+        .route(
+            "/ui/lnaddress/:domain/:username/update",
+            post(update_form_submit),
+        )
+        .route(
+            "/ui/lnaddress/:domain/:username/settings",
+            post(settings_form_submit),
+        )
+        .route(
+            "/ui/lnaddress/:domain/:username/link",
+            post(link_form_submit),
+        )
+        .route(
+            "/ui/lnaddress/:domain/:username/status",
+            post(status_form_submit),
+        )
+        .route(
+            "/ui/lnaddress/:domain/:username/report",
+            get(report_form).post(report_form_submit),
+        )
+        .route(
+            "/ui/lnaddress/:domain/:username/remove",
+            post(remove_form_submit),
+        )
+        .route(
+            "/ui/login/token",
+            get(token_login_page).post(token_login_submit),
+        )
+        .route("/ui/logout", post(logout_submit))
+        .route("/ui/owner/bulk", post(bulk_form_submit))
+        .route_layer(from_fn_with_state(app_state.clone(), limit_management));
+
+    let mut app = Router::new()
+        .route("/domains", get(list_domains_handler))
+        .route("/", get(register_form))
+        .merge(register_routes)
+        .merge(lookup_routes)
+        .merge(manage_routes)
         .fallback(|_req: axum::http::Request<axum::body::Body>| async move {
             axum::http::StatusCode::NOT_FOUND
         })
-        .layer(axum::middleware::map_response(
-            |res: axum::response::Response| async {
-                if res.status().is_client_error() || res.status().is_server_error() {
-                    let status = res.status();
-                    let body = format!(
-                        r#"<!DOCTYPE html>
+        .layer(axum::middleware::map_response(html_error_page));
+    match config.admin_bind {
+        Some(admin_bind) => {
+            let acceptor = admin_tls_acceptor(config)?;
+            let mutual_tls = config.admin_tls_client_ca.is_some();
+            let listener = TcpListener::bind(admin_bind).await?;
+            info!(bind=%admin_bind, tls=acceptor.is_some(), mutual_tls, "Starting admin server");
+            tokio::spawn(serve_admin(
+                listener,
+                acceptor,
+                mutual_tls,
+                admin_router(app_state.clone()).with_state(app_state.clone()),
+            ));
+        }
+        // Nested after the HTML error pages are added so that the admin API's errors stay JSON
+        None if config.admin_api_key.is_some() => {
+            ensure!(
+                config.admin_public,
+                "The admin API needs its own listener, set --admin-bind or explicitly serve it on \
+                 the public server with --admin-public"
+            );
+            app = app.nest(
+                "/admin",
+                admin_router(app_state.clone())
+                    .route_layer(from_fn_with_state(app_state.clone(), limit_management)),
+            );
+        }
+        None => {}
+    }
+    let app = app.with_state(app_state);
+
+    info!(bind=%config.bind, "Starting HTTP server");
+    let listener = TcpListener::bind(&config.bind).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Replaces error responses of the public server with a minimal HTML page
+async fn html_error_page(res: axum::response::Response) -> axum::response::Response {
+    if !res.status().is_client_error() && !res.status().is_server_error() {
+        return res;
+    }
+
+    let status = res.status();
+    let body = format!(
+        r#"<!DOCTYPE html>
                     <html>
                         <head><title>{} {}</title></head>
                         <body>
                             <h1>{} {}</h1>
                         </body>
                     </html>"#,
-                        status.as_u16(),
-                        status.canonical_reason().unwrap_or("Unknown"),
-                        status.as_u16(),
-                        status.canonical_reason().unwrap_or("Unknown")
-                    );
-                    return (
-                        status,
-                        [(axum::http::header::CONTENT_TYPE, "text/html")],
-                        body,
-                    )
-                        .into_response();
-                }
-                res
-            },
-        ));
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("Unknown"),
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("Unknown")
+    );
+    let mut html = (
+        status,
+        [(axum::http::header::CONTENT_TYPE, "text/html")],
+        body,
+    )
+        .into_response();
+    // Rate limited clients still need to know when to retry
+    if let Some(retry_after) = res.headers().get(axum::http::header::RETRY_AFTER) {
+        html.headers_mut()
+            .insert(axum::http::header::RETRY_AFTER, retry_after.clone());
+    }
+    html
+}
 
-    info!(bind=%config.bind, "Starting HTTP server");
-    let listener = TcpListener::bind(&config.bind).await?;
-    axum::serve(listener, app).await?;
+/// Periodically forgets clients and addresses that are within their rate limits again
+async fn prune_rate_limits(rate_limits: Arc<RateLimits>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let pruned = rate_limits.prune();
+        if pruned > 0 {
+            debug!(pruned, "Pruned rate limit buckets");
+        }
+    }
+}
 
-    Ok(())
+/// Periodically checks zapped invoices for payment and publishes their zap receipts
+async fn publish_zap_receipts(service: LnaddrService, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        match service.publish_zap_receipts().await {
+            Ok(0) => {}
+            Ok(published) => info!(published, "Published zap receipts"),
+            Err(e) => warn!(error=%e, "Failed to publish zap receipts"),
+        }
+    }
+}
+
+/// Periodically pays the BOLT12 offers owed for paid invoices of the node backend
+async fn forward_offer_payments(service: LnaddrService, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        match service.forward_offer_payments().await {
+            Ok(0) => {}
+            Ok(forwarded) => info!(forwarded, "Forwarded payments to BOLT12 offers"),
+            Err(e) => warn!(error=%e, "Failed to forward payments to BOLT12 offers"),
+        }
+    }
+}
+
+/// Periodically delivers due webhooks from the outbox
+async fn deliver_webhooks(service: LnaddrService, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        match service.deliver_webhooks().await {
+            Ok(0) => {}
+            Ok(delivered) => debug!(delivered, "Delivered webhooks"),
+            Err(e) => warn!(error=%e, "Failed to deliver webhooks"),
+        }
+    }
+}
+
+/// Periodically checks recent unpaid invoices for payment to send webhooks of paid invoices
+async fn check_unsettled_invoices(service: LnaddrService, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        match service.check_unsettled_invoices().await {
+            Ok(0) => {}
+            Ok(paid) => info!(paid, "Found paid invoices"),
+            Err(e) => warn!(error=%e, "Failed to check unpaid invoices"),
+        }
+    }
+}
+
+/// Periodically removes expired addresses so their usernames become available again
+async fn sweep_expired_addresses(service: LnaddrService, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        match service.remove_expired_lnaddrs().await {
+            Ok(0) => {}
+            Ok(removed) => info!(removed, "Removed expired addresses"),
+            Err(e) => warn!(error=%e, "Failed to remove expired addresses"),
+        }
+    }
 }
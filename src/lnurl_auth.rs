@@ -0,0 +1,106 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result, ensure};
+use bitcoin::hex::FromHex;
+use secp256k1::{Message, PublicKey, Secp256k1, ecdsa::Signature};
+
+/// Generates a random `k1` challenge, hex encoded
+pub fn generate_k1() -> String {
+    rand::random::<[u8; 32]>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Bech32 encoded LUD-04 login URL for the challenge, uppercase for compact QR codes
+pub fn login_lnurl(domain: &str, k1: &str) -> Result<String> {
+    let mut url = url::Url::parse(&format!("https://{domain}/lnurl-auth"))?;
+    url.query_pairs_mut()
+        .append_pair("tag", "login")
+        .append_pair("k1", k1)
+        .append_pair("action", "login");
+    Ok(lnurl::lnurl::LnUrl::from_url(url.to_string())
+        .encode()
+        .to_uppercase())
+}
+
+/// Verifies the DER encoded signature of a wallet over `k1` with its linking key, returning the
+/// normalized hex encoded linking key
+pub fn verify(k1: &str, sig: &str, key: &str) -> Result<String> {
+    let k1 = <[u8; 32]>::from_hex(k1).context("Invalid k1")?;
+    let key = PublicKey::from_str(key).context("Invalid linking key")?;
+    let mut sig = Signature::from_str(sig).context("Invalid signature")?;
+    // Wallets aren't required to produce low-S signatures, which the verifier insists on
+    sig.normalize_s();
+
+    let verified = Secp256k1::verification_only()
+        .verify_ecdsa(&Message::from_digest(k1), &sig, &key)
+        .is_ok();
+    ensure!(verified, "Invalid signature");
+
+    Ok(key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signature over `K1` with the secret key `0x11` repeated, as a wallet would send it
+    const K1: &str = "4242424242424242424242424242424242424242424242424242424242424242";
+    const SIG: &str = "304402200f394e2439520511a6bc9c9abdfc2238e845bd8b6d57a555a319fbac502d51de022025ba594ec4bf19aad4391a95ecffd6f08f7cffbc721637bae3c37f9b16862b2f";
+    /// The same signature with high S
+    const HIGH_S_SIG: &str = "304502200f394e2439520511a6bc9c9abdfc2238e845bd8b6d57a555a319fbac502d51de022100da45a6b13b40e6552bc6e56a1300290e2b31dd2a3d326880dc0edef1b9b01612";
+    const KEY: &str = "034f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa";
+    const UNCOMPRESSED_KEY: &str = "044f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa385b6b1b8ead809ca67454d9683fcf2ba03456d6fe2c4abe2b07f0fbdbb2f1c1";
+
+    #[test]
+    fn verifies_signature() {
+        assert_eq!(verify(K1, SIG, KEY).unwrap(), KEY);
+    }
+
+    #[test]
+    fn normalizes_linking_key_and_signature() {
+        assert_eq!(verify(K1, SIG, UNCOMPRESSED_KEY).unwrap(), KEY);
+        assert_eq!(verify(K1, &SIG.to_uppercase(), KEY).unwrap(), KEY);
+        assert_eq!(verify(K1, HIGH_S_SIG, KEY).unwrap(), KEY);
+    }
+
+    #[test]
+    fn rejects_signature_over_other_challenge() {
+        let other_k1 = "4242424242424242424242424242424242424242424242424242424242424243";
+        assert!(verify(other_k1, SIG, KEY).is_err());
+    }
+
+    #[test]
+    fn rejects_signature_of_other_key() {
+        let generator = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        assert!(verify(K1, SIG, generator).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        // Truncated or garbled DER encodings
+        assert!(verify(K1, &SIG[8..], KEY).is_err());
+        assert!(verify(K1, &SIG[..SIG.len() - 2], KEY).is_err());
+        assert!(verify(K1, "not a signature", KEY).is_err());
+        assert!(verify(K1, SIG, &KEY[2..]).is_err());
+        assert!(verify(K1, SIG, "05ffff").is_err());
+        assert!(verify(&K1[2..], SIG, KEY).is_err());
+        assert!(verify(&format!("{K1}42"), SIG, KEY).is_err());
+        assert!(verify(&K1.replace('4', "g"), SIG, KEY).is_err());
+    }
+
+    #[test]
+    fn login_lnurl_encodes_challenge() {
+        let k1 = generate_k1();
+        assert_eq!(k1.len(), 64);
+        assert!(k1.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let lnurl = login_lnurl("example.com", K1).unwrap();
+        let decoded = lnurl::lnurl::LnUrl::decode(lnurl.to_lowercase()).unwrap();
+        assert_eq!(
+            decoded.url,
+            format!("https://example.com/lnurl-auth?tag=login&k1={K1}&action=login")
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+
+use crate::bolt12::Offer;
+
+pub type NodeBackend = Arc<dyn INodeBackend + Send + Sync>;
+
+/// Lightning node used to bridge LNURL-pay payments to BOLT12 offers: payers pay an invoice of
+/// the node, which then pays the offer
+#[async_trait]
+pub trait INodeBackend {
+    /// Creates an invoice committing to the SHA256 hash of `description`
+    async fn create_invoice(
+        &self,
+        amount_msat: u64,
+        description: &str,
+        expiry: Duration,
+    ) -> Result<NodeInvoice>;
+
+    /// Returns the preimage of the invoice if it was paid
+    async fn invoice_preimage(&self, payment_hash: &str) -> Result<Option<String>>;
+
+    /// Fetches an invoice from the offer and pays it. Errors mean the outcome of the payment is
+    /// unknown, payments known to have failed are reported as [`OfferPayment::Failed`].
+    async fn pay_offer(
+        &self,
+        offer: &Offer,
+        amount_msat: u64,
+        payer_note: Option<&str>,
+    ) -> Result<OfferPayment>;
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeInvoice {
+    pub bolt11: String,
+    pub payment_hash: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum OfferPayment {
+    Paid,
+    /// Nothing was paid, the payment can be retried
+    Failed(String),
+}
+
+/// Timeout for node requests, paying waits for the payment to complete
+const NODE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Error codes of Core Lightning's `pay` that guarantee nothing was paid
+const CLN_PAY_FAILED_CODES: [i64; 5] = [203, 205, 206, 207, 210];
+
+/// Core Lightning node accessed through its REST plugin, authenticated by a rune
+pub struct ClnRestBackend {
+    url: url::Url,
+    rune: String,
+    http_client: reqwest::Client,
+}
+
+impl ClnRestBackend {
+    pub fn new(url: &str, rune: &str) -> Result<Self> {
+        let url = url::Url::parse(url).context("Invalid Core Lightning REST URL")?;
+        let http_client = reqwest::Client::builder().timeout(NODE_TIMEOUT).build()?;
+
+        Ok(Self {
+            url,
+            rune: rune.to_owned(),
+            http_client,
+        })
+    }
+
+    pub fn into_dyn(self) -> NodeBackend {
+        Arc::new(self)
+    }
+
+    /// Calls an RPC method, returning Core Lightning's error object as error value
+    async fn call(&self, method: &str, params: Value) -> Result<Result<Value, Value>> {
+        let url = self.url.join(&format!("v1/{method}"))?;
+        let response = self
+            .http_client
+            .post(url)
+            .header("Rune", &self.rune)
+            .json(&params)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.json::<Value>().await?;
+        if status.is_success() {
+            Ok(Ok(body))
+        } else if body.get("code").is_some() {
+            Ok(Err(body))
+        } else {
+            bail!("Core Lightning returned {status}");
+        }
+    }
+}
+
+/// Message of a Core Lightning error object
+fn error_message(error: &Value) -> String {
+    error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or("unknown error")
+        .to_owned()
+}
+
+#[async_trait]
+impl INodeBackend for ClnRestBackend {
+    async fn create_invoice(
+        &self,
+        amount_msat: u64,
+        description: &str,
+        expiry: Duration,
+    ) -> Result<NodeInvoice> {
+        let label = format!(
+            "lnaddrd-{}",
+            rand::distributions::DistString::sample_string(
+                &rand::distributions::Alphanumeric,
+                &mut rand::thread_rng(),
+                24
+            )
+        );
+        let invoice = self
+            .call(
+                "invoice",
+                json!({
+                    "amount_msat": amount_msat,
+                    "label": label,
+                    "description": description,
+                    "expiry": expiry.as_secs(),
+                    "deschashonly": true,
+                }),
+            )
+            .await?
+            .map_err(|e| anyhow!("Failed to create invoice: {}", error_message(&e)))?;
+
+        Ok(NodeInvoice {
+            bolt11: invoice
+                .get("bolt11")
+                .and_then(Value::as_str)
+                .context("Invalid invoice response")?
+                .to_owned(),
+            payment_hash: invoice
+                .get("payment_hash")
+                .and_then(Value::as_str)
+                .context("Invalid invoice response")?
+                .to_owned(),
+        })
+    }
+
+    async fn invoice_preimage(&self, payment_hash: &str) -> Result<Option<String>> {
+        let response = self
+            .call("listinvoices", json!({ "payment_hash": payment_hash }))
+            .await?
+            .map_err(|e| anyhow!("Failed to look up invoice: {}", error_message(&e)))?;
+        let invoice = response
+            .get("invoices")
+            .and_then(Value::as_array)
+            .and_then(|invoices| invoices.first())
+            .context("Unknown invoice")?;
+
+        if invoice.get("status").and_then(Value::as_str) != Some("paid") {
+            return Ok(None);
+        }
+        Ok(invoice
+            .get("payment_preimage")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned))
+    }
+
+    async fn pay_offer(
+        &self,
+        offer: &Offer,
+        amount_msat: u64,
+        payer_note: Option<&str>,
+    ) -> Result<OfferPayment> {
+        let mut params = json!({ "offer": offer.encoded });
+        // The amount must only be given for offers without a fixed amount
+        if offer.amount_msat.is_none() {
+            params["amount_msat"] = json!(amount_msat);
+        }
+        if let Some(payer_note) = payer_note {
+            params["payer_note"] = Value::String(payer_note.to_owned());
+        }
+        let invoice = match self.call("fetchinvoice", params).await? {
+            Ok(response) => response
+                .get("invoice")
+                .and_then(Value::as_str)
+                .context("Invalid fetchinvoice response")?
+                .to_owned(),
+            Err(e) => return Ok(OfferPayment::Failed(error_message(&e))),
+        };
+
+        match self.call("pay", json!({ "bolt11": invoice })).await? {
+            Ok(_) => Ok(OfferPayment::Paid),
+            Err(e)
+                if e.get("code")
+                    .and_then(Value::as_i64)
+                    .is_some_and(|code| CLN_PAY_FAILED_CODES.contains(&code)) =>
+            {
+                Ok(OfferPayment::Failed(error_message(&e)))
+            }
+            Err(e) => bail!(
+                "Payment of offer invoice ended in unknown state: {}",
+                error_message(&e)
+            ),
+        }
+    }
+}
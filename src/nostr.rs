@@ -0,0 +1,462 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use base64::Engine;
+use futures::{SinkExt, StreamExt};
+use secp256k1::{Keypair, Message, Secp256k1, XOnlyPublicKey, schnorr::Signature};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tokio_tungstenite::tungstenite;
+
+/// NIP-57 zap request
+pub const ZAP_REQUEST_KIND: u64 = 9734;
+/// NIP-57 zap receipt
+pub const ZAP_RECEIPT_KIND: u64 = 9735;
+/// NIP-98 HTTP auth
+pub const HTTP_AUTH_KIND: u64 = 27235;
+
+/// Accepted difference between the timestamp of a NIP-98 event and the server's clock
+const HTTP_AUTH_MAX_SKEW: Duration = Duration::from_secs(60);
+
+/// Maximum number of relays a zap receipt is published to
+const MAX_ZAP_RELAYS: usize = 10;
+
+/// Parses a public key given as hex or NIP-19 `npub`
+pub fn parse_pubkey(pubkey: &str) -> Result<XOnlyPublicKey> {
+    let pubkey = pubkey.trim();
+    if pubkey.starts_with("npub1") {
+        let (hrp, data) = bech32::decode(pubkey).context("Invalid npub")?;
+        ensure!(hrp.as_str() == "npub", "Invalid npub");
+        return XOnlyPublicKey::from_slice(&data).context("Invalid npub");
+    }
+
+    XOnlyPublicKey::from_str(pubkey).context("Invalid public key")
+}
+
+/// Whether the URL is a websocket URL as used for relays
+pub fn is_relay_url(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "ws" | "wss") && url.has_host())
+}
+
+/// Signed Nostr event as defined by NIP-01
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl Event {
+    pub fn sign(
+        keys: &Keypair,
+        kind: u64,
+        tags: Vec<Vec<String>>,
+        content: String,
+        created_at: SystemTime,
+    ) -> Result<Event> {
+        let pubkey = keys.x_only_public_key().0.to_string();
+        let created_at = created_at.duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content)?;
+
+        let aux_rand = rand::random::<[u8; 32]>();
+        let sig = Secp256k1::signing_only().sign_schnorr_with_aux_rand(
+            &Message::from_digest(id),
+            keys,
+            &aux_rand,
+        );
+
+        Ok(Event {
+            id: hex(&id),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: sig.to_string(),
+        })
+    }
+
+    /// Checks that the id matches the content of the event and is signed by its pubkey
+    pub fn verify(&self) -> Result<()> {
+        let id = event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        )?;
+        ensure!(hex(&id) == self.id, "Event id does not match its content");
+
+        let pubkey = XOnlyPublicKey::from_str(&self.pubkey).context("Invalid event pubkey")?;
+        let sig = Signature::from_str(&self.sig).context("Invalid event signature")?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&sig, &Message::from_digest(id), &pubkey)
+            .context("Invalid event signature")?;
+
+        Ok(())
+    }
+
+    /// Values of all tags with the given name
+    pub fn tags(&self, name: &str) -> impl Iterator<Item = &[String]> {
+        self.tags
+            .iter()
+            .filter(move |tag| tag.first().is_some_and(|tag_name| tag_name == name))
+            .map(|tag| &tag[1..])
+    }
+
+    /// First value of the first tag with the given name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags(name)
+            .next()
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+}
+
+/// NIP-57 zap request sent to the LNURL-pay callback
+#[derive(Debug, Clone)]
+pub struct ZapRequest {
+    pub event: Event,
+    /// The event as sent by the payer, which is committed to by the invoice description
+    pub json: String,
+}
+
+impl ZapRequest {
+    /// Parses and validates a zap request as described in NIP-57 appendix D
+    pub fn parse(json: &str, amount_msat: u64) -> Result<ZapRequest> {
+        let event = serde_json::from_str::<Event>(json).context("Invalid zap request")?;
+        event.verify()?;
+
+        ensure!(event.kind == ZAP_REQUEST_KIND, "Zap request has wrong kind");
+        ensure!(
+            event.tags("p").count() == 1,
+            "Zap request must have exactly one p tag"
+        );
+        XOnlyPublicKey::from_str(event.tag("p").unwrap_or_default())
+            .context("Zap request p tag is not a valid pubkey")?;
+        ensure!(
+            event.tags("e").count() <= 1,
+            "Zap request must not have multiple e tags"
+        );
+        ensure!(
+            event.tags("a").count() <= 1,
+            "Zap request must not have multiple a tags"
+        );
+        ensure!(
+            event
+                .tags("relays")
+                .next()
+                .is_some_and(|relays| !relays.is_empty()),
+            "Zap request has no relays"
+        );
+        if let Some(amount) = event.tag("amount") {
+            ensure!(
+                amount.parse::<u64>().ok() == Some(amount_msat),
+                "Zap request amount does not match the requested amount"
+            );
+        }
+        if let Some(sender) = event.tag("P") {
+            ensure!(
+                sender == event.pubkey,
+                "Zap request P tag does not match its pubkey"
+            );
+        }
+
+        Ok(ZapRequest {
+            event,
+            json: json.to_owned(),
+        })
+    }
+
+    /// Relays the zap receipt should be published to
+    pub fn relays(&self) -> Vec<String> {
+        self.event
+            .tags("relays")
+            .flatten()
+            .filter(|relay| is_relay_url(relay))
+            .take(MAX_ZAP_RELAYS)
+            .cloned()
+            .collect()
+    }
+
+    /// Builds the zap receipt for the paid invoice
+    pub fn receipt(
+        &self,
+        keys: &Keypair,
+        bolt11: &str,
+        preimage: Option<&str>,
+        paid_at: SystemTime,
+    ) -> Result<Event> {
+        let mut tags = Vec::new();
+        for name in ["p", "e", "a"] {
+            if let Some(value) = self.event.tag(name) {
+                tags.push(vec![name.to_owned(), value.to_owned()]);
+            }
+        }
+        tags.push(vec!["P".to_owned(), self.event.pubkey.clone()]);
+        tags.push(vec!["bolt11".to_owned(), bolt11.to_owned()]);
+        tags.push(vec!["description".to_owned(), self.json.clone()]);
+        if let Some(preimage) = preimage {
+            tags.push(vec!["preimage".to_owned(), preimage.to_owned()]);
+        }
+
+        Event::sign(keys, ZAP_RECEIPT_KIND, tags, String::new(), paid_at)
+    }
+}
+
+/// Verifies a NIP-98 `Authorization: Nostr` token of a request, returning the pubkey that signed
+/// it. Schemes aren't compared as TLS is commonly terminated by a reverse proxy. Requests with a
+/// body must commit to it with a `payload` tag, so that the event can't be reused for a different
+/// request.
+pub fn verify_http_auth(
+    token: &str,
+    url: &url::Url,
+    method: &str,
+    body: &[u8],
+    now: SystemTime,
+) -> Result<String> {
+    let json = base64::engine::general_purpose::STANDARD
+        .decode(token.trim())
+        .context("Authorization event is not valid base64")?;
+    let event = serde_json::from_slice::<Event>(&json).context("Invalid authorization event")?;
+    event.verify()?;
+
+    ensure!(
+        event.kind == HTTP_AUTH_KIND,
+        "Authorization event has wrong kind"
+    );
+    let created_at = SystemTime::UNIX_EPOCH + Duration::from_secs(event.created_at);
+    let skew = now
+        .duration_since(created_at)
+        .unwrap_or_else(|e| e.duration());
+    ensure!(
+        skew <= HTTP_AUTH_MAX_SKEW,
+        "Authorization event is too old or in the future"
+    );
+
+    let signed_url = url::Url::parse(event.tag("u").unwrap_or_default())
+        .context("Authorization event has no valid u tag")?;
+    ensure!(
+        matches!(signed_url.scheme(), "http" | "https")
+            && signed_url[url::Position::BeforeHost..] == url[url::Position::BeforeHost..],
+        "Authorization event is for a different URL"
+    );
+    ensure!(
+        event
+            .tag("method")
+            .is_some_and(|signed| signed.eq_ignore_ascii_case(method)),
+        "Authorization event is for a different method"
+    );
+    if !body.is_empty() || event.tag("payload").is_some() {
+        ensure!(
+            event.tag("payload") == Some(hex(&Sha256::digest(body)).as_str()),
+            "Authorization event does not match the request body"
+        );
+    }
+
+    Ok(XOnlyPublicKey::from_str(&event.pubkey)?.to_string())
+}
+
+/// Publishes an event to a relay and waits for the relay to accept it
+pub async fn publish(relay: &str, event: &Event, timeout: Duration) -> Result<()> {
+    tokio::time::timeout(timeout, async {
+        let (mut socket, _) = tokio_tungstenite::connect_async(relay).await?;
+        socket
+            .send(tungstenite::Message::text(
+                json!(["EVENT", event]).to_string(),
+            ))
+            .await?;
+
+        while let Some(message) = socket.next().await {
+            let tungstenite::Message::Text(text) = message? else {
+                continue;
+            };
+            let Ok(Value::Array(response)) = serde_json::from_str::<Value>(&text) else {
+                continue;
+            };
+            if response.first().and_then(Value::as_str) != Some("OK")
+                || response.get(1).and_then(Value::as_str) != Some(event.id.as_str())
+            {
+                continue;
+            }
+
+            let _ = socket.close(None).await;
+            if response.get(2).and_then(Value::as_bool) == Some(true) {
+                return Ok(());
+            }
+            let reason = response.get(3).and_then(Value::as_str).unwrap_or_default();
+            bail!("Relay rejected event: {reason}");
+        }
+
+        bail!("Relay closed the connection")
+    })
+    .await
+    .context("Relay did not respond in time")?
+}
+
+/// Event id as defined by NIP-01
+fn event_id(
+    pubkey: &str,
+    created_at: u64,
+    kind: u64,
+    tags: &[Vec<String>],
+    content: &str,
+) -> Result<[u8; 32]> {
+    let serialized = serde_json::to_string(&json!([0, pubkey, created_at, kind, tags, content]))?;
+    Ok(Sha256::digest(serialized.as_bytes()).into())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/lnaddress/remove";
+    const BODY: &[u8] = br#"{"domain":"example.com","username":"alice"}"#;
+
+    fn keys() -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[0x11; 32]).unwrap()
+    }
+
+    fn now() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    fn token(
+        kind: u64,
+        created_at: SystemTime,
+        url: &str,
+        method: &str,
+        body: Option<&[u8]>,
+    ) -> String {
+        let mut tags = vec![
+            vec!["u".to_owned(), url.to_owned()],
+            vec!["method".to_owned(), method.to_owned()],
+        ];
+        if let Some(body) = body {
+            tags.push(vec!["payload".to_owned(), hex(&Sha256::digest(body))]);
+        }
+        let event = Event::sign(&keys(), kind, tags, String::new(), created_at).unwrap();
+        base64::engine::general_purpose::STANDARD.encode(serde_json::to_string(&event).unwrap())
+    }
+
+    fn verify(token: &str, url: &str, method: &str, body: &[u8]) -> Result<String> {
+        verify_http_auth(token, &url::Url::parse(url).unwrap(), method, body, now())
+    }
+
+    #[test]
+    fn returns_signing_pubkey() {
+        let token = token(HTTP_AUTH_KIND, now(), URL, "POST", Some(BODY));
+        assert_eq!(
+            verify(&token, URL, "POST", BODY).unwrap(),
+            keys().x_only_public_key().0.to_string()
+        );
+    }
+
+    #[test]
+    fn accepts_up_to_60_seconds_of_clock_skew() {
+        let skew = Duration::from_secs(60);
+        let late = Duration::from_secs(61);
+        for (created_at, valid) in [
+            (now() - skew, true),
+            (now() + skew, true),
+            (now() - late, false),
+            (now() + late, false),
+        ] {
+            let token = token(HTTP_AUTH_KIND, created_at, URL, "POST", Some(BODY));
+            assert_eq!(verify(&token, URL, "POST", BODY).is_ok(), valid);
+        }
+    }
+
+    #[test]
+    fn url_must_match_except_for_scheme() {
+        let token = token(HTTP_AUTH_KIND, now(), URL, "POST", Some(BODY));
+
+        assert!(verify(&token, "http://example.com/lnaddress/remove", "POST", BODY).is_ok());
+        assert!(verify(&token, "https://example.com/lnaddress/update", "POST", BODY).is_err());
+        assert!(verify(&token, "https://example.org/lnaddress/remove", "POST", BODY).is_err());
+        assert!(
+            verify(
+                &token,
+                "https://example.com/lnaddress/remove?a=b",
+                "POST",
+                BODY
+            )
+            .is_err()
+        );
+        assert!(
+            verify(
+                &token,
+                "https://example.com:8443/lnaddress/remove",
+                "POST",
+                BODY
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn method_must_match() {
+        let token = token(HTTP_AUTH_KIND, now(), URL, "post", Some(BODY));
+
+        assert!(verify(&token, URL, "POST", BODY).is_ok());
+        assert!(verify(&token, URL, "PUT", BODY).is_err());
+    }
+
+    #[test]
+    fn payload_must_match_body() {
+        let signed = token(HTTP_AUTH_KIND, now(), URL, "POST", Some(BODY));
+        assert!(
+            verify(
+                &signed,
+                URL,
+                "POST",
+                br#"{"domain":"example.com","username":"bob"}"#
+            )
+            .is_err()
+        );
+        assert!(verify(&signed, URL, "POST", b"").is_err());
+
+        // Requests with a body must commit to it
+        let unsigned = token(HTTP_AUTH_KIND, now(), URL, "POST", None);
+        assert!(verify(&unsigned, URL, "POST", BODY).is_err());
+        let without_body = token(HTTP_AUTH_KIND, now(), "https://example.com/x", "GET", None);
+        assert!(verify(&without_body, "https://example.com/x", "GET", b"").is_ok());
+    }
+
+    #[test]
+    fn rejects_other_kinds_and_tampered_events() {
+        let zap = token(ZAP_REQUEST_KIND, now(), URL, "POST", Some(BODY));
+        assert!(verify(&zap, URL, "POST", BODY).is_err());
+
+        let json = base64::engine::general_purpose::STANDARD
+            .decode(token(HTTP_AUTH_KIND, now(), URL, "POST", Some(BODY)))
+            .unwrap();
+        let mut event = serde_json::from_slice::<Event>(&json).unwrap();
+        event.tags[0][1] = "https://example.com/lnaddress/update".to_owned();
+        let tampered = base64::engine::general_purpose::STANDARD
+            .encode(serde_json::to_string(&event).unwrap());
+        assert!(
+            verify(
+                &tampered,
+                "https://example.com/lnaddress/update",
+                "POST",
+                BODY
+            )
+            .is_err()
+        );
+
+        assert!(verify("not base64!", URL, "POST", BODY).is_err());
+    }
+}
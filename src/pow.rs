@@ -0,0 +1,138 @@
+use anyhow::{Result, ensure};
+use bitcoin::hashes::{Hash, sha256};
+
+/// Generates a random challenge nonce, hex encoded
+pub fn generate_nonce() -> String {
+    rand::random::<[u8; 16]>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Number of leading zero bits of the SHA-256 hash of `{nonce}:{solution}`
+pub fn leading_zero_bits(nonce: &str, solution: &str) -> u32 {
+    let hash = sha256::Hash::hash(format!("{nonce}:{solution}").as_bytes());
+    let mut bits = 0;
+    for byte in hash.to_byte_array() {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Verifies that the solution hashes with the nonce to at least `difficulty` leading zero bits
+pub fn verify(nonce: &str, solution: &str, difficulty: u8) -> Result<()> {
+    ensure!(solution.len() <= 32, "Proof-of-work solution is too long");
+    ensure!(
+        leading_zero_bits(nonce, solution) >= u32::from(difficulty),
+        "Insufficient proof of work"
+    );
+    Ok(())
+}
+
+/// Script fetching and solving a challenge when the register form is submitted, filling in its
+/// `pow_nonce` and `pow_solution` inputs. Hashes in batches so the page stays responsive.
+pub const SOLVER_SCRIPT: &str = r#"
+(function () {
+    const form = document.getElementById('register-form');
+    if (!form || !form.pow_nonce) return;
+    const zeroBits = (hash) => {
+        let bits = 0;
+        for (const byte of new Uint8Array(hash)) {
+            if (byte !== 0) return bits + Math.clz32(byte) - 24;
+            bits += 8;
+        }
+        return bits;
+    };
+    form.addEventListener('submit', async (event) => {
+        if (form.pow_solution.value) return;
+        event.preventDefault();
+        const button = form.querySelector('button[type=submit]');
+        button.disabled = true;
+        const label = button.textContent;
+        button.textContent = 'Computing proof of work…';
+        const response = await fetch('/lnaddress/challenge');
+        if (!response.ok) {
+            button.disabled = false;
+            button.textContent = response.status === 429 ? 'Too many attempts, try again later' : label;
+            return;
+        }
+        const { nonce, difficulty } = await response.json();
+        form.pow_nonce.value = nonce;
+        const encoder = new TextEncoder();
+        for (let start = 0; ; start += 512) {
+            const candidates = Array.from({ length: 512 }, (_, i) => String(start + i));
+            const hashes = await Promise.all(candidates.map((candidate) =>
+                crypto.subtle.digest('SHA-256', encoder.encode(nonce + ':' + candidate))));
+            const found = hashes.findIndex((hash) => zeroBits(hash) >= difficulty);
+            if (found >= 0) {
+                form.pow_solution.value = candidates[found];
+                form.submit();
+                return;
+            }
+        }
+    });
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONCE: &str = "00112233445566778899aabbccddeeff";
+
+    /// Leading zero bits counted over the first 16 bytes of the hash as one integer
+    fn expected_bits(solution: &str) -> u32 {
+        let hash = sha256::Hash::hash(format!("{NONCE}:{solution}").as_bytes());
+        u128::from_be_bytes(hash.to_byte_array()[..16].try_into().unwrap()).leading_zeros()
+    }
+
+    /// First numeric solution with exactly the given number of leading zero bits
+    fn solution_with(bits: u32) -> String {
+        (0u32..)
+            .map(|n| n.to_string())
+            .find(|solution| expected_bits(solution) == bits)
+            .unwrap()
+    }
+
+    #[test]
+    fn counts_zero_bits_across_byte_boundaries() {
+        for bits in [0, 1, 7, 8, 9, 12] {
+            let solution = solution_with(bits);
+            assert_eq!(leading_zero_bits(NONCE, &solution), bits);
+        }
+    }
+
+    #[test]
+    fn verify_requires_the_exact_difficulty() {
+        for bits in [7, 8, 9] {
+            let solution = solution_with(bits);
+            assert!(verify(NONCE, &solution, bits as u8).is_ok());
+            assert!(verify(NONCE, &solution, bits as u8 - 1).is_ok());
+            assert!(verify(NONCE, &solution, bits as u8 + 1).is_err());
+        }
+    }
+
+    #[test]
+    fn difficulty_zero_accepts_any_solution() {
+        let solution = solution_with(0);
+        assert!(verify(NONCE, &solution, 0).is_ok());
+        assert!(verify(NONCE, "", 0).is_ok());
+    }
+
+    #[test]
+    fn rejects_long_solutions() {
+        assert!(verify(NONCE, &"0".repeat(32), 0).is_ok());
+        assert!(verify(NONCE, &"0".repeat(33), 0).is_err());
+    }
+
+    #[test]
+    fn nonces_are_random_hex() {
+        let nonce = generate_nonce();
+        assert_eq!(nonce.len(), 32);
+        assert!(nonce.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(nonce, generate_nonce());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, ensure};
+use axum::{
+    extract::{Host, Path, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::AppState;
+use crate::client::ClientInfo;
+use crate::config::Config;
+
+/// Number of requests allowed per period, parsed from `count/period`, e.g. `10/1h`
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub count: u32,
+    pub period: Duration,
+}
+
+impl FromStr for RateLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, period) = s
+            .split_once('/')
+            .context("Rate limit must be given as count/period, e.g. 10/1h")?;
+        let count = count
+            .trim()
+            .parse::<u32>()
+            .context("Invalid rate limit count")?;
+        let period =
+            humantime::parse_duration(period.trim()).context("Invalid rate limit period")?;
+        ensure!(count > 0, "Rate limit count must be positive");
+        ensure!(!period.is_zero(), "Rate limit period must be positive");
+        Ok(RateLimit { count, period })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token buckets holding `count` tokens per key, refilled evenly over the period
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refill_rate(&self) -> f64 {
+        f64::from(self.limit.count) / self.limit.period.as_secs_f64()
+    }
+
+    /// Takes a token of the key's bucket, or returns how long until one is available
+    pub fn check(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.limit.count);
+        let rate = self.refill_rate();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(capacity);
+        bucket.updated_at = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate));
+        }
+        bucket.tokens -= 1.0;
+
+        Ok(())
+    }
+
+    /// Forgets buckets that refilled completely, as they are equivalent to new ones
+    pub fn prune(&self, now: Instant) -> usize {
+        let capacity = f64::from(self.limit.count);
+        let rate = self.refill_rate();
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated_at);
+            bucket.tokens + elapsed.as_secs_f64() * rate < capacity
+        });
+        before - buckets.len()
+    }
+}
+
+/// Separate budgets for registrations, lookups and management calls. Budgets that aren't
+/// configured are unlimited.
+#[derive(Default)]
+pub struct RateLimits {
+    /// Registrations per client IP
+    pub register: Option<RateLimiter>,
+    /// Manifest and callback lookups per client IP
+    pub lookup: Option<RateLimiter>,
+    /// Manifest and callback lookups per address, regardless of the client
+    pub lookup_address: Option<RateLimiter>,
+    /// Management calls per client IP
+    pub manage: Option<RateLimiter>,
+}
+
+impl RateLimits {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            register: config.register_rate_limit.map(RateLimiter::new),
+            lookup: config.lookup_rate_limit.map(RateLimiter::new),
+            lookup_address: config.lookup_address_rate_limit.map(RateLimiter::new),
+            manage: config.manage_rate_limit.map(RateLimiter::new),
+        }
+    }
+
+    pub fn prune(&self) -> usize {
+        let now = Instant::now();
+        [
+            &self.register,
+            &self.lookup,
+            &self.lookup_address,
+            &self.manage,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|limiter| limiter.prune(now))
+        .sum()
+    }
+}
+
+/// Bucket shared by all clients whose IP is unknown, so they can't evade the limits
+const UNKNOWN_CLIENT: &str = "unknown";
+
+/// Checks the client's bucket of a budget
+fn check_client(limiter: Option<&RateLimiter>, client: &ClientInfo) -> Result<(), Duration> {
+    let Some(limiter) = limiter else {
+        return Ok(());
+    };
+    match client.ip {
+        Some(ip) => limiter.check(&ip.to_string(), Instant::now()),
+        None => limiter.check(UNKNOWN_CLIENT, Instant::now()),
+    }
+}
+
+/// 429 response telling the client when to retry, in whole seconds
+fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, seconds.to_string())],
+    )
+        .into_response()
+}
+
+pub async fn limit_registrations(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(retry_after) = check_client(state.rate_limits.register.as_ref(), &client) {
+        return too_many_requests(retry_after);
+    }
+    next.run(request).await
+}
+
+pub async fn limit_management(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(retry_after) = check_client(state.rate_limits.manage.as_ref(), &client) {
+        return too_many_requests(retry_after);
+    }
+    next.run(request).await
+}
+
+/// Limits lookups per client and per address, the address being taken from the path and
+/// defaulting to the requested host as domain
+pub async fn limit_lookups(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Host(host): Host,
+    params: Option<Path<HashMap<String, String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(retry_after) = check_client(state.rate_limits.lookup.as_ref(), &client) {
+        return too_many_requests(retry_after);
+    }
+    let params = params.map(|Path(params)| params).unwrap_or_default();
+    if let (Some(limiter), Some(username)) =
+        (&state.rate_limits.lookup_address, params.get("username"))
+    {
+        let domain = params.get("domain").unwrap_or(&host);
+        let key = format!("{}@{}", username.to_lowercase(), domain.to_lowercase());
+        if let Err(retry_after) = limiter.check(&key, Instant::now()) {
+            return too_many_requests(retry_after);
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limit: &str) -> RateLimiter {
+        RateLimiter::new(limit.parse().unwrap())
+    }
+
+    #[test]
+    fn buckets_refill_evenly_over_the_period() {
+        let limiter = limiter("2/8s");
+        let start = Instant::now();
+
+        assert_eq!(limiter.check("a", start), Ok(()));
+        assert_eq!(limiter.check("a", start), Ok(()));
+        assert_eq!(limiter.check("a", start), Err(Duration::from_secs(4)));
+        // Other keys have their own buckets
+        assert_eq!(limiter.check("b", start), Ok(()));
+
+        // Half a token is back after a quarter of the period
+        let later = start + Duration::from_secs(2);
+        assert_eq!(limiter.check("a", later), Err(Duration::from_secs(2)));
+        let later = start + Duration::from_secs(4);
+        assert_eq!(limiter.check("a", later), Ok(()));
+        assert!(limiter.check("a", later).is_err());
+    }
+
+    #[test]
+    fn buckets_refill_up_to_their_capacity() {
+        let limiter = limiter("2/8s");
+        let start = Instant::now();
+        assert_eq!(limiter.check("a", start), Ok(()));
+
+        let later = start + Duration::from_secs(3600);
+        assert_eq!(limiter.check("a", later), Ok(()));
+        assert_eq!(limiter.check("a", later), Ok(()));
+        assert!(limiter.check("a", later).is_err());
+    }
+
+    #[test]
+    fn prune_forgets_only_full_buckets() {
+        let limiter = limiter("2/8s");
+        let start = Instant::now();
+        limiter.check("a", start).unwrap();
+        limiter.check("a", start).unwrap();
+        limiter.check("b", start + Duration::from_secs(4)).unwrap();
+        limiter.check("b", start + Duration::from_secs(4)).unwrap();
+
+        // "a" is full again after the period, "b" only half a period later
+        assert_eq!(limiter.prune(start + Duration::from_secs(7)), 0);
+        assert_eq!(limiter.prune(start + Duration::from_secs(8)), 1);
+        assert_eq!(limiter.prune(start + Duration::from_secs(8)), 0);
+        assert_eq!(limiter.prune(start + Duration::from_secs(12)), 1);
+    }
+
+    #[test]
+    fn clients_without_ip_share_a_bucket() {
+        let limiter = limiter("1/1h");
+        let client = ClientInfo::default();
+        assert_eq!(check_client(Some(&limiter), &client), Ok(()));
+        assert!(check_client(Some(&limiter), &client).is_err());
+        assert_eq!(check_client(None, &client), Ok(()));
+    }
+}
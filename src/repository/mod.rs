@@ -1,11 +1,18 @@
 pub mod pg;
 
-use std::{fmt::Display, str::FromStr, sync::Arc, time::SystemTime};
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use anyhow::{Result, ensure};
+use anyhow::{Context, Result, bail, ensure};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
+use crate::bolt12::Offer;
+
 pub type PaymentAddressRepository = Arc<dyn IPaymentAddressRepository + Send + Sync>;
 
 #[async_trait]
@@ -16,78 +23,1351 @@ pub trait IPaymentAddressRepository {
         username: &str,
     ) -> Result<Option<PaymentAddress>>;
 
+    /// Adds the payment address with its initial settings and owner in one transaction
+    #[allow(clippy::too_many_arguments)]
     async fn add_payment_address(
         &self,
         domain: &str,
         username: &str,
-        destination: DestinationPaymentAddress,
+        routing: Routing,
+        settings: AddressSettings,
+        owner_id: Option<i32>,
         authentication_token: &str,
+        expires_at: Option<SystemTime>,
     ) -> Result<()>;
 
+    /// Removes the payment address, returns the removed entry if it existed
     async fn remove_payment_address(
         &self,
         domain: &str,
         username: &str,
+    ) -> Result<Option<PaymentAddress>>;
+
+    /// Replaces the destinations and routing rules of the payment address
+    async fn update_payment_address_routing(
+        &self,
+        domain: &str,
+        username: &str,
+        routing: Routing,
+    ) -> Result<()>;
+
+    async fn update_payment_address_settings(
+        &self,
+        domain: &str,
+        username: &str,
+        settings: AddressSettings,
+    ) -> Result<()>;
+
+    async fn add_payment_comment(
+        &self,
+        payment_address_id: i32,
+        amount_msat: u64,
+        comment: &str,
+    ) -> Result<()>;
+
+    /// Lists the most recent comments of a payment address, newest first
+    async fn list_payment_comments(
+        &self,
+        payment_address_id: i32,
+        limit: i64,
+    ) -> Result<Vec<PaymentComment>>;
+
+    /// Stores the LUD-18 payer data sent along with a payment, `payer_data` is the JSON object
+    /// as sent by the payer
+    async fn add_payer_data(
+        &self,
+        payment_address_id: i32,
+        amount_msat: u64,
+        payer_data: &str,
+    ) -> Result<()>;
+
+    /// Lists the most recent payer data of a payment address, newest first
+    async fn list_payer_data(&self, payment_address_id: i32, limit: i64) -> Result<Vec<PayerData>>;
+
+    async fn add_invoice(&self, invoice: NewInvoice) -> Result<()>;
+
+    /// Looks up an invoice by its verify id, scoped to the payment address that issued it
+    async fn get_invoice(
+        &self,
+        payment_address_id: i32,
+        verify_id: &str,
+    ) -> Result<Option<Invoice>>;
+
+    /// Marks an invoice as paid, returns false if it already was
+    async fn settle_invoice(&self, verify_id: &str, preimage: Option<&str>) -> Result<bool>;
+
+    /// Lists unpaid invoices created after `created_after` whose payment status can be checked,
+    /// only of addresses with a webhook unless `all` is set
+    async fn list_unsettled_invoices(
+        &self,
+        created_after: SystemTime,
+        all: bool,
+    ) -> Result<Vec<Invoice>>;
+
+    /// Lists invoices created after `created_after` carrying a zap request whose receipt wasn't
+    /// published yet
+    async fn list_pending_zap_invoices(&self, created_after: SystemTime) -> Result<Vec<Invoice>>;
+
+    async fn mark_zap_receipt_published(&self, verify_id: &str) -> Result<()>;
+
+    /// Lists invoices created after `created_after` that are owed to a BOLT12 offer and weren't
+    /// forwarded yet
+    async fn list_pending_offer_forwards(&self, created_after: SystemTime) -> Result<Vec<Invoice>>;
+
+    /// Marks an invoice as forwarded to its offer, returns false if it already was. Claiming the
+    /// forward before paying guarantees the offer is paid at most once.
+    async fn claim_offer_forward(&self, verify_id: &str) -> Result<bool>;
+
+    /// Undoes a claim after a payment to the offer failed, so it is retried
+    async fn release_offer_forward(&self, verify_id: &str) -> Result<()>;
+
+    /// Counts a manifest request served by the destination
+    async fn record_destination_selection(&self, destination_id: i32) -> Result<()>;
+
+    /// Lists the unexpired payment addresses of a domain that have BIP-353 payment instructions or
+    /// a BOLT12 offer destination
+    async fn list_bip353_payment_addresses(&self, domain: &str) -> Result<Vec<PaymentAddress>>;
+
+    async fn update_authentication_token(
+        &self,
+        domain: &str,
+        username: &str,
         authentication_token: &str,
     ) -> Result<()>;
+
+    /// Sets a new expiry time for the payment address
+    async fn renew_payment_address(
+        &self,
+        domain: &str,
+        username: &str,
+        expires_at: SystemTime,
+    ) -> Result<()>;
+
+    /// Stores an invoice of the node backend paying for the renewal of a payment address
+    async fn add_renewal_invoice(
+        &self,
+        payment_address_id: i32,
+        payment_hash: &str,
+        amount_msat: u64,
+    ) -> Result<()>;
+
+    /// Marks an unredeemed renewal invoice of the payment address as redeemed and extends its
+    /// expiry time by `lifetime` in one transaction. Returns the new expiry time, `None` if there
+    /// is no such invoice.
+    async fn redeem_renewal_invoice(
+        &self,
+        payment_address_id: i32,
+        payment_hash: &str,
+        lifetime: Duration,
+        now: SystemTime,
+    ) -> Result<Option<SystemTime>>;
+
+    /// Removes all payment addresses that expired before `now`, returns the removed entries
+    async fn remove_expired_payment_addresses(
+        &self,
+        now: SystemTime,
+    ) -> Result<Vec<PaymentAddress>>;
+
+    async fn add_address_event(&self, event: NewAddressEvent) -> Result<()>;
+
+    /// Lists events matching the filter, newest first
+    async fn list_address_events(&self, filter: AddressEventFilter) -> Result<Vec<AddressEvent>>;
+
+    /// Assigns the payment address to an owner, or detaches it from its owner if `None`
+    async fn update_payment_address_owner(
+        &self,
+        domain: &str,
+        username: &str,
+        owner_id: Option<i32>,
+    ) -> Result<()>;
+
+    /// Lists the payment addresses of an owner, ordered by domain and username
+    async fn list_owner_payment_addresses(&self, owner_id: i32) -> Result<Vec<PaymentAddress>>;
+
+    /// Creates a new owner without linking key
+    async fn add_owner(&self) -> Result<Owner>;
+
+    async fn get_owner(&self, owner_id: i32) -> Result<Option<Owner>>;
+
+    /// Returns the owner logging in with the linking key, creating it on first login
+    async fn get_or_add_owner_by_linking_key(&self, linking_key: &str) -> Result<Owner>;
+
+    /// Returns the owner authenticating with the Nostr pubkey, creating it on first use
+    async fn get_or_add_owner_by_nostr_pubkey(&self, pubkey: &str) -> Result<Owner>;
+
+    /// Returns the owner authenticating with the Nostr pubkey if it was used before
+    async fn get_owner_by_nostr_pubkey(&self, pubkey: &str) -> Result<Option<Owner>>;
+
+    /// Stores a new LNURL-auth challenge, removing challenges created before `expired_before`
+    async fn add_auth_challenge(&self, k1: &str, expired_before: SystemTime) -> Result<()>;
+
+    /// Records the linking key that signed a challenge created after `created_after`. Returns
+    /// false if there is no such challenge or it was already signed.
+    async fn sign_auth_challenge(
+        &self,
+        k1: &str,
+        linking_key: &str,
+        created_after: SystemTime,
+    ) -> Result<bool>;
+
+    /// Removes a signed challenge created after `created_after`, returning the linking key that
+    /// signed it. Unsigned challenges are left in place.
+    async fn take_signed_auth_challenge(
+        &self,
+        k1: &str,
+        created_after: SystemTime,
+    ) -> Result<Option<String>>;
+
+    /// Stores a new proof-of-work challenge, removing challenges created before `expired_before`
+    async fn add_pow_challenge(&self, nonce: &str, expired_before: SystemTime) -> Result<()>;
+
+    /// Removes a challenge created after `created_after` so it can only be solved once. Returns
+    /// false if there is no such challenge.
+    async fn take_pow_challenge(&self, nonce: &str, created_after: SystemTime) -> Result<bool>;
+
+    /// Stores a new owner session, removing sessions that expired before `now`
+    async fn add_owner_session(
+        &self,
+        token: &str,
+        owner_id: i32,
+        expires_at: SystemTime,
+        now: SystemTime,
+    ) -> Result<()>;
+
+    /// Returns the owner of a session that is still valid at `now`
+    async fn get_owner_session(&self, token: &str, now: SystemTime) -> Result<Option<Owner>>;
+
+    async fn remove_owner_session(&self, token: &str) -> Result<()>;
+
+    /// Lists payment addresses matching the filter, ordered by domain and username, together with
+    /// the total number of matches
+    async fn list_payment_addresses(
+        &self,
+        filter: PaymentAddressFilter,
+    ) -> Result<(Vec<PaymentAddress>, i64)>;
+
+    async fn get_payment_address_stats(
+        &self,
+        payment_address: &PaymentAddress,
+    ) -> Result<PaymentAddressStats>;
+
+    /// Reserves a username, replacing the reason if it was already reserved
+    async fn add_reserved_name(
+        &self,
+        domain: &str,
+        username: &str,
+        reason: Option<&str>,
+    ) -> Result<()>;
+
+    /// Releases a reserved username, returns false if it wasn't reserved
+    async fn remove_reserved_name(&self, domain: &str, username: &str) -> Result<bool>;
+
+    async fn list_reserved_names(&self, domain: Option<&str>) -> Result<Vec<ReservedName>>;
+
+    async fn is_name_reserved(&self, domain: &str, username: &str) -> Result<bool>;
+
+    async fn update_payment_address_status(
+        &self,
+        domain: &str,
+        username: &str,
+        status: AddressStatus,
+    ) -> Result<()>;
+
+    async fn add_address_report(&self, report: NewAddressReport) -> Result<()>;
+
+    /// Number of distinct clients with open reports of an address
+    async fn count_address_reporters(&self, payment_address_id: i32) -> Result<i64>;
+
+    /// Lists open reports of all addresses, oldest first
+    async fn list_open_address_reports(&self) -> Result<Vec<AddressReport>>;
+
+    /// Closes all open reports of an address, returns the number of closed reports
+    async fn resolve_address_reports(&self, payment_address_id: i32) -> Result<usize>;
+
+    async fn get_payment_address_by_id(&self, id: i32) -> Result<Option<PaymentAddress>>;
+
+    /// Sets or removes the webhook endpoint of an address
+    async fn update_payment_address_webhook(
+        &self,
+        domain: &str,
+        username: &str,
+        webhook: Option<&AddressWebhook>,
+    ) -> Result<()>;
+
+    /// Adds webhooks to the outbox, to be delivered right away
+    async fn add_webhook_events(&self, events: Vec<NewWebhookEvent>) -> Result<()>;
+
+    /// Lists webhooks whose next attempt is due at `now`, oldest first
+    async fn list_due_webhook_events(
+        &self,
+        now: SystemTime,
+        limit: i64,
+    ) -> Result<Vec<WebhookEvent>>;
+
+    /// Removes a webhook from the outbox once it was delivered or given up on
+    async fn remove_webhook_event(&self, id: i32) -> Result<()>;
+
+    /// Records a failed attempt of a webhook and when to try again
+    async fn retry_webhook_event(&self, id: i32, next_attempt_at: SystemTime) -> Result<()>;
+
+    /// Records a delivery attempt in the delivery log, removing entries created before
+    /// `expired_before`
+    async fn add_webhook_delivery(
+        &self,
+        delivery: NewWebhookDelivery,
+        expired_before: SystemTime,
+    ) -> Result<()>;
+
+    /// Lists the most recent delivery attempts, of the webhook of one address if given
+    async fn list_webhook_deliveries(
+        &self,
+        payment_address_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PaymentAddress {
+    pub id: i32,
     pub username: String,
     pub domain: String,
-    pub destination: DestinationPaymentAddress,
+    /// Destinations in the order they should be tried, never empty
+    pub destinations: Vec<AddressDestination>,
+    pub routing_policy: RoutingPolicy,
+    pub amount_rules: Vec<AmountRule>,
     pub authentication_token: String,
     pub created_at: SystemTime,
     pub updated_at: SystemTime,
+    pub expires_at: Option<SystemTime>,
+    pub settings: AddressSettings,
+    /// Owner account that may manage the address without its token
+    pub owner_id: Option<i32>,
+    pub status: AddressStatus,
+    /// Endpoint the owner receives the address's webhooks at
+    pub webhook: Option<AddressWebhook>,
+}
+
+/// Webhook endpoint configured by the owner of an address
+#[derive(Debug, Clone)]
+pub struct AddressWebhook {
+    pub url: String,
+    /// Secret the webhooks are signed with
+    pub secret: String,
+}
+
+/// Account grouping the payment addresses of one person
+#[derive(Debug, Clone)]
+pub struct Owner {
+    pub id: i32,
+    /// LNURL-auth linking key the owner logs in with, if any
+    pub linking_key: Option<String>,
+    pub created_at: SystemTime,
+    /// Hex Nostr pubkey the owner signs NIP-98 API requests with, if any
+    pub nostr_pubkey: Option<String>,
+}
+
+impl Owner {
+    /// Whether the owner logs in with LNURL-auth or Nostr, which the tokens of single addresses
+    /// mustn't bypass
+    pub fn has_login(&self) -> bool {
+        self.linking_key.is_some() || self.nostr_pubkey.is_some()
+    }
+}
+
+/// Owner controlled settings of a payment address
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressSettings {
+    /// Store LUD-12 comments of incoming payments for the owner
+    pub store_comments: bool,
+    /// LUD-18 payer data requested from payers and stored for the owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payer_data: Option<PayerDataRequest>,
+    /// LUD-09 success action shown to payers after paying
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_action: Option<SuccessAction>,
+    /// NIP-05 identity served for the username
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nostr: Option<NostrIdentity>,
+    /// Payment instructions published via BIP-353 DNS records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bip353: Option<PaymentInstructions>,
+}
+
+/// BIP-353 payment instructions of an address, at least one of the fields is set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentInstructions {
+    /// BOLT12 offer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offer: Option<String>,
+    /// On-chain address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub onchain: Option<String>,
+}
+
+/// Nostr public key and relays of the owner of an address, published via NIP-05
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NostrIdentity {
+    /// Hex encoded public key
+    pub pubkey: String,
+    #[serde(default)]
+    pub relays: Vec<String>,
+}
+
+/// LUD-09 success action set by the owner of an address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+pub enum SuccessAction {
+    Message { message: String },
+    Url { description: String, url: String },
+}
+
+/// LUD-18 `payerData` object of a manifest, listing the fields a payer is asked to send
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayerDataRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<PayerDataField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<PayerDataField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<PayerDataField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<PayerDataField>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayerDataField {
+    pub mandatory: bool,
+}
+
+impl PayerDataRequest {
+    /// Field names and requirements of the requested fields
+    pub fn fields(&self) -> [(&'static str, Option<PayerDataField>); 4] {
+        [
+            ("name", self.name),
+            ("pubkey", self.pubkey),
+            ("identifier", self.identifier),
+            ("email", self.email),
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields().iter().all(|(_, field)| field.is_none())
+    }
+
+    /// Combines two requests, a field is mandatory if either request makes it mandatory
+    pub fn merge(&self, other: &PayerDataRequest) -> PayerDataRequest {
+        fn merge_field(
+            a: Option<PayerDataField>,
+            b: Option<PayerDataField>,
+        ) -> Option<PayerDataField> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(PayerDataField {
+                    mandatory: a.mandatory || b.mandatory,
+                }),
+                (a, b) => a.or(b),
+            }
+        }
+
+        PayerDataRequest {
+            name: merge_field(self.name, other.name),
+            pubkey: merge_field(self.pubkey, other.pubkey),
+            identifier: merge_field(self.identifier, other.identifier),
+            email: merge_field(self.email, other.email),
+        }
+    }
+
+    /// Drops the fields not requested by `other`
+    pub fn restrict_to(&self, other: &PayerDataRequest) -> PayerDataRequest {
+        PayerDataRequest {
+            name: self.name.filter(|_| other.name.is_some()),
+            pubkey: self.pubkey.filter(|_| other.pubkey.is_some()),
+            identifier: self.identifier.filter(|_| other.identifier.is_some()),
+            email: self.email.filter(|_| other.email.is_some()),
+        }
+    }
+}
+
+/// LUD-12 comment sent along with a payment
+#[derive(Debug, Clone)]
+pub struct PaymentComment {
+    pub amount_msat: u64,
+    pub comment: String,
+    pub created_at: SystemTime,
+}
+
+/// LUD-18 payer data sent along with a payment
+#[derive(Debug, Clone)]
+pub struct PayerData {
+    pub amount_msat: u64,
+    /// JSON object as sent by the payer
+    pub payer_data: String,
+    pub created_at: SystemTime,
+}
+
+/// Invoice issued through a proxied callback
+#[derive(Debug, Clone)]
+pub struct NewInvoice {
+    pub payment_address_id: i32,
+    /// Opaque id used in the LUD-21 verify URL
+    pub verify_id: String,
+    pub pr: String,
+    pub amount_msat: u64,
+    /// LUD-21 verify URL of the upstream that issued the invoice
+    pub upstream_verify: Option<String>,
+    /// NIP-57 zap request to publish a zap receipt for once paid
+    pub zap_request: Option<String>,
+    /// Set for invoices issued by the node backend, to be forwarded to a BOLT12 offer once paid
+    pub forward: Option<OfferForward>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Payment to a BOLT12 offer owed for a paid invoice of the node backend
+#[derive(Debug, Clone)]
+pub struct OfferForward {
+    pub offer: Offer,
+    /// Payment hash of the node backend's invoice
+    pub payment_hash: String,
+    /// LUD-12 comment of the payer, forwarded as payer note
+    pub payer_note: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Invoice {
+    pub payment_address_id: i32,
+    pub verify_id: String,
+    pub pr: String,
+    pub amount_msat: u64,
+    pub upstream_verify: Option<String>,
+    pub settled: bool,
+    pub preimage: Option<String>,
+    pub zap_request: Option<String>,
+    pub zap_receipt_published_at: Option<SystemTime>,
+    pub forward: Option<OfferForward>,
+    pub forwarded_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+}
+
+/// Destination of a payment address with its routing weight
+#[derive(Debug, Clone)]
+pub struct WeightedDestination {
+    pub destination: DestinationPaymentAddress,
+    pub weight: u32,
+}
+
+/// Rule routing payments in the amount range `[min_msat, max_msat)` to a specific destination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AmountRule {
+    pub min_msat: Option<u64>,
+    pub max_msat: Option<u64>,
+    /// Index into the destinations of the payment address
+    pub destination_position: usize,
+}
+
+impl AmountRule {
+    pub fn matches(&self, amount_msat: u64) -> bool {
+        self.min_msat.is_none_or(|min| amount_msat >= min)
+            && self.max_msat.is_none_or(|max| amount_msat < max)
+    }
+}
+
+/// Destinations of a payment address and the rules for choosing among them
+#[derive(Debug, Clone)]
+pub struct Routing {
+    pub destinations: Vec<WeightedDestination>,
+    pub policy: RoutingPolicy,
+    /// Evaluated in order, the first matching rule takes precedence over the routing policy
+    pub amount_rules: Vec<AmountRule>,
+}
+
+/// Stored destination of a payment address
+#[derive(Debug, Clone)]
+pub struct AddressDestination {
+    pub id: i32,
+    pub destination: DestinationPaymentAddress,
+    pub weight: u32,
+    pub selection_count: u64,
+    pub last_selected_at: Option<SystemTime>,
+}
+
+/// Strategy for choosing among the destinations of a payment address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingPolicy {
+    /// Always use the first reachable destination in order
+    #[default]
+    Failover,
+    /// Pick a destination at random, proportionally to its weight
+    WeightedRandom,
+    /// Cycle through destinations, proportionally to their weight
+    RoundRobin,
+}
+
+impl RoutingPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoutingPolicy::Failover => "failover",
+            RoutingPolicy::WeightedRandom => "weighted_random",
+            RoutingPolicy::RoundRobin => "round_robin",
+        }
+    }
+}
+
+impl Display for RoutingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RoutingPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "failover" => RoutingPolicy::Failover,
+            "weighted_random" => RoutingPolicy::WeightedRandom,
+            "round_robin" => RoutingPolicy::RoundRobin,
+            _ => bail!("Unknown routing policy: {s}"),
+        })
+    }
+}
+
+/// Whether a payment address is served
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressStatus {
+    #[default]
+    Active,
+    /// Temporarily stopped by its owner
+    Paused,
+    /// Stopped by an operator, e.g. after it was reported for abuse. Only operators can serve a
+    /// suspended address again.
+    Suspended,
+}
+
+impl AddressStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressStatus::Active => "active",
+            AddressStatus::Paused => "paused",
+            AddressStatus::Suspended => "suspended",
+        }
+    }
+}
+
+impl Display for AddressStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AddressStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "active" => AddressStatus::Active,
+            "paused" => AddressStatus::Paused,
+            "suspended" => AddressStatus::Suspended,
+            _ => bail!("Unknown address status: {s}"),
+        })
+    }
+}
+
+/// Type of change recorded in the address event log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressEventKind {
+    Registered,
+    DestinationUpdated,
+    TokenRotated,
+    Renewed,
+    Removed,
+    Expired,
+    AdminRemoved,
+    SettingsUpdated,
+    Linked,
+    Unlinked,
+    Suspended,
+    Reinstated,
+    Paused,
+    Resumed,
+    WebhookUpdated,
+}
+
+impl AddressEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressEventKind::Registered => "registered",
+            AddressEventKind::DestinationUpdated => "destination_updated",
+            AddressEventKind::TokenRotated => "token_rotated",
+            AddressEventKind::Renewed => "renewed",
+            AddressEventKind::Removed => "removed",
+            AddressEventKind::Expired => "expired",
+            AddressEventKind::AdminRemoved => "admin_removed",
+            AddressEventKind::SettingsUpdated => "settings_updated",
+            AddressEventKind::Linked => "linked",
+            AddressEventKind::Unlinked => "unlinked",
+            AddressEventKind::Suspended => "suspended",
+            AddressEventKind::Reinstated => "reinstated",
+            AddressEventKind::Paused => "paused",
+            AddressEventKind::Resumed => "resumed",
+            AddressEventKind::WebhookUpdated => "webhook_updated",
+        }
+    }
+}
+
+impl Display for AddressEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AddressEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "registered" => AddressEventKind::Registered,
+            "destination_updated" => AddressEventKind::DestinationUpdated,
+            "token_rotated" => AddressEventKind::TokenRotated,
+            "renewed" => AddressEventKind::Renewed,
+            "removed" => AddressEventKind::Removed,
+            "expired" => AddressEventKind::Expired,
+            "admin_removed" => AddressEventKind::AdminRemoved,
+            "settings_updated" => AddressEventKind::SettingsUpdated,
+            "linked" => AddressEventKind::Linked,
+            "unlinked" => AddressEventKind::Unlinked,
+            "suspended" => AddressEventKind::Suspended,
+            "reinstated" => AddressEventKind::Reinstated,
+            "paused" => AddressEventKind::Paused,
+            "resumed" => AddressEventKind::Resumed,
+            "webhook_updated" => AddressEventKind::WebhookUpdated,
+            _ => bail!("Unknown address event kind: {s}"),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAddressEvent {
+    pub domain: String,
+    pub username: String,
+    pub kind: AddressEventKind,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub old_destination: Option<String>,
+    pub new_destination: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddressEvent {
+    pub id: i32,
+    pub domain: String,
+    pub username: String,
+    pub kind: AddressEventKind,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub old_destination: Option<String>,
+    pub new_destination: Option<String>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AddressEventFilter {
+    pub domain: Option<String>,
+    pub username: Option<String>,
+    pub client_ip: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAddressReport {
+    pub payment_address_id: i32,
+    pub reason: String,
+    /// How the reporter may be contacted, if they want to
+    pub contact: Option<String>,
+    pub client_ip: Option<String>,
+}
+
+/// Abuse report of a payment address
+#[derive(Debug, Clone)]
+pub struct AddressReport {
+    pub id: i32,
+    pub domain: String,
+    pub username: String,
+    pub reason: String,
+    pub contact: Option<String>,
+    pub client_ip: Option<String>,
+    pub created_at: SystemTime,
+}
+
+/// Webhook to be added to the outbox
+#[derive(Debug, Clone)]
+pub struct NewWebhookEvent {
+    /// Address whose owner configured the endpoint, `None` for the operator's endpoints
+    pub payment_address_id: Option<i32>,
+    pub url: String,
+    /// Secret of an owner's endpoint, the operator's endpoints use the configured secret
+    pub secret: Option<String>,
+    pub event_id: String,
+    pub event: String,
+    pub payload: String,
+}
+
+/// Webhook waiting in the outbox
+#[derive(Debug, Clone)]
+pub struct WebhookEvent {
+    pub id: i32,
+    pub payment_address_id: Option<i32>,
+    pub url: String,
+    pub secret: Option<String>,
+    pub event_id: String,
+    pub event: String,
+    pub payload: String,
+    /// Failed delivery attempts so far
+    pub attempts: u32,
+    pub created_at: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewWebhookDelivery {
+    pub payment_address_id: Option<i32>,
+    pub url: String,
+    pub event_id: String,
+    pub event: String,
+    pub attempt: u32,
+    /// HTTP status of the endpoint's response, if it responded
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+/// Attempt of delivering a webhook
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub payment_address_id: Option<i32>,
+    pub url: String,
+    pub event_id: String,
+    pub event: String,
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+    pub created_at: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct PaymentAddressFilter {
+    pub domain: Option<String>,
+    /// Case-insensitive substring of the username or any of the destinations
+    pub search: Option<String>,
+    pub owner_id: Option<i32>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Usage of a payment address. Invoices are only known for proxied addresses.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PaymentAddressStats {
+    pub invoices: i64,
+    pub settled_invoices: i64,
+    pub settled_msat: i64,
+    pub last_invoice_at: Option<SystemTime>,
+    pub comments: i64,
+    pub events: i64,
+    pub last_event_at: Option<SystemTime>,
+}
+
+/// Username kept from being registered
+#[derive(Debug, Clone)]
+pub struct ReservedName {
+    pub domain: String,
+    pub username: String,
+    pub reason: Option<String>,
+    pub created_at: SystemTime,
+}
+
+/// Expiry time of an address renewed at `now`. Renewing before expiry extends the current
+/// lifetime instead of shortening it.
+pub fn renewed_expiry(
+    expires_at: Option<SystemTime>,
+    now: SystemTime,
+    lifetime: Duration,
+) -> SystemTime {
+    expires_at.unwrap_or(now).max(now) + lifetime
+}
+
+impl PaymentAddress {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Payment instructions to publish via BIP-353, if any
+    pub fn payment_instructions(&self) -> Option<PaymentInstructions> {
+        self.settings
+            .payment_instructions(self.destinations.iter().map(|d| &d.destination))
+    }
+}
+
+impl AddressSettings {
+    /// Payment instructions to publish via BIP-353 for an address with these settings and
+    /// destinations. The owner's instructions take precedence, the first BOLT12 offer destination
+    /// is published if they don't include an offer.
+    pub fn payment_instructions<'a>(
+        &self,
+        destinations: impl IntoIterator<Item = &'a DestinationPaymentAddress>,
+    ) -> Option<PaymentInstructions> {
+        let mut instructions = self.bip353.clone().unwrap_or(PaymentInstructions {
+            offer: None,
+            onchain: None,
+        });
+        if instructions.offer.is_none() {
+            instructions.offer =
+                destinations
+                    .into_iter()
+                    .find_map(|destination| match destination {
+                        DestinationPaymentAddress::Bolt12Offer(offer) => Some(offer.to_string()),
+                        _ => None,
+                    });
+        }
+
+        (instructions.offer.is_some() || instructions.onchain.is_some()).then_some(instructions)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum DestinationPaymentAddress {
     Lnurl(lnurl::lnurl::LnUrl),
-    LnAddress {
-        user: String,
-        domain: String
-    },
+    LnAddress { user: String, domain: String },
+    Bolt12Offer(Offer),
 }
 
 impl DestinationPaymentAddress {
-    pub fn url(&self) -> String {
+    /// LNURL-pay URL of the destination, BOLT12 offers have none
+    pub fn url(&self) -> Option<String> {
         match self {
-            DestinationPaymentAddress::Lnurl(lnurl) => lnurl.url.clone(),
-            DestinationPaymentAddress::LnAddress { user, domain } => format!("https://{domain}/.well-known/lnurlp/{user}"),
+            DestinationPaymentAddress::Lnurl(lnurl) => Some(lnurl.url.clone()),
+            DestinationPaymentAddress::LnAddress { user, domain } => {
+                Some(format!("https://{domain}/.well-known/lnurlp/{user}"))
+            }
+            DestinationPaymentAddress::Bolt12Offer(_) => None,
         }
     }
+
+    /// Host serving the LNURL-pay endpoint of the destination, BOLT12 offers have none
+    pub fn host(&self) -> Option<String> {
+        let url = url::Url::parse(&self.url()?).ok()?;
+        url.host_str().map(str::to_lowercase)
+    }
 }
 
 impl Display for DestinationPaymentAddress {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DestinationPaymentAddress::Lnurl(lnurl) => write!(f, "{}", lnurl),
-            DestinationPaymentAddress::LnAddress { user, domain } => write!(f, "{}@{}", user, domain),
+            DestinationPaymentAddress::LnAddress { user, domain } => {
+                write!(f, "{}@{}", user, domain)
+            }
+            DestinationPaymentAddress::Bolt12Offer(offer) => write!(f, "{}", offer),
         }
     }
 }
 
+impl DestinationPaymentAddress {
+    /// Parses a destination as stored by lnaddrd. Unlike input, stored LNURLs may use plain HTTP
+    /// for clearnet hosts since those were accepted before, so that their addresses can still be
+    /// loaded and updated. Such destinations are never requested.
+    pub fn from_stored(s: &str) -> Result<Self> {
+        if strip_prefix_ignore_case(s, "lnurl1").is_some() {
+            let lnurl = lnurl::lnurl::LnUrl::decode(s.to_lowercase())
+                .map_err(|_| anyhow::anyhow!("Invalid bech32 LNURL"))?;
+            return Ok(DestinationPaymentAddress::Lnurl(lnurl_from_any_http_url(
+                &lnurl.url,
+            )?));
+        }
+        DestinationPaymentAddress::from_str(s)
+    }
+}
+
 impl FromStr for DestinationPaymentAddress {
     type Err = anyhow::Error;
 
+    /// Parses a bech32 LNURL, LUD-17 `lnurlp://` URL, LNURL-pay URL, lightning address or BOLT12
+    /// offer, optionally prefixed with `lightning:`. The result is displayed in its canonical form,
+    /// which parses to the same destination.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Ok(lnurl) = lnurl::lnurl::LnUrl::decode(s.to_owned()) {
-            Ok(DestinationPaymentAddress::Lnurl(lnurl))
-        } else {
-            let parts: Vec<&str> = s.split('@').collect();
+        let s = s.trim();
+        let s = strip_prefix_ignore_case(s, "lightning:")
+            .unwrap_or(s)
+            .trim();
 
+        if strip_prefix_ignore_case(s, "lno1").is_some() {
+            return Ok(DestinationPaymentAddress::Bolt12Offer(Offer::parse(s)?));
+        }
+        if strip_prefix_ignore_case(s, "lnurl1").is_some() {
+            // bech32 is case insensitive, but the decoder rejects mixed case
+            let lnurl = lnurl::lnurl::LnUrl::decode(s.to_lowercase())
+                .map_err(|_| anyhow::anyhow!("Invalid bech32 LNURL"))?;
+            return Ok(DestinationPaymentAddress::Lnurl(lnurl_from_url(
+                &lnurl.url,
+            )?));
+        }
+        if let Some(rest) = strip_prefix_ignore_case(s, "lnurlp://") {
+            // LUD-17: onion services are reached over plain HTTP, everything else over HTTPS
+            let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+            let scheme = if host
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .ends_with(".onion")
+            {
+                "http"
+            } else {
+                "https"
+            };
+            return Ok(DestinationPaymentAddress::Lnurl(lnurl_from_url(&format!(
+                "{scheme}://{rest}"
+            ))?));
+        }
+        for scheme in ["lnurlw://", "lnurlc://", "keyauth://"] {
             ensure!(
-                parts.len() == 2,
-                "Invalid destination payment address, neither lnurl nor lnaddress"
+                strip_prefix_ignore_case(s, scheme).is_none(),
+                "Only LNURL-pay destinations are supported"
             );
+        }
+        if strip_prefix_ignore_case(s, "https://").is_some()
+            || strip_prefix_ignore_case(s, "http://").is_some()
+        {
+            let url = url::Url::parse(s)?;
+            // LUD-01 fallback scheme, the LNURL is passed as query parameter
+            if let Some((_, lnurl)) = url.query_pairs().find(|(key, _)| key == "lightning") {
+                return DestinationPaymentAddress::from_str(&lnurl);
+            }
+            return Ok(DestinationPaymentAddress::Lnurl(lnurl_from_url(s)?));
+        }
+
+        let Some((user, domain)) = s.split_once('@') else {
+            bail!("Invalid destination payment address, neither lnurl, lnaddress nor BOLT12 offer");
+        };
+        ensure!(
+            !domain.contains('@'),
+            "Invalid lightning address, it must contain exactly one @"
+        );
+        // LUD-16 only allows lowercase usernames, but wallets commonly accept any case
+        let user = user.to_lowercase();
+        ensure!(
+            !user.is_empty()
+                && user.chars().all(|c| {
+                    c.is_ascii_lowercase()
+                        || c.is_ascii_digit()
+                        || matches!(c, '-' | '_' | '.' | '+')
+                }),
+            "Invalid lightning address username {user}"
+        );
+        let url = url::Url::parse(&format!("https://{domain}"))
+            .ok()
+            .filter(|url| {
+                url.path() == "/"
+                    && url.query().is_none()
+                    && url.fragment().is_none()
+                    && url.username().is_empty()
+                    && url.password().is_none()
+            })
+            .with_context(|| format!("Invalid lightning address domain {domain}"))?;
+        let host = url.host_str().unwrap_or_default();
+        let domain = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_owned(),
+        };
+
+        Ok(DestinationPaymentAddress::LnAddress { user, domain })
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    s.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &s[prefix.len()..])
+}
+
+/// LNURL of a LNURL-pay URL, normalizing the URL so equal URLs have the same encoding
+fn lnurl_from_url(url: &str) -> Result<lnurl::lnurl::LnUrl> {
+    check_lnurl_url(url)?;
+    lnurl_from_any_http_url(url)
+}
+
+/// Like [`lnurl_from_url`], but also accepting plain HTTP for clearnet hosts
+fn lnurl_from_any_http_url(url: &str) -> Result<lnurl::lnurl::LnUrl> {
+    let url = url::Url::parse(url).context("Invalid LNURL-pay URL")?;
+    ensure!(
+        matches!(url.scheme(), "https" | "http") && url.has_host(),
+        "LNURL-pay URL must be an HTTP(S) URL"
+    );
+    ensure!(
+        url.username().is_empty() && url.password().is_none(),
+        "LNURL-pay URL must not contain credentials"
+    );
+
+    Ok(lnurl::lnurl::LnUrl::from_url(url.to_string()))
+}
 
-            Ok(DestinationPaymentAddress::LnAddress {
-                user: parts[0].to_string(),
-                domain: parts[1].to_string(),
+/// Checks that a LNURL-pay URL uses HTTPS. As in LUD-17 only onion services, which are
+/// authenticated by their address, may be reached over plain HTTP.
+pub fn check_lnurl_url(url: &str) -> Result<()> {
+    let url = url::Url::parse(url).context("Invalid LNURL-pay URL")?;
+    let onion = url
+        .host_str()
+        .is_some_and(|host| host.to_lowercase().ends_with(".onion"));
+    ensure!(
+        url.scheme() == "https" || (url.scheme() == "http" && onion),
+        "LNURL-pay URL must be an HTTPS URL, plain HTTP is only allowed for onion services"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const OFFER: &str =
+        "lno1pgx9getnwss8vetrw3hhyuckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg";
+
+    fn lnaddress() -> impl Strategy<Value = DestinationPaymentAddress> {
+        (
+            "[a-z0-9][a-z0-9._+-]{0,15}",
+            "[a-z0-9]{1,10}(\\.[a-z0-9-]{1,10}){0,2}\\.[a-z]{2,6}",
+        )
+            .prop_map(|(user, domain)| DestinationPaymentAddress::LnAddress { user, domain })
+    }
+
+    fn lnurl() -> impl Strategy<Value = DestinationPaymentAddress> {
+        (
+            "[a-z0-9]{1,10}\\.[a-z]{2,6}",
+            proptest::option::of(1u16..),
+            "(/[a-zA-Z0-9_.~-]{1,12}){0,4}",
+            proptest::option::of("[a-z]{1,8}=[a-zA-Z0-9]{0,12}"),
+        )
+            .prop_map(|(host, port, path, query)| {
+                let port = port.map(|port| format!(":{port}")).unwrap_or_default();
+                let query = query.map(|query| format!("?{query}")).unwrap_or_default();
+                let url = format!("https://{host}{port}{path}{query}");
+                DestinationPaymentAddress::Lnurl(lnurl_from_url(&url).unwrap())
             })
+    }
+
+    fn destination() -> impl Strategy<Value = DestinationPaymentAddress> {
+        prop_oneof![
+            lnaddress(),
+            lnurl(),
+            Just(DestinationPaymentAddress::Bolt12Offer(
+                Offer::parse(OFFER).unwrap()
+            )),
+        ]
+    }
+
+    fn parse(s: &str) -> DestinationPaymentAddress {
+        DestinationPaymentAddress::from_str(s).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trips(destination in destination()) {
+            let displayed = destination.to_string();
+            let parsed = parse(&displayed);
+            prop_assert_eq!(&parsed, &destination);
+            prop_assert_eq!(parsed.to_string(), displayed);
+        }
+
+        #[test]
+        fn accepts_decorated_input(
+            destination in destination(),
+            uppercase: bool,
+            prefix in prop_oneof![Just(""), Just("lightning:"), Just("LIGHTNING:")],
+            padding in "[ \t\n]{0,3}",
+        ) {
+            let mut input = destination.to_string();
+            if uppercase {
+                input = input.to_uppercase();
+            }
+            let input = format!("{padding}{prefix}{input}{padding}");
+            prop_assert_eq!(parse(&input), destination);
+        }
+
+        #[test]
+        fn lnurl_urls_match_their_bech32_encoding(destination in lnurl()) {
+            let DestinationPaymentAddress::Lnurl(lnurl) = &destination else {
+                unreachable!();
+            };
+            prop_assert_eq!(parse(&lnurl.url), destination.clone());
+            let lud17 = lnurl.url.replacen("https://", "lnurlp://", 1);
+            prop_assert_eq!(parse(&lud17), destination.clone());
+            let fallback = format!(
+                "https://wallet.example?lightning={}",
+                lnurl.encode().to_uppercase()
+            );
+            prop_assert_eq!(parse(&fallback), destination);
+        }
+
+        #[test]
+        fn rejects_plain_http_lnurls_unless_onion(destination in lnurl(), onion: bool) {
+            let DestinationPaymentAddress::Lnurl(lnurl) = &destination else {
+                unreachable!();
+            };
+            let mut url = url::Url::parse(&lnurl.url).unwrap();
+            if onion {
+                let host = format!("{}.onion", url.host_str().unwrap());
+                url.set_host(Some(&host)).unwrap();
+            }
+            let http = url.as_str().replacen("https://", "http://", 1);
+            let bech32 = lnurl::lnurl::LnUrl::from_url(http.clone()).encode();
+            for input in [&http, &bech32] {
+                prop_assert_eq!(DestinationPaymentAddress::from_str(input).is_ok(), onion);
+            }
+        }
+
+        #[test]
+        fn rejects_multiple_at_signs(user in "[a-z]{1,8}", domain in "[a-z]{1,8}\\.com") {
+            let input = format!("{user}@{user}@{domain}");
+            prop_assert!(DestinationPaymentAddress::from_str(&input).is_err());
+        }
+    }
+
+    #[test]
+    fn normalizes_onion_lud17_urls_to_http() {
+        let DestinationPaymentAddress::Lnurl(lnurl) = parse("lnurlp://abcdef.onion/pay") else {
+            panic!("not an LNURL");
+        };
+        assert_eq!(lnurl.url, "http://abcdef.onion/pay");
+    }
+
+    #[test]
+    fn rejects_plain_http_for_clearnet_hosts() {
+        assert!(DestinationPaymentAddress::from_str("http://example.com/pay").is_err());
+        assert!(DestinationPaymentAddress::from_str("http://abcdef.onion/pay").is_ok());
+        let lnurl = lnurl::lnurl::LnUrl::from_url("http://example.com/pay".to_owned());
+        assert!(DestinationPaymentAddress::from_str(&lnurl.encode()).is_err());
+        assert!(DestinationPaymentAddress::from_stored(&lnurl.encode()).is_ok());
+    }
+
+    #[test]
+    fn rejects_non_pay_lnurls() {
+        assert!(DestinationPaymentAddress::from_str("lnurlw://example.com/withdraw").is_err());
+        assert!(DestinationPaymentAddress::from_str("ftp://example.com/pay").is_err());
+    }
+
+    fn amount_rule(min_msat: Option<u64>, max_msat: Option<u64>) -> AmountRule {
+        AmountRule {
+            min_msat,
+            max_msat,
+            destination_position: 0,
         }
     }
+
+    #[test]
+    fn amount_rule_range_includes_min_and_excludes_max() {
+        let rule = amount_rule(Some(1_000), Some(5_000));
+
+        assert!(!rule.matches(999));
+        assert!(rule.matches(1_000));
+        assert!(rule.matches(4_999));
+        assert!(!rule.matches(5_000));
+    }
+
+    #[test]
+    fn amount_rule_without_bounds_is_open_ended() {
+        assert!(amount_rule(None, Some(5_000)).matches(0));
+        assert!(!amount_rule(None, Some(5_000)).matches(5_000));
+        assert!(amount_rule(Some(1_000), None).matches(u64::MAX));
+        assert!(!amount_rule(Some(1_000), None).matches(999));
+        assert!(amount_rule(None, None).matches(0));
+    }
+
+    #[test]
+    fn adjacent_amount_rules_do_not_overlap() {
+        let below = amount_rule(None, Some(100_000));
+        let above = amount_rule(Some(100_000), None);
+
+        for amount_msat in [0, 99_999, 100_000, 100_001] {
+            assert!(below.matches(amount_msat) != above.matches(amount_msat));
+        }
+    }
+
+    fn payment_address(expires_at: Option<SystemTime>) -> PaymentAddress {
+        PaymentAddress {
+            id: 1,
+            username: "alice".to_owned(),
+            domain: "example.com".to_owned(),
+            destinations: Vec::new(),
+            routing_policy: RoutingPolicy::default(),
+            amount_rules: Vec::new(),
+            authentication_token: "token".to_owned(),
+            created_at: UNIX_EPOCH,
+            updated_at: UNIX_EPOCH,
+            expires_at,
+            settings: AddressSettings::default(),
+            owner_id: None,
+            status: AddressStatus::default(),
+            webhook: None,
+        }
+    }
+
+    #[test]
+    fn expires_at_expiry_time() {
+        let expires_at = UNIX_EPOCH + Duration::from_secs(1000);
+        let entry = payment_address(Some(expires_at));
+
+        assert!(!entry.is_expired(expires_at - Duration::from_secs(1)));
+        assert!(entry.is_expired(expires_at));
+        assert!(entry.is_expired(expires_at + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn never_expires_without_expiry_time() {
+        assert!(!payment_address(None).is_expired(SystemTime::now()));
+    }
+
+    #[test]
+    fn owners_with_lnurl_auth_or_nostr_have_a_login() {
+        let owner = Owner {
+            id: 1,
+            linking_key: None,
+            created_at: UNIX_EPOCH,
+            nostr_pubkey: None,
+        };
+        assert!(!owner.has_login());
+        let linked = Owner {
+            linking_key: Some("02".repeat(33)),
+            ..owner.clone()
+        };
+        assert!(linked.has_login());
+        let nostr = Owner {
+            nostr_pubkey: Some("ab".repeat(32)),
+            ..owner
+        };
+        assert!(nostr.has_login());
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn renewal_extends_remaining_lifetime() {
+        let now = UNIX_EPOCH + 100 * DAY;
+        assert_eq!(
+            renewed_expiry(Some(now + 5 * DAY), now, 30 * DAY),
+            now + 35 * DAY
+        );
+    }
+
+    #[test]
+    fn renewal_of_expired_address_starts_now() {
+        let now = UNIX_EPOCH + 100 * DAY;
+        assert_eq!(
+            renewed_expiry(Some(now - 5 * DAY), now, 30 * DAY),
+            now + 30 * DAY
+        );
+        assert_eq!(renewed_expiry(None, now, 30 * DAY), now + 30 * DAY);
+    }
 }
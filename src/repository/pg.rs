@@ -1,5 +1,9 @@
-use anyhow::{bail, Result};
-use std::{str::FromStr, sync::Arc, time::SystemTime};
+use anyhow::{Result, bail};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 use tracing::info;
 
 use async_trait::async_trait;
@@ -10,8 +14,14 @@ use diesel::{
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 
 use super::{
-    DestinationPaymentAddress, IPaymentAddressRepository, PaymentAddress, PaymentAddressRepository,
+    AddressDestination, AddressEvent, AddressEventFilter, AddressEventKind, AddressReport,
+    AddressSettings, AddressStatus, AddressWebhook, AmountRule, DestinationPaymentAddress,
+    IPaymentAddressRepository, Invoice, NewAddressEvent, NewAddressReport, NewInvoice,
+    NewWebhookDelivery, NewWebhookEvent, OfferForward, Owner, PayerData, PaymentAddress,
+    PaymentAddressFilter, PaymentAddressRepository, PaymentAddressStats, PaymentComment,
+    ReservedName, Routing, RoutingPolicy, WebhookDelivery, WebhookEvent, renewed_expiry,
 };
+use crate::bolt12::Offer;
 
 type PooledConnection =
     diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>;
@@ -51,7 +61,7 @@ impl IPaymentAddressRepository for PgPaymentAddressRepository {
             .filter(payment_addresses::username.eq(username))
             .first::<PaymentAddressEntry>(&mut conn)
         {
-            Ok(lnaddress) => Ok(Some(lnaddress.into())),
+            Ok(lnaddress) => Ok(Some(load_payment_address(&mut conn, lnaddress)?)),
             Err(diesel::result::Error::NotFound) => Ok(None),
             Err(e) => Err(e.into()),
         }
@@ -61,92 +71,1804 @@ impl IPaymentAddressRepository for PgPaymentAddressRepository {
         &self,
         domain: &str,
         username: &str,
-        destination: DestinationPaymentAddress,
+        routing: Routing,
+        settings: AddressSettings,
+        owner_id: Option<i32>,
         authentication_token: &str,
+        expires_at: Option<SystemTime>,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        conn.transaction(|conn| {
+            // Expired addresses that weren't swept yet shouldn't block re-registration
+            diesel::delete(
+                payment_addresses::table
+                    .filter(payment_addresses::domain.eq(domain))
+                    .filter(payment_addresses::username.eq(username))
+                    .filter(payment_addresses::expires_at.le(SystemTime::now())),
+            )
+            .execute(conn)?;
+
+            let payment_address_id = diesel::insert_into(payment_addresses::table)
+                .values((
+                    payment_addresses::domain.eq(domain),
+                    payment_addresses::username.eq(username),
+                    payment_addresses::authentication_token.eq(authentication_token),
+                    payment_addresses::expires_at.eq(expires_at),
+                    payment_addresses::routing_policy.eq(routing.policy.as_str()),
+                    payment_addresses::owner_id.eq(owner_id),
+                ))
+                .returning(payment_addresses::id)
+                .get_result::<i32>(conn)?;
+
+            insert_routing(conn, payment_address_id, &routing)?;
+            update_settings(conn, domain, username, settings)?;
+
+            Ok::<_, anyhow::Error>(())
+        })
+    }
+
+    async fn remove_payment_address(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<PaymentAddress>> {
+        let mut conn = self.pool.get()?;
+
+        conn.transaction(|conn| {
+            let Some(entry) = payment_addresses::table
+                .filter(payment_addresses::domain.eq(domain))
+                .filter(payment_addresses::username.eq(username))
+                .first::<PaymentAddressEntry>(conn)
+                .optional()?
+            else {
+                return Ok(None);
+            };
+            let removed = load_payment_address(conn, entry)?;
+
+            diesel::delete(payment_addresses::table.find(removed.id)).execute(conn)?;
+
+            Ok(Some(removed))
+        })
+    }
+
+    async fn update_payment_address_routing(
+        &self,
+        domain: &str,
+        username: &str,
+        routing: Routing,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        conn.transaction(|conn| {
+            let Some(payment_address_id) = diesel::update(
+                payment_addresses::table
+                    .filter(payment_addresses::domain.eq(domain))
+                    .filter(payment_addresses::username.eq(username)),
+            )
+            .set((
+                payment_addresses::routing_policy.eq(routing.policy.as_str()),
+                payment_addresses::updated_at.eq(SystemTime::now()),
+            ))
+            .returning(payment_addresses::id)
+            .get_result::<i32>(conn)
+            .optional()?
+            else {
+                bail!("Unknown payment address {username}@{domain}");
+            };
+
+            diesel::delete(
+                payment_address_destinations::table.filter(
+                    payment_address_destinations::payment_address_id.eq(payment_address_id),
+                ),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                amount_rules::table.filter(amount_rules::payment_address_id.eq(payment_address_id)),
+            )
+            .execute(conn)?;
+            insert_routing(conn, payment_address_id, &routing)?;
+
+            Ok(())
+        })
+    }
+
+    async fn update_payment_address_settings(
+        &self,
+        domain: &str,
+        username: &str,
+        settings: AddressSettings,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        update_settings(&mut conn, domain, username, settings)
+    }
+
+    async fn add_payment_comment(
+        &self,
+        payment_address_id: i32,
+        amount_msat: u64,
+        comment: &str,
     ) -> Result<()> {
         let mut conn = self.pool.get()?;
 
-        diesel::insert_into(payment_addresses::table)
+        diesel::insert_into(payment_comments::table)
             .values((
-                payment_addresses::domain.eq(domain),
-                payment_addresses::username.eq(username),
-                payment_addresses::lnurl.eq(destination.to_string()),
-                payment_addresses::authentication_token.eq(authentication_token),
+                payment_comments::payment_address_id.eq(payment_address_id),
+                payment_comments::amount_msat.eq(i64::try_from(amount_msat)?),
+                payment_comments::comment.eq(comment),
             ))
             .execute(&mut conn)?;
 
         Ok(())
     }
 
-    async fn remove_payment_address(
+    async fn list_payment_comments(
+        &self,
+        payment_address_id: i32,
+        limit: i64,
+    ) -> Result<Vec<PaymentComment>> {
+        let mut conn = self.pool.get()?;
+
+        payment_comments::table
+            .filter(payment_comments::payment_address_id.eq(payment_address_id))
+            .order(payment_comments::id.desc())
+            .limit(limit)
+            .select((
+                payment_comments::amount_msat,
+                payment_comments::comment,
+                payment_comments::created_at,
+            ))
+            .load::<(i64, String, SystemTime)>(&mut conn)?
+            .into_iter()
+            .map(|(amount_msat, comment, created_at)| {
+                Ok(PaymentComment {
+                    amount_msat: amount_msat.try_into()?,
+                    comment,
+                    created_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn add_payer_data(
+        &self,
+        payment_address_id: i32,
+        amount_msat: u64,
+        payer_data: &str,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(payment_payer_data::table)
+            .values((
+                payment_payer_data::payment_address_id.eq(payment_address_id),
+                payment_payer_data::amount_msat.eq(i64::try_from(amount_msat)?),
+                payment_payer_data::payer_data.eq(payer_data),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn list_payer_data(&self, payment_address_id: i32, limit: i64) -> Result<Vec<PayerData>> {
+        let mut conn = self.pool.get()?;
+
+        payment_payer_data::table
+            .filter(payment_payer_data::payment_address_id.eq(payment_address_id))
+            .order(payment_payer_data::id.desc())
+            .limit(limit)
+            .select((
+                payment_payer_data::amount_msat,
+                payment_payer_data::payer_data,
+                payment_payer_data::created_at,
+            ))
+            .load::<(i64, String, SystemTime)>(&mut conn)?
+            .into_iter()
+            .map(|(amount_msat, payer_data, created_at)| {
+                Ok(PayerData {
+                    amount_msat: amount_msat.try_into()?,
+                    payer_data,
+                    created_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn add_invoice(&self, invoice: NewInvoice) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(invoices::table)
+            .values((
+                invoices::payment_address_id.eq(invoice.payment_address_id),
+                invoices::verify_id.eq(&invoice.verify_id),
+                invoices::pr.eq(&invoice.pr),
+                invoices::amount_msat.eq(i64::try_from(invoice.amount_msat)?),
+                invoices::upstream_verify.eq(&invoice.upstream_verify),
+                invoices::zap_request.eq(&invoice.zap_request),
+                invoices::bolt12_offer.eq(invoice.forward.as_ref().map(|f| f.offer.to_string())),
+                invoices::payment_hash.eq(invoice.forward.as_ref().map(|f| &f.payment_hash)),
+                invoices::payer_note
+                    .eq(invoice.forward.as_ref().and_then(|f| f.payer_note.as_ref())),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn get_invoice(
+        &self,
+        payment_address_id: i32,
+        verify_id: &str,
+    ) -> Result<Option<Invoice>> {
+        let mut conn = self.pool.get()?;
+
+        invoices::table
+            .filter(invoices::payment_address_id.eq(payment_address_id))
+            .filter(invoices::verify_id.eq(verify_id))
+            .first::<InvoiceEntry>(&mut conn)
+            .optional()?
+            .map(TryInto::try_into)
+            .transpose()
+    }
+
+    async fn settle_invoice(&self, verify_id: &str, preimage: Option<&str>) -> Result<bool> {
+        let mut conn = self.pool.get()?;
+
+        let settled = diesel::update(
+            invoices::table
+                .filter(invoices::verify_id.eq(verify_id))
+                .filter(invoices::settled.eq(false)),
+        )
+        .set((invoices::settled.eq(true), invoices::preimage.eq(preimage)))
+        .execute(&mut conn)?;
+
+        Ok(settled > 0)
+    }
+
+    async fn list_unsettled_invoices(
+        &self,
+        created_after: SystemTime,
+        all: bool,
+    ) -> Result<Vec<Invoice>> {
+        let mut conn = self.pool.get()?;
+
+        let mut query = invoices::table
+            .filter(invoices::settled.eq(false))
+            .filter(invoices::created_at.gt(created_after))
+            .filter(
+                invoices::upstream_verify
+                    .is_not_null()
+                    .or(invoices::payment_hash.is_not_null()),
+            )
+            .into_boxed();
+        if !all {
+            query = query.filter(
+                invoices::payment_address_id.eq_any(
+                    payment_addresses::table
+                        .filter(payment_addresses::webhook_url.is_not_null())
+                        .select(payment_addresses::id),
+                ),
+            );
+        }
+
+        query
+            .order(invoices::created_at.asc())
+            .load::<InvoiceEntry>(&mut conn)?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+
+    async fn list_pending_zap_invoices(&self, created_after: SystemTime) -> Result<Vec<Invoice>> {
+        let mut conn = self.pool.get()?;
+
+        invoices::table
+            .filter(invoices::zap_request.is_not_null())
+            .filter(invoices::zap_receipt_published_at.is_null())
+            .filter(invoices::created_at.gt(created_after))
+            .order(invoices::id.asc())
+            .load::<InvoiceEntry>(&mut conn)?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+
+    async fn mark_zap_receipt_published(&self, verify_id: &str) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::update(invoices::table.filter(invoices::verify_id.eq(verify_id)))
+            .set(invoices::zap_receipt_published_at.eq(Some(SystemTime::now())))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn list_pending_offer_forwards(&self, created_after: SystemTime) -> Result<Vec<Invoice>> {
+        let mut conn = self.pool.get()?;
+
+        invoices::table
+            .filter(invoices::bolt12_offer.is_not_null())
+            .filter(invoices::forwarded_at.is_null())
+            .filter(invoices::created_at.gt(created_after))
+            .order(invoices::id.asc())
+            .load::<InvoiceEntry>(&mut conn)?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+
+    async fn claim_offer_forward(&self, verify_id: &str) -> Result<bool> {
+        let mut conn = self.pool.get()?;
+
+        let claimed = diesel::update(
+            invoices::table
+                .filter(invoices::verify_id.eq(verify_id))
+                .filter(invoices::forwarded_at.is_null()),
+        )
+        .set(invoices::forwarded_at.eq(Some(SystemTime::now())))
+        .execute(&mut conn)?;
+
+        Ok(claimed > 0)
+    }
+
+    async fn release_offer_forward(&self, verify_id: &str) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::update(invoices::table.filter(invoices::verify_id.eq(verify_id)))
+            .set(invoices::forwarded_at.eq(None::<SystemTime>))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn record_destination_selection(&self, destination_id: i32) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::update(payment_address_destinations::table.find(destination_id))
+            .set((
+                payment_address_destinations::selection_count
+                    .eq(payment_address_destinations::selection_count + 1),
+                payment_address_destinations::last_selected_at.eq(Some(SystemTime::now())),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn list_bip353_payment_addresses(&self, domain: &str) -> Result<Vec<PaymentAddress>> {
+        let mut conn = self.pool.get()?;
+
+        payment_addresses::table
+            .filter(payment_addresses::domain.eq(domain))
+            .filter(
+                payment_addresses::bip353
+                    .is_not_null()
+                    .or(diesel::dsl::exists(
+                        payment_address_destinations::table
+                            .filter(
+                                payment_address_destinations::payment_address_id
+                                    .eq(payment_addresses::id),
+                            )
+                            .filter(payment_address_destinations::destination.like("lno1%")),
+                    )),
+            )
+            .filter(
+                payment_addresses::expires_at
+                    .is_null()
+                    .or(payment_addresses::expires_at.gt(SystemTime::now())),
+            )
+            .order(payment_addresses::username.asc())
+            .load::<PaymentAddressEntry>(&mut conn)?
+            .into_iter()
+            .map(|entry| load_payment_address(&mut conn, entry))
+            .collect()
+    }
+
+    async fn update_authentication_token(
         &self,
         domain: &str,
         username: &str,
-        token: &str,
+        authentication_token: &str,
     ) -> Result<()> {
-        use diesel::prelude::*;
+        let mut conn = self.pool.get()?;
+
+        let updated = diesel::update(
+            payment_addresses::table
+                .filter(payment_addresses::domain.eq(domain))
+                .filter(payment_addresses::username.eq(username)),
+        )
+        .set((
+            payment_addresses::authentication_token.eq(authentication_token),
+            payment_addresses::updated_at.eq(SystemTime::now()),
+        ))
+        .execute(&mut conn)?;
+
+        if updated == 0 {
+            bail!("Unknown payment address {username}@{domain}");
+        }
 
+        Ok(())
+    }
+
+    async fn renew_payment_address(
+        &self,
+        domain: &str,
+        username: &str,
+        expires_at: SystemTime,
+    ) -> Result<()> {
         let mut conn = self.pool.get()?;
 
-        let record: Option<PaymentAddressEntry> = payment_addresses::table
-            .filter(payment_addresses::domain.eq(domain))
-            .filter(payment_addresses::username.eq(username))
-            .first::<PaymentAddressEntry>(&mut conn)
-            .optional()?;
-        match record {
-            None => Ok(()),
-            Some(entry) => {
-                if entry.authentication_token != token {
-                    bail!("Invalid authentication token for payment address {username}@{domain}");
-                }
-
-                diesel::delete(
-                    payment_addresses::table
-                        .filter(payment_addresses::domain.eq(domain))
-                        .filter(payment_addresses::username.eq(username))
-                )
-                .execute(&mut conn)?;
+        let updated = diesel::update(
+            payment_addresses::table
+                .filter(payment_addresses::domain.eq(domain))
+                .filter(payment_addresses::username.eq(username)),
+        )
+        .set((
+            payment_addresses::expires_at.eq(Some(expires_at)),
+            payment_addresses::updated_at.eq(SystemTime::now()),
+        ))
+        .execute(&mut conn)?;
 
-                Ok(())
-            }
+        if updated == 0 {
+            bail!("Unknown payment address {username}@{domain}");
         }
+
+        Ok(())
     }
-}
 
-diesel::table! {
-    payment_addresses (id) {
-        id -> Integer,
-        username -> VarChar,
-        domain -> VarChar,
-        lnurl -> Text,
-        authentication_token -> VarChar,
-        created_at -> Timestamp,
-        updated_at -> Timestamp,
+    async fn add_renewal_invoice(
+        &self,
+        payment_address_id: i32,
+        payment_hash: &str,
+        amount_msat: u64,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(renewal_invoices::table)
+            .values((
+                renewal_invoices::payment_hash.eq(payment_hash),
+                renewal_invoices::payment_address_id.eq(payment_address_id),
+                renewal_invoices::amount_msat.eq(i64::try_from(amount_msat)?),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
     }
-}
 
-/// Lnaddress table entry
-#[derive(Queryable)]
-struct PaymentAddressEntry {
-    _id: i32,
-    username: String,
-    domain: String,
-    lnurl: String,
-    authentication_token: String,
-    created_at: SystemTime,
-    updated_at: SystemTime,
-}
+    async fn redeem_renewal_invoice(
+        &self,
+        payment_address_id: i32,
+        payment_hash: &str,
+        lifetime: Duration,
+        now: SystemTime,
+    ) -> Result<Option<SystemTime>> {
+        let mut conn = self.pool.get()?;
 
-impl From<PaymentAddressEntry> for PaymentAddress {
-    fn from(entry: PaymentAddressEntry) -> Self {
-        Self {
-            username: entry.username,
-            domain: entry.domain,
-            destination: DestinationPaymentAddress::from_str(&entry.lnurl).expect("Invalid lnurl"),
-            authentication_token: entry.authentication_token,
-            created_at: entry.created_at,
-            updated_at: entry.updated_at,
+        conn.transaction(|conn| {
+            let redeemed = diesel::update(
+                renewal_invoices::table
+                    .filter(renewal_invoices::payment_hash.eq(payment_hash))
+                    .filter(renewal_invoices::payment_address_id.eq(payment_address_id))
+                    .filter(renewal_invoices::redeemed_at.is_null()),
+            )
+            .set(renewal_invoices::redeemed_at.eq(Some(now)))
+            .execute(conn)?;
+            if redeemed == 0 {
+                return Ok(None);
+            }
+
+            // Locked so that concurrent renewals each extend the expiry time
+            let expires_at = payment_addresses::table
+                .find(payment_address_id)
+                .select(payment_addresses::expires_at)
+                .for_update()
+                .first::<Option<SystemTime>>(conn)?;
+            let expires_at = renewed_expiry(expires_at, now, lifetime);
+            diesel::update(payment_addresses::table.find(payment_address_id))
+                .set((
+                    payment_addresses::expires_at.eq(Some(expires_at)),
+                    payment_addresses::updated_at.eq(now),
+                ))
+                .execute(conn)?;
+
+            Ok(Some(expires_at))
+        })
+    }
+
+    async fn remove_expired_payment_addresses(
+        &self,
+        now: SystemTime,
+    ) -> Result<Vec<PaymentAddress>> {
+        let mut conn = self.pool.get()?;
+
+        conn.transaction(|conn| {
+            let removed = payment_addresses::table
+                .filter(payment_addresses::expires_at.le(now))
+                .load::<PaymentAddressEntry>(conn)?
+                .into_iter()
+                .map(|entry| load_payment_address(conn, entry))
+                .collect::<Result<Vec<_>>>()?;
+
+            diesel::delete(
+                payment_addresses::table
+                    .filter(payment_addresses::id.eq_any(removed.iter().map(|entry| entry.id))),
+            )
+            .execute(conn)?;
+
+            Ok(removed)
+        })
+    }
+
+    async fn add_address_event(&self, event: NewAddressEvent) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(address_events::table)
+            .values((
+                address_events::domain.eq(event.domain),
+                address_events::username.eq(event.username),
+                address_events::kind.eq(event.kind.as_str()),
+                address_events::client_ip.eq(event.client_ip),
+                address_events::user_agent.eq(event.user_agent),
+                address_events::old_destination.eq(event.old_destination),
+                address_events::new_destination.eq(event.new_destination),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn update_payment_address_owner(
+        &self,
+        domain: &str,
+        username: &str,
+        owner_id: Option<i32>,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        let updated = diesel::update(
+            payment_addresses::table
+                .filter(payment_addresses::domain.eq(domain))
+                .filter(payment_addresses::username.eq(username)),
+        )
+        .set((
+            payment_addresses::owner_id.eq(owner_id),
+            payment_addresses::updated_at.eq(SystemTime::now()),
+        ))
+        .execute(&mut conn)?;
+
+        if updated == 0 {
+            bail!("Unknown payment address {username}@{domain}");
         }
+
+        Ok(())
+    }
+
+    async fn list_owner_payment_addresses(&self, owner_id: i32) -> Result<Vec<PaymentAddress>> {
+        let mut conn = self.pool.get()?;
+
+        payment_addresses::table
+            .filter(payment_addresses::owner_id.eq(owner_id))
+            .order((
+                payment_addresses::domain.asc(),
+                payment_addresses::username.asc(),
+            ))
+            .load::<PaymentAddressEntry>(&mut conn)?
+            .into_iter()
+            .map(|entry| load_payment_address(&mut conn, entry))
+            .collect()
+    }
+
+    async fn add_owner(&self) -> Result<Owner> {
+        let mut conn = self.pool.get()?;
+
+        let entry = diesel::insert_into(owners::table)
+            .default_values()
+            .get_result::<OwnerEntry>(&mut conn)?;
+
+        Ok(entry.into())
+    }
+
+    async fn get_owner(&self, owner_id: i32) -> Result<Option<Owner>> {
+        let mut conn = self.pool.get()?;
+
+        Ok(owners::table
+            .find(owner_id)
+            .first::<OwnerEntry>(&mut conn)
+            .optional()?
+            .map(Owner::from))
+    }
+
+    async fn get_or_add_owner_by_linking_key(&self, linking_key: &str) -> Result<Owner> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(owners::table)
+            .values(owners::linking_key.eq(linking_key))
+            .on_conflict(owners::linking_key)
+            .do_nothing()
+            .execute(&mut conn)?;
+        let entry = owners::table
+            .filter(owners::linking_key.eq(linking_key))
+            .first::<OwnerEntry>(&mut conn)?;
+
+        Ok(entry.into())
+    }
+
+    async fn get_or_add_owner_by_nostr_pubkey(&self, pubkey: &str) -> Result<Owner> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(owners::table)
+            .values(owners::nostr_pubkey.eq(pubkey))
+            .on_conflict(owners::nostr_pubkey)
+            .do_nothing()
+            .execute(&mut conn)?;
+        let entry = owners::table
+            .filter(owners::nostr_pubkey.eq(pubkey))
+            .first::<OwnerEntry>(&mut conn)?;
+
+        Ok(entry.into())
+    }
+
+    async fn get_owner_by_nostr_pubkey(&self, pubkey: &str) -> Result<Option<Owner>> {
+        let mut conn = self.pool.get()?;
+
+        let entry = owners::table
+            .filter(owners::nostr_pubkey.eq(pubkey))
+            .first::<OwnerEntry>(&mut conn)
+            .optional()?;
+
+        Ok(entry.map(Into::into))
+    }
+
+    async fn add_auth_challenge(&self, k1: &str, expired_before: SystemTime) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(
+            auth_challenges::table.filter(auth_challenges::created_at.lt(expired_before)),
+        )
+        .execute(&mut conn)?;
+        diesel::insert_into(auth_challenges::table)
+            .values(auth_challenges::k1.eq(k1))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn sign_auth_challenge(
+        &self,
+        k1: &str,
+        linking_key: &str,
+        created_after: SystemTime,
+    ) -> Result<bool> {
+        let mut conn = self.pool.get()?;
+
+        let signed = diesel::update(
+            auth_challenges::table
+                .filter(auth_challenges::k1.eq(k1))
+                .filter(auth_challenges::linking_key.is_null())
+                .filter(auth_challenges::created_at.gt(created_after)),
+        )
+        .set(auth_challenges::linking_key.eq(linking_key))
+        .execute(&mut conn)?;
+
+        Ok(signed > 0)
+    }
+
+    async fn take_signed_auth_challenge(
+        &self,
+        k1: &str,
+        created_after: SystemTime,
+    ) -> Result<Option<String>> {
+        let mut conn = self.pool.get()?;
+
+        let linking_key = diesel::delete(
+            auth_challenges::table
+                .filter(auth_challenges::k1.eq(k1))
+                .filter(auth_challenges::linking_key.is_not_null())
+                .filter(auth_challenges::created_at.gt(created_after)),
+        )
+        .returning(auth_challenges::linking_key)
+        .get_result::<Option<String>>(&mut conn)
+        .optional()?;
+
+        Ok(linking_key.flatten())
+    }
+
+    async fn add_pow_challenge(&self, nonce: &str, expired_before: SystemTime) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(pow_challenges::table.filter(pow_challenges::created_at.lt(expired_before)))
+            .execute(&mut conn)?;
+        diesel::insert_into(pow_challenges::table)
+            .values(pow_challenges::nonce.eq(nonce))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn take_pow_challenge(&self, nonce: &str, created_after: SystemTime) -> Result<bool> {
+        let mut conn = self.pool.get()?;
+
+        let taken = diesel::delete(
+            pow_challenges::table
+                .filter(pow_challenges::nonce.eq(nonce))
+                .filter(pow_challenges::created_at.gt(created_after)),
+        )
+        .execute(&mut conn)?;
+
+        Ok(taken > 0)
+    }
+
+    async fn add_owner_session(
+        &self,
+        token: &str,
+        owner_id: i32,
+        expires_at: SystemTime,
+        now: SystemTime,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(owner_sessions::table.filter(owner_sessions::expires_at.le(now)))
+            .execute(&mut conn)?;
+        diesel::insert_into(owner_sessions::table)
+            .values((
+                owner_sessions::token.eq(token),
+                owner_sessions::owner_id.eq(owner_id),
+                owner_sessions::expires_at.eq(expires_at),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn get_owner_session(&self, token: &str, now: SystemTime) -> Result<Option<Owner>> {
+        let mut conn = self.pool.get()?;
+
+        Ok(owner_sessions::table
+            .inner_join(owners::table)
+            .filter(owner_sessions::token.eq(token))
+            .filter(owner_sessions::expires_at.gt(now))
+            .select(owners::all_columns)
+            .first::<OwnerEntry>(&mut conn)
+            .optional()?
+            .map(Owner::from))
+    }
+
+    async fn remove_owner_session(&self, token: &str) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(owner_sessions::table.find(token)).execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn list_address_events(&self, filter: AddressEventFilter) -> Result<Vec<AddressEvent>> {
+        let mut conn = self.pool.get()?;
+
+        let mut query = address_events::table.into_boxed();
+        if let Some(domain) = filter.domain {
+            query = query.filter(address_events::domain.eq(domain));
+        }
+        if let Some(username) = filter.username {
+            query = query.filter(address_events::username.eq(username));
+        }
+        if let Some(client_ip) = filter.client_ip {
+            query = query.filter(address_events::client_ip.eq(client_ip));
+        }
+
+        query
+            .order(address_events::id.desc())
+            .limit(filter.limit)
+            .offset(filter.offset)
+            .load::<AddressEventEntry>(&mut conn)?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+
+    async fn list_payment_addresses(
+        &self,
+        filter: PaymentAddressFilter,
+    ) -> Result<(Vec<PaymentAddress>, i64)> {
+        let mut conn = self.pool.get()?;
+
+        let total = filtered_payment_addresses(&filter)
+            .count()
+            .get_result::<i64>(&mut conn)?;
+        let addresses = filtered_payment_addresses(&filter)
+            .order((
+                payment_addresses::domain.asc(),
+                payment_addresses::username.asc(),
+            ))
+            .limit(filter.limit)
+            .offset(filter.offset)
+            .load::<PaymentAddressEntry>(&mut conn)?
+            .into_iter()
+            .map(|entry| load_payment_address(&mut conn, entry))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((addresses, total))
+    }
+
+    async fn get_payment_address_stats(
+        &self,
+        payment_address: &PaymentAddress,
+    ) -> Result<PaymentAddressStats> {
+        use diesel::dsl::{count_star, sql};
+        use diesel::sql_types::BigInt;
+
+        let mut conn = self.pool.get()?;
+
+        let (invoices, last_invoice_at) = invoices::table
+            .filter(invoices::payment_address_id.eq(payment_address.id))
+            .select((count_star(), diesel::dsl::max(invoices::created_at)))
+            .get_result::<(i64, Option<SystemTime>)>(&mut conn)?;
+        let (settled_invoices, settled_msat) = invoices::table
+            .filter(invoices::payment_address_id.eq(payment_address.id))
+            .filter(invoices::settled.eq(true))
+            .select((
+                count_star(),
+                sql::<BigInt>("COALESCE(SUM(amount_msat), 0)::BIGINT"),
+            ))
+            .get_result::<(i64, i64)>(&mut conn)?;
+        let comments = payment_comments::table
+            .filter(payment_comments::payment_address_id.eq(payment_address.id))
+            .count()
+            .get_result::<i64>(&mut conn)?;
+        let (events, last_event_at) = address_events::table
+            .filter(address_events::domain.eq(&payment_address.domain))
+            .filter(address_events::username.eq(&payment_address.username))
+            .select((count_star(), diesel::dsl::max(address_events::created_at)))
+            .get_result::<(i64, Option<SystemTime>)>(&mut conn)?;
+
+        Ok(PaymentAddressStats {
+            invoices,
+            settled_invoices,
+            settled_msat,
+            last_invoice_at,
+            comments,
+            events,
+            last_event_at,
+        })
+    }
+
+    async fn add_reserved_name(
+        &self,
+        domain: &str,
+        username: &str,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(reserved_names::table)
+            .values((
+                reserved_names::domain.eq(domain),
+                reserved_names::username.eq(username),
+                reserved_names::reason.eq(reason),
+            ))
+            .on_conflict((reserved_names::domain, reserved_names::username))
+            .do_update()
+            .set(reserved_names::reason.eq(reason))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn remove_reserved_name(&self, domain: &str, username: &str) -> Result<bool> {
+        let mut conn = self.pool.get()?;
+
+        let removed =
+            diesel::delete(reserved_names::table.find((domain, username))).execute(&mut conn)?;
+
+        Ok(removed > 0)
+    }
+
+    async fn list_reserved_names(&self, domain: Option<&str>) -> Result<Vec<ReservedName>> {
+        let mut conn = self.pool.get()?;
+
+        let mut query = reserved_names::table.into_boxed();
+        if let Some(domain) = domain {
+            query = query.filter(reserved_names::domain.eq(domain));
+        }
+
+        Ok(query
+            .order((reserved_names::domain.asc(), reserved_names::username.asc()))
+            .load::<(String, String, Option<String>, SystemTime)>(&mut conn)?
+            .into_iter()
+            .map(|(domain, username, reason, created_at)| ReservedName {
+                domain,
+                username,
+                reason,
+                created_at,
+            })
+            .collect())
+    }
+
+    async fn is_name_reserved(&self, domain: &str, username: &str) -> Result<bool> {
+        let mut conn = self.pool.get()?;
+
+        Ok(diesel::select(diesel::dsl::exists(
+            reserved_names::table.find((domain, username)),
+        ))
+        .get_result::<bool>(&mut conn)?)
+    }
+
+    async fn update_payment_address_status(
+        &self,
+        domain: &str,
+        username: &str,
+        status: AddressStatus,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        let updated = diesel::update(
+            payment_addresses::table
+                .filter(payment_addresses::domain.eq(domain))
+                .filter(payment_addresses::username.eq(username)),
+        )
+        .set((
+            payment_addresses::status.eq(status.as_str()),
+            payment_addresses::updated_at.eq(SystemTime::now()),
+        ))
+        .execute(&mut conn)?;
+
+        if updated == 0 {
+            bail!("Unknown payment address {username}@{domain}");
+        }
+
+        Ok(())
+    }
+
+    async fn add_address_report(&self, report: NewAddressReport) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::insert_into(address_reports::table)
+            .values((
+                address_reports::payment_address_id.eq(report.payment_address_id),
+                address_reports::reason.eq(report.reason),
+                address_reports::contact.eq(report.contact),
+                address_reports::client_ip.eq(report.client_ip),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn count_address_reporters(&self, payment_address_id: i32) -> Result<i64> {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+
+        let mut conn = self.pool.get()?;
+
+        // Reports without client IP can't be attributed and count individually
+        Ok(address_reports::table
+            .filter(address_reports::payment_address_id.eq(payment_address_id))
+            .filter(address_reports::resolved_at.is_null())
+            .select(sql::<BigInt>(
+                "COUNT(DISTINCT COALESCE(client_ip, id::TEXT))",
+            ))
+            .get_result::<i64>(&mut conn)?)
+    }
+
+    async fn list_open_address_reports(&self) -> Result<Vec<AddressReport>> {
+        let mut conn = self.pool.get()?;
+
+        Ok(address_reports::table
+            .inner_join(payment_addresses::table)
+            .filter(address_reports::resolved_at.is_null())
+            .order(address_reports::created_at.asc())
+            .select((
+                address_reports::id,
+                payment_addresses::domain,
+                payment_addresses::username,
+                address_reports::reason,
+                address_reports::contact,
+                address_reports::client_ip,
+                address_reports::created_at,
+            ))
+            .load::<(
+                i32,
+                String,
+                String,
+                String,
+                Option<String>,
+                Option<String>,
+                SystemTime,
+            )>(&mut conn)?
+            .into_iter()
+            .map(
+                |(id, domain, username, reason, contact, client_ip, created_at)| AddressReport {
+                    id,
+                    domain,
+                    username,
+                    reason,
+                    contact,
+                    client_ip,
+                    created_at,
+                },
+            )
+            .collect())
+    }
+
+    async fn resolve_address_reports(&self, payment_address_id: i32) -> Result<usize> {
+        let mut conn = self.pool.get()?;
+
+        Ok(diesel::update(
+            address_reports::table
+                .filter(address_reports::payment_address_id.eq(payment_address_id))
+                .filter(address_reports::resolved_at.is_null()),
+        )
+        .set(address_reports::resolved_at.eq(SystemTime::now()))
+        .execute(&mut conn)?)
+    }
+
+    async fn get_payment_address_by_id(&self, id: i32) -> Result<Option<PaymentAddress>> {
+        let mut conn = self.pool.get()?;
+
+        payment_addresses::table
+            .find(id)
+            .first::<PaymentAddressEntry>(&mut conn)
+            .optional()?
+            .map(|entry| load_payment_address(&mut conn, entry))
+            .transpose()
+    }
+
+    async fn update_payment_address_webhook(
+        &self,
+        domain: &str,
+        username: &str,
+        webhook: Option<&AddressWebhook>,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        let updated = diesel::update(
+            payment_addresses::table
+                .filter(payment_addresses::domain.eq(domain))
+                .filter(payment_addresses::username.eq(username)),
+        )
+        .set((
+            payment_addresses::webhook_url.eq(webhook.map(|webhook| &webhook.url)),
+            payment_addresses::webhook_secret.eq(webhook.map(|webhook| &webhook.secret)),
+            payment_addresses::updated_at.eq(SystemTime::now()),
+        ))
+        .execute(&mut conn)?;
+
+        if updated == 0 {
+            bail!("Unknown payment address {username}@{domain}");
+        }
+
+        Ok(())
+    }
+
+    async fn add_webhook_events(&self, events: Vec<NewWebhookEvent>) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        let rows = events
+            .into_iter()
+            .map(|event| {
+                (
+                    webhook_outbox::payment_address_id.eq(event.payment_address_id),
+                    webhook_outbox::url.eq(event.url),
+                    webhook_outbox::secret.eq(event.secret),
+                    webhook_outbox::event_id.eq(event.event_id),
+                    webhook_outbox::event.eq(event.event),
+                    webhook_outbox::payload.eq(event.payload),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(webhook_outbox::table)
+            .values(rows)
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn list_due_webhook_events(
+        &self,
+        now: SystemTime,
+        limit: i64,
+    ) -> Result<Vec<WebhookEvent>> {
+        let mut conn = self.pool.get()?;
+
+        webhook_outbox::table
+            .filter(webhook_outbox::next_attempt_at.le(now))
+            .order(webhook_outbox::id.asc())
+            .limit(limit)
+            .load::<WebhookEventEntry>(&mut conn)?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+
+    async fn remove_webhook_event(&self, id: i32) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(webhook_outbox::table.find(id)).execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn retry_webhook_event(&self, id: i32, next_attempt_at: SystemTime) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::update(webhook_outbox::table.find(id))
+            .set((
+                webhook_outbox::attempts.eq(webhook_outbox::attempts + 1),
+                webhook_outbox::next_attempt_at.eq(next_attempt_at),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn add_webhook_delivery(
+        &self,
+        delivery: NewWebhookDelivery,
+        expired_before: SystemTime,
+    ) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(
+            webhook_deliveries::table.filter(webhook_deliveries::created_at.lt(expired_before)),
+        )
+        .execute(&mut conn)?;
+        diesel::insert_into(webhook_deliveries::table)
+            .values((
+                webhook_deliveries::payment_address_id.eq(delivery.payment_address_id),
+                webhook_deliveries::url.eq(delivery.url),
+                webhook_deliveries::event_id.eq(delivery.event_id),
+                webhook_deliveries::event.eq(delivery.event),
+                webhook_deliveries::attempt.eq(i32::try_from(delivery.attempt)?),
+                webhook_deliveries::status_code.eq(delivery.status_code.map(i32::from)),
+                webhook_deliveries::error.eq(delivery.error),
+                webhook_deliveries::delivered.eq(delivery.delivered),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        payment_address_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let mut conn = self.pool.get()?;
+
+        let mut query = webhook_deliveries::table.into_boxed();
+        if let Some(payment_address_id) = payment_address_id {
+            query = query.filter(webhook_deliveries::payment_address_id.eq(payment_address_id));
+        }
+
+        query
+            .order(webhook_deliveries::id.desc())
+            .limit(limit)
+            .load::<WebhookDeliveryEntry>(&mut conn)?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+}
+
+/// Payment addresses matching the filter, built twice for counting and loading a page
+fn filtered_payment_addresses(
+    filter: &PaymentAddressFilter,
+) -> payment_addresses::BoxedQuery<'static, diesel::pg::Pg> {
+    let mut query = payment_addresses::table.into_boxed();
+    if let Some(domain) = &filter.domain {
+        query = query.filter(payment_addresses::domain.eq(domain.clone()));
+    }
+    if let Some(owner_id) = filter.owner_id {
+        query = query.filter(payment_addresses::owner_id.eq(owner_id));
+    }
+    if let Some(search) = &filter.search {
+        let pattern = format!(
+            "%{}%",
+            search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let matching_destinations = payment_address_destinations::table
+            .filter(payment_address_destinations::destination.ilike(pattern.clone()))
+            .select(payment_address_destinations::payment_address_id);
+        query = query.filter(
+            payment_addresses::username
+                .ilike(pattern)
+                .or(payment_addresses::id.eq_any(matching_destinations)),
+        );
+    }
+    query
+}
+
+diesel::table! {
+    payment_addresses (id) {
+        id -> Integer,
+        username -> VarChar,
+        domain -> VarChar,
+        authentication_token -> VarChar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        routing_policy -> VarChar,
+        store_comments -> Bool,
+        payer_data -> Nullable<Text>,
+        success_action -> Nullable<Text>,
+        nostr -> Nullable<Text>,
+        bip353 -> Nullable<Text>,
+        owner_id -> Nullable<Integer>,
+        status -> VarChar,
+        webhook_url -> Nullable<Text>,
+        webhook_secret -> Nullable<VarChar>,
+    }
+}
+
+diesel::table! {
+    payment_address_destinations (id) {
+        id -> Integer,
+        payment_address_id -> Integer,
+        position -> Integer,
+        destination -> Text,
+        weight -> Integer,
+        selection_count -> BigInt,
+        last_selected_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    amount_rules (id) {
+        id -> Integer,
+        payment_address_id -> Integer,
+        position -> Integer,
+        min_msat -> Nullable<BigInt>,
+        max_msat -> Nullable<BigInt>,
+        destination_position -> Integer,
+    }
+}
+
+diesel::table! {
+    payment_comments (id) {
+        id -> Integer,
+        payment_address_id -> Integer,
+        amount_msat -> BigInt,
+        comment -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    payment_payer_data (id) {
+        id -> Integer,
+        payment_address_id -> Integer,
+        amount_msat -> BigInt,
+        payer_data -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    invoices (id) {
+        id -> Integer,
+        payment_address_id -> Integer,
+        verify_id -> VarChar,
+        pr -> Text,
+        amount_msat -> BigInt,
+        upstream_verify -> Nullable<Text>,
+        settled -> Bool,
+        preimage -> Nullable<VarChar>,
+        created_at -> Timestamp,
+        zap_request -> Nullable<Text>,
+        zap_receipt_published_at -> Nullable<Timestamp>,
+        bolt12_offer -> Nullable<Text>,
+        payment_hash -> Nullable<VarChar>,
+        payer_note -> Nullable<Text>,
+        forwarded_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    address_events (id) {
+        id -> Integer,
+        domain -> VarChar,
+        username -> VarChar,
+        kind -> VarChar,
+        client_ip -> Nullable<VarChar>,
+        user_agent -> Nullable<Text>,
+        old_destination -> Nullable<Text>,
+        new_destination -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    address_reports (id) {
+        id -> Integer,
+        payment_address_id -> Integer,
+        reason -> Text,
+        contact -> Nullable<VarChar>,
+        client_ip -> Nullable<VarChar>,
+        created_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    auth_challenges (k1) {
+        k1 -> VarChar,
+        linking_key -> Nullable<VarChar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    webhook_outbox (id) {
+        id -> Integer,
+        payment_address_id -> Nullable<Integer>,
+        url -> Text,
+        secret -> Nullable<VarChar>,
+        event_id -> VarChar,
+        event -> VarChar,
+        payload -> Text,
+        attempts -> Integer,
+        next_attempt_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Integer,
+        payment_address_id -> Nullable<Integer>,
+        url -> Text,
+        event_id -> VarChar,
+        event -> VarChar,
+        attempt -> Integer,
+        status_code -> Nullable<Integer>,
+        error -> Nullable<Text>,
+        delivered -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    renewal_invoices (payment_hash) {
+        payment_hash -> VarChar,
+        payment_address_id -> Integer,
+        amount_msat -> BigInt,
+        created_at -> Timestamp,
+        redeemed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    pow_challenges (nonce) {
+        nonce -> VarChar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    owner_sessions (token) {
+        token -> VarChar,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        owner_id -> Integer,
+    }
+}
+
+diesel::table! {
+    owners (id) {
+        id -> Integer,
+        linking_key -> Nullable<VarChar>,
+        created_at -> Timestamp,
+        nostr_pubkey -> Nullable<VarChar>,
+    }
+}
+
+diesel::table! {
+    reserved_names (domain, username) {
+        domain -> VarChar,
+        username -> VarChar,
+        reason -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(owner_sessions -> owners (owner_id));
+diesel::allow_tables_to_appear_in_same_query!(owner_sessions, owners);
+
+diesel::joinable!(address_reports -> payment_addresses (payment_address_id));
+diesel::allow_tables_to_appear_in_same_query!(address_reports, payment_addresses);
+
+diesel::allow_tables_to_appear_in_same_query!(payment_addresses, payment_address_destinations);
+diesel::allow_tables_to_appear_in_same_query!(invoices, payment_addresses);
+
+/// Lnaddress table entry
+#[derive(Queryable)]
+struct PaymentAddressEntry {
+    id: i32,
+    username: String,
+    domain: String,
+    authentication_token: String,
+    created_at: SystemTime,
+    updated_at: SystemTime,
+    expires_at: Option<SystemTime>,
+    routing_policy: String,
+    store_comments: bool,
+    payer_data: Option<String>,
+    success_action: Option<String>,
+    nostr: Option<String>,
+    bip353: Option<String>,
+    owner_id: Option<i32>,
+    status: String,
+    webhook_url: Option<String>,
+    webhook_secret: Option<String>,
+}
+
+/// Owners table entry
+#[derive(Queryable)]
+struct OwnerEntry {
+    id: i32,
+    linking_key: Option<String>,
+    created_at: SystemTime,
+    nostr_pubkey: Option<String>,
+}
+
+impl From<OwnerEntry> for Owner {
+    fn from(entry: OwnerEntry) -> Self {
+        Owner {
+            id: entry.id,
+            linking_key: entry.linking_key,
+            created_at: entry.created_at,
+            nostr_pubkey: entry.nostr_pubkey,
+        }
+    }
+}
+
+/// Invoice table entry
+#[derive(Queryable)]
+struct InvoiceEntry {
+    _id: i32,
+    payment_address_id: i32,
+    verify_id: String,
+    pr: String,
+    amount_msat: i64,
+    upstream_verify: Option<String>,
+    settled: bool,
+    preimage: Option<String>,
+    created_at: SystemTime,
+    zap_request: Option<String>,
+    zap_receipt_published_at: Option<SystemTime>,
+    bolt12_offer: Option<String>,
+    payment_hash: Option<String>,
+    payer_note: Option<String>,
+    forwarded_at: Option<SystemTime>,
+}
+
+impl TryFrom<InvoiceEntry> for Invoice {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: InvoiceEntry) -> Result<Self> {
+        let forward = match (entry.bolt12_offer, entry.payment_hash) {
+            (Some(offer), Some(payment_hash)) => Some(OfferForward {
+                offer: Offer::parse(&offer)?,
+                payment_hash,
+                payer_note: entry.payer_note,
+            }),
+            _ => None,
+        };
+
+        Ok(Self {
+            payment_address_id: entry.payment_address_id,
+            verify_id: entry.verify_id,
+            pr: entry.pr,
+            amount_msat: entry.amount_msat.try_into()?,
+            upstream_verify: entry.upstream_verify,
+            settled: entry.settled,
+            preimage: entry.preimage,
+            zap_request: entry.zap_request,
+            zap_receipt_published_at: entry.zap_receipt_published_at,
+            forward,
+            forwarded_at: entry.forwarded_at,
+            created_at: entry.created_at,
+        })
+    }
+}
+
+/// Payment address destination table entry
+#[derive(Queryable)]
+struct DestinationEntry {
+    id: i32,
+    _payment_address_id: i32,
+    _position: i32,
+    destination: String,
+    weight: i32,
+    selection_count: i64,
+    last_selected_at: Option<SystemTime>,
+}
+
+impl TryFrom<DestinationEntry> for AddressDestination {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: DestinationEntry) -> Result<Self> {
+        Ok(Self {
+            id: entry.id,
+            destination: DestinationPaymentAddress::from_stored(&entry.destination)?,
+            weight: entry.weight.try_into()?,
+            selection_count: entry.selection_count.try_into()?,
+            last_selected_at: entry.last_selected_at,
+        })
+    }
+}
+
+/// Amount rule table entry
+#[derive(Queryable)]
+struct AmountRuleEntry {
+    _id: i32,
+    _payment_address_id: i32,
+    _position: i32,
+    min_msat: Option<i64>,
+    max_msat: Option<i64>,
+    destination_position: i32,
+}
+
+impl TryFrom<AmountRuleEntry> for AmountRule {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: AmountRuleEntry) -> Result<Self> {
+        Ok(Self {
+            min_msat: entry.min_msat.map(TryInto::try_into).transpose()?,
+            max_msat: entry.max_msat.map(TryInto::try_into).transpose()?,
+            destination_position: entry.destination_position.try_into()?,
+        })
+    }
+}
+
+/// Loads the destinations and amount rules of a payment address table entry
+fn load_payment_address(
+    conn: &mut PgConnection,
+    entry: PaymentAddressEntry,
+) -> Result<PaymentAddress> {
+    let destinations = payment_address_destinations::table
+        .filter(payment_address_destinations::payment_address_id.eq(entry.id))
+        .order(payment_address_destinations::position.asc())
+        .load::<DestinationEntry>(conn)?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>>>()?;
+
+    let amount_rules = amount_rules::table
+        .filter(amount_rules::payment_address_id.eq(entry.id))
+        .order(amount_rules::position.asc())
+        .load::<AmountRuleEntry>(conn)?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(PaymentAddress {
+        id: entry.id,
+        username: entry.username,
+        domain: entry.domain,
+        destinations,
+        routing_policy: RoutingPolicy::from_str(&entry.routing_policy)?,
+        amount_rules,
+        authentication_token: entry.authentication_token,
+        created_at: entry.created_at,
+        updated_at: entry.updated_at,
+        expires_at: entry.expires_at,
+        settings: AddressSettings {
+            store_comments: entry.store_comments,
+            payer_data: entry
+                .payer_data
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            success_action: entry
+                .success_action
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            nostr: entry
+                .nostr
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            bip353: entry
+                .bip353
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+        },
+        owner_id: entry.owner_id,
+        status: AddressStatus::from_str(&entry.status)?,
+        webhook: match (entry.webhook_url, entry.webhook_secret) {
+            (Some(url), Some(secret)) => Some(AddressWebhook { url, secret }),
+            _ => None,
+        },
+    })
+}
+
+/// Replaces the settings of a payment address
+fn update_settings(
+    conn: &mut PgConnection,
+    domain: &str,
+    username: &str,
+    settings: AddressSettings,
+) -> Result<()> {
+    let updated = diesel::update(
+        payment_addresses::table
+            .filter(payment_addresses::domain.eq(domain))
+            .filter(payment_addresses::username.eq(username)),
+    )
+    .set((
+        payment_addresses::store_comments.eq(settings.store_comments),
+        payment_addresses::payer_data.eq(settings
+            .payer_data
+            .map(|request| serde_json::to_string(&request))
+            .transpose()?),
+        payment_addresses::success_action.eq(settings
+            .success_action
+            .map(|action| serde_json::to_string(&action))
+            .transpose()?),
+        payment_addresses::nostr.eq(settings
+            .nostr
+            .map(|identity| serde_json::to_string(&identity))
+            .transpose()?),
+        payment_addresses::bip353.eq(settings
+            .bip353
+            .map(|instructions| serde_json::to_string(&instructions))
+            .transpose()?),
+        payment_addresses::updated_at.eq(SystemTime::now()),
+    ))
+    .execute(conn)?;
+
+    if updated == 0 {
+        bail!("Unknown payment address {username}@{domain}");
+    }
+
+    Ok(())
+}
+
+fn insert_routing(
+    conn: &mut PgConnection,
+    payment_address_id: i32,
+    routing: &Routing,
+) -> Result<()> {
+    let destinations = routing
+        .destinations
+        .iter()
+        .enumerate()
+        .map(|(position, destination)| {
+            (
+                payment_address_destinations::payment_address_id.eq(payment_address_id),
+                payment_address_destinations::position.eq(position as i32),
+                payment_address_destinations::destination.eq(destination.destination.to_string()),
+                payment_address_destinations::weight.eq(destination.weight as i32),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(payment_address_destinations::table)
+        .values(destinations)
+        .execute(conn)?;
+
+    let rules = routing
+        .amount_rules
+        .iter()
+        .enumerate()
+        .map(|(position, rule)| {
+            Ok((
+                amount_rules::payment_address_id.eq(payment_address_id),
+                amount_rules::position.eq(position as i32),
+                amount_rules::min_msat.eq(rule.min_msat.map(i64::try_from).transpose()?),
+                amount_rules::max_msat.eq(rule.max_msat.map(i64::try_from).transpose()?),
+                amount_rules::destination_position.eq(i32::try_from(rule.destination_position)?),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    diesel::insert_into(amount_rules::table)
+        .values(rules)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Address event log table entry
+#[derive(Queryable)]
+struct AddressEventEntry {
+    id: i32,
+    domain: String,
+    username: String,
+    kind: String,
+    client_ip: Option<String>,
+    user_agent: Option<String>,
+    old_destination: Option<String>,
+    new_destination: Option<String>,
+    created_at: SystemTime,
+}
+
+impl TryFrom<AddressEventEntry> for AddressEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: AddressEventEntry) -> Result<Self> {
+        Ok(Self {
+            id: entry.id,
+            domain: entry.domain,
+            username: entry.username,
+            kind: AddressEventKind::from_str(&entry.kind)?,
+            client_ip: entry.client_ip,
+            user_agent: entry.user_agent,
+            old_destination: entry.old_destination,
+            new_destination: entry.new_destination,
+            created_at: entry.created_at,
+        })
+    }
+}
+
+/// Webhook outbox table entry
+#[derive(Queryable)]
+struct WebhookEventEntry {
+    id: i32,
+    payment_address_id: Option<i32>,
+    url: String,
+    secret: Option<String>,
+    event_id: String,
+    event: String,
+    payload: String,
+    attempts: i32,
+    _next_attempt_at: SystemTime,
+    created_at: SystemTime,
+}
+
+impl TryFrom<WebhookEventEntry> for WebhookEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: WebhookEventEntry) -> Result<Self> {
+        Ok(Self {
+            id: entry.id,
+            payment_address_id: entry.payment_address_id,
+            url: entry.url,
+            secret: entry.secret,
+            event_id: entry.event_id,
+            event: entry.event,
+            payload: entry.payload,
+            attempts: entry.attempts.try_into()?,
+            created_at: entry.created_at,
+        })
+    }
+}
+
+/// Webhook delivery log table entry
+#[derive(Queryable)]
+struct WebhookDeliveryEntry {
+    id: i32,
+    payment_address_id: Option<i32>,
+    url: String,
+    event_id: String,
+    event: String,
+    attempt: i32,
+    status_code: Option<i32>,
+    error: Option<String>,
+    delivered: bool,
+    created_at: SystemTime,
+}
+
+impl TryFrom<WebhookDeliveryEntry> for WebhookDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: WebhookDeliveryEntry) -> Result<Self> {
+        Ok(Self {
+            id: entry.id,
+            payment_address_id: entry.payment_address_id,
+            url: entry.url,
+            event_id: entry.event_id,
+            event: entry.event,
+            attempt: entry.attempt.try_into()?,
+            status_code: entry.status_code.map(u16::try_from).transpose()?,
+            error: entry.error,
+            delivered: entry.delivered,
+            created_at: entry.created_at,
+        })
     }
 }
 
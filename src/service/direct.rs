@@ -1,30 +1,1258 @@
-use std::{str::FromStr, sync::Arc};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use super::{ILnaddrService, LnaddrService, RegisterResponse};
-use crate::repository::{DestinationPaymentAddress, PaymentAddressRepository};
-use anyhow::{Result, bail};
+use super::{
+    AuthChallenge, BulkAction, BulkResult, Credentials, DestinationHealth, ILnaddrService,
+    InvoiceRequest, InvoiceResponse, LnaddrDetails, LnaddrPage, LnaddrService, OwnerSession,
+    PayManifest, PowChallenge, RegisterResponse, RenewResponse, RenewalInvoice,
+    RotateTokenResponse, RoutingInput, SettingsUpdate, VerifyResponse,
+};
+use crate::bip353::{self, Bip353Record, DnsUpdater};
+use crate::bolt11;
+use crate::bolt12::Offer;
+use crate::client::ClientInfo;
+use crate::config::Config;
+use crate::lnurl_auth;
+use crate::node::{NodeBackend, OfferPayment};
+use crate::nostr::{self, ZapRequest};
+use crate::pow;
+use crate::repository::{
+    AddressDestination, AddressEvent, AddressEventFilter, AddressEventKind, AddressReport,
+    AddressSettings, AddressStatus, AddressWebhook, AmountRule, DestinationPaymentAddress, Invoice,
+    NewAddressEvent, NewAddressReport, NewInvoice, NewWebhookDelivery, NewWebhookEvent,
+    NostrIdentity, OfferForward, Owner, PayerData, PayerDataRequest, PaymentAddress,
+    PaymentAddressFilter, PaymentAddressRepository, PaymentAddressStats, PaymentComment,
+    PaymentInstructions, ReservedName, Routing, RoutingPolicy, SuccessAction, WebhookDelivery,
+    WebhookEvent, WeightedDestination, check_lnurl_url, renewed_expiry,
+};
+use crate::webhook;
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use async_trait::async_trait;
-use lnurl::{LnUrlResponse, pay::PayResponse};
-use rand::distributions::DistString;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::hex::FromHex;
+use rand::distributions::{DistString, Distribution, WeightedIndex};
+use secp256k1::{Keypair, Secp256k1};
+use serde_json::{Map, Value};
+use subtle::ConstantTimeEq;
+use tracing::{info, warn};
+
+/// Maximum number of events returned to owners of an address
+const MAX_OWNER_EVENTS: i64 = 100;
+/// Maximum number of destinations per address
+const MAX_DESTINATIONS: usize = 5;
+/// Maximum routing weight of a single destination
+const MAX_WEIGHT: u32 = 1000;
+/// Maximum number of amount rules per address
+const MAX_AMOUNT_RULES: usize = 10;
+/// Maximum number of comments returned to owners of an address
+const MAX_OWNER_COMMENTS: i64 = 100;
+/// Maximum number of payer data entries returned to owners of an address
+const MAX_OWNER_PAYER_DATA: i64 = 100;
+/// Maximum length of a single LUD-18 payer data field
+const MAX_PAYER_DATA_FIELD_LENGTH: usize = 256;
+/// Maximum length of LUD-09 success action messages and descriptions
+const MAX_SUCCESS_ACTION_TEXT_LENGTH: usize = 144;
+/// Maximum number of relays published via NIP-05
+const MAX_NOSTR_RELAYS: usize = 10;
+/// How long zapped invoices are watched for payment
+const ZAP_RECEIPT_WINDOW: Duration = Duration::from_secs(2 * 60 * 60);
+/// Timeout for publishing a zap receipt to a single relay
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Expiry of invoices issued by the node backend for BOLT12 offer destinations
+const OFFER_INVOICE_EXPIRY: Duration = Duration::from_secs(10 * 60);
+/// Expiry of invoices issued by the node backend for paid renewals
+const RENEWAL_INVOICE_EXPIRY: Duration = Duration::from_secs(60 * 60);
+/// How long paid invoices are retried to be forwarded to their BOLT12 offer
+const OFFER_FORWARD_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+/// Maximum length of the reason given in an abuse report
+const MAX_REPORT_REASON_LENGTH: usize = 2000;
+/// Maximum length of the contact given in an abuse report
+const MAX_REPORT_CONTACT_LENGTH: usize = 256;
+/// Minimum amount accepted for BOLT12 offer destinations without fixed amount
+const OFFER_MIN_SENDABLE_MSAT: u64 = 1000;
+/// Time a wallet has to sign a LNURL-auth challenge and the browser to pick up the login
+const AUTH_CHALLENGE_LIFETIME: Duration = Duration::from_secs(10 * 60);
+/// Webhooks delivered concurrently in one pass over the outbox
+const WEBHOOK_BATCH_SIZE: i64 = 50;
+/// Attempts after which an undeliverable webhook is dropped, spanning about a day and a half
+const WEBHOOK_MAX_ATTEMPTS: u32 = 15;
+/// How long webhook delivery attempts are kept in the delivery log
+const WEBHOOK_LOG_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Maximum number of webhook delivery attempts listed to an owner
+const MAX_OWNER_WEBHOOK_DELIVERIES: i64 = 100;
+/// How long unpaid invoices are checked for payment to send webhooks of paid invoices
+const INVOICE_CHECK_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// Time a registration proof-of-work challenge can be solved in
+const POW_CHALLENGE_LIFETIME: Duration = Duration::from_secs(30 * 60);
 
 pub struct DirectLnaddrService {
     repo: PaymentAddressRepository,
     domains: Vec<String>,
-    client: lnurl::AsyncClient,
+    address_lifetime: Option<Duration>,
+    renewal_price_msat: Option<u64>,
+    owner_session_lifetime: Duration,
+    proxy_callbacks: bool,
+    max_comment_length: u32,
+    trusted_success_action_domains: Vec<String>,
+    nostr_keys: Option<Keypair>,
+    nostr_relays: Vec<String>,
+    bip353_ttl: u32,
+    dns_updater: Option<DnsUpdater>,
+    node: Option<NodeBackend>,
+    bolt12_max_sendable_msat: u64,
+    report_suspend_threshold: u32,
+    report_webhook_url: Option<String>,
+    register_pow_difficulty: u8,
+    webhook_urls: Vec<String>,
+    webhook_secret: Option<String>,
+    http_client: reqwest::Client,
+    /// Client for the endpoints of owners, which may only be public
+    owner_webhook_client: reqwest::Client,
+}
+
+/// Parameters of a payer's callback request that are forwarded to the upstream
+struct CallbackParams<'a> {
+    amount_msat: u64,
+    comment: Option<&'a str>,
+    payer_data: Option<&'a str>,
+    zap_request: Option<&'a str>,
 }
 
 impl DirectLnaddrService {
-    pub fn new(repo: PaymentAddressRepository, domains: Vec<String>) -> Self {
-        Self {
-            repo,
-            domains,
-            client: lnurl::AsyncClient::from_client(reqwest::Client::new()),
+    pub fn new(
+        repo: PaymentAddressRepository,
+        node: Option<NodeBackend>,
+        config: &Config,
+    ) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.upstream_timeout))
+            .build()?;
+        // Redirects aren't followed, they could lead to private addresses
+        let owner_webhook_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.upstream_timeout))
+            .dns_resolver(Arc::new(webhook::PublicResolver))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        let nostr_keys = config
+            .nostr_secret_key
+            .as_deref()
+            .map(|key| Keypair::from_seckey_str(&Secp256k1::new(), key))
+            .transpose()
+            .context("Invalid Nostr secret key")?;
+        let dns_updater = config
+            .dns_update_server
+            .map(|server| {
+                DnsUpdater::new(
+                    server,
+                    config.dns_update_zone.as_deref(),
+                    config.bip353_ttl,
+                    config.dns_tsig_key.as_deref(),
+                )
+            })
+            .transpose()?;
+        for url in &config.webhook_urls {
+            webhook::validate_url(url)?;
         }
+        ensure!(
+            config.renewal_price.is_none() || node.is_some(),
+            "Paid renewals require a node backend to issue invoices"
+        );
+        ensure!(
+            config.renewal_price.is_none() || config.address_lifetime_days.is_some(),
+            "Paid renewals require an address lifetime"
+        );
+        ensure!(
+            config.webhook_urls.is_empty() || config.webhook_secret.is_some(),
+            "A webhook secret is required to sign webhooks to the configured URLs"
+        );
+
+        Ok(Self {
+            repo,
+            domains: config.domains.clone(),
+            address_lifetime: config.address_lifetime(),
+            renewal_price_msat: config.renewal_price.map(sats_to_msat).transpose()?,
+            owner_session_lifetime: config.owner_session_lifetime(),
+            proxy_callbacks: config.proxy_callbacks,
+            max_comment_length: config.max_comment_length,
+            trusted_success_action_domains: config.trusted_success_action_domains.clone(),
+            nostr_keys,
+            nostr_relays: config.nostr_relays.clone(),
+            bip353_ttl: config.bip353_ttl,
+            dns_updater,
+            node,
+            bolt12_max_sendable_msat: sats_to_msat(config.bolt12_max_sendable)?,
+            report_suspend_threshold: config.report_suspend_threshold,
+            report_webhook_url: config.report_webhook_url.clone(),
+            register_pow_difficulty: config.register_pow_difficulty,
+            webhook_urls: config.webhook_urls.clone(),
+            webhook_secret: config.webhook_secret.clone(),
+            http_client,
+            owner_webhook_client,
+        })
     }
 
     pub fn into_dyn(self) -> LnaddrService {
         Arc::new(self)
     }
+
+    /// Fetches a payment address, treating expired addresses as non-existent
+    async fn get_active_payment_address(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<PaymentAddress>> {
+        Ok(self
+            .repo
+            .get_payment_address(domain, username)
+            .await?
+            .filter(|entry| !entry.is_expired(SystemTime::now())))
+    }
+
+    async fn start_owner_session(&self, owner_id: i32) -> Result<OwnerSession> {
+        let now = SystemTime::now();
+        let token = rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+        let expires_at = now + self.owner_session_lifetime;
+        self.repo
+            .add_owner_session(&token, owner_id, expires_at, now)
+            .await?;
+
+        Ok(OwnerSession {
+            token,
+            owner_id,
+            expires_at,
+        })
+    }
+
+    /// Fetches a payment address if the credentials allow managing it
+    async fn authorize(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<PaymentAddress> {
+        let entry = self.repo.get_payment_address(domain, username).await?;
+        match (entry, credentials) {
+            (Some(entry), Credentials::Token(token))
+                if bool::from(
+                    entry
+                        .authentication_token
+                        .as_bytes()
+                        .ct_eq(token.as_bytes()),
+                ) =>
+            {
+                Ok(entry)
+            }
+            (Some(entry), Credentials::Owner(owner_id)) if entry.owner_id == Some(*owner_id) => {
+                Ok(entry)
+            }
+            (Some(entry), Credentials::Admin) => Ok(entry),
+            (_, Credentials::Token(_)) => {
+                bail!("Invalid authentication token for payment address {username}@{domain}")
+            }
+            (_, Credentials::Owner(_)) => {
+                bail!("Payment address {username}@{domain} is not linked to your account")
+            }
+            (None, Credentials::Admin) => bail!("Unknown payment address {username}@{domain}"),
+        }
+    }
+
+    /// Ensures owners don't change addresses the operator suspended, which only the operator
+    /// may modify or remove until they are reinstated
+    fn ensure_not_suspended(entry: &PaymentAddress, credentials: &Credentials) -> Result<()> {
+        ensure!(
+            matches!(credentials, Credentials::Admin) || entry.status != AddressStatus::Suspended,
+            "{}@{} has been suspended by the operator",
+            entry.username,
+            entry.domain
+        );
+        Ok(())
+    }
+
+    fn lnaddr_details(entry: PaymentAddress) -> Result<LnaddrDetails> {
+        let url = Self::lnurlp_url(&entry.domain, &entry.username)?;
+        Ok(LnaddrDetails {
+            lnaddr: format!("{}@{}", entry.username, entry.domain),
+            lnurl: lnurl::lnurl::LnUrl::from_url(url.to_string())
+                .encode()
+                .to_uppercase(),
+            lud17: url.to_string().replacen("https://", "lnurlp://", 1),
+            owner_id: entry.owner_id,
+            status: entry.status,
+            destinations: entry.destinations,
+            routing_policy: entry.routing_policy,
+            amount_rules: entry.amount_rules,
+            settings: entry.settings,
+            created_at: entry.created_at,
+            expires_at: entry.expires_at,
+        })
+    }
+
+    /// Records an event in the address event log and sends its webhooks. Failing to do so is
+    /// logged but doesn't fail the already performed action.
+    async fn record_event(
+        &self,
+        domain: &str,
+        username: &str,
+        kind: AddressEventKind,
+        client: &ClientInfo,
+        old_destinations: Option<String>,
+        new_destinations: Option<String>,
+    ) {
+        let event = NewAddressEvent {
+            domain: domain.to_owned(),
+            username: username.to_owned(),
+            kind,
+            client_ip: client.ip.map(|ip| ip.to_string()),
+            user_agent: client.user_agent.clone(),
+            old_destination: old_destinations,
+            new_destination: new_destinations,
+        };
+        let entry = self
+            .repo
+            .get_payment_address(domain, username)
+            .await
+            .unwrap_or_else(|e| {
+                warn!(error=%e, %domain, %username, "Failed to look up address webhook");
+                None
+            });
+
+        self.store_event(event, entry.as_ref()).await;
+    }
+
+    /// Records the removal of an address, whose webhook is taken from the removed entry
+    async fn record_removal(
+        &self,
+        removed: &PaymentAddress,
+        kind: AddressEventKind,
+        client: &ClientInfo,
+    ) {
+        let event = NewAddressEvent {
+            domain: removed.domain.clone(),
+            username: removed.username.clone(),
+            kind,
+            client_ip: client.ip.map(|ip| ip.to_string()),
+            user_agent: client.user_agent.clone(),
+            old_destination: Some(format_destinations(
+                removed.destinations.iter().map(|d| &d.destination),
+            )),
+            new_destination: None,
+        };
+
+        self.store_event(event, Some(removed)).await;
+    }
+
+    async fn store_event(&self, event: NewAddressEvent, entry: Option<&PaymentAddress>) {
+        let (domain, username, kind) = (event.domain.clone(), event.username.clone(), event.kind);
+        let data = serde_json::json!({
+            "old_destination": event.old_destination,
+            "new_destination": event.new_destination,
+        });
+
+        if let Err(e) = self.repo.add_address_event(event).await {
+            warn!(error=%e, %domain, %username, %kind, "Failed to record address event");
+        }
+        self.queue_webhooks(&format!("address_{kind}"), &domain, &username, entry, data)
+            .await;
+    }
+
+    /// Adds the webhooks of an event to the outbox, for the operator's endpoints and the endpoint
+    /// of the address's owner. Failing to do so is logged but doesn't fail the already performed
+    /// action.
+    async fn queue_webhooks(
+        &self,
+        event: &str,
+        domain: &str,
+        username: &str,
+        entry: Option<&PaymentAddress>,
+        data: Value,
+    ) {
+        let owner_webhook = entry.and_then(|entry| Some((entry.id, entry.webhook.as_ref()?)));
+        if self.webhook_urls.is_empty() && owner_webhook.is_none() {
+            return;
+        }
+
+        let event_id = webhook::generate_event_id();
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let payload = serde_json::json!({
+            "id": event_id,
+            "event": event,
+            "created_at": created_at,
+            "lnaddr": format!("{username}@{domain}"),
+            "data": data,
+        })
+        .to_string();
+        let operator_events = self.webhook_urls.iter().map(|url| NewWebhookEvent {
+            payment_address_id: None,
+            url: url.clone(),
+            secret: None,
+            event_id: event_id.clone(),
+            event: event.to_owned(),
+            payload: payload.clone(),
+        });
+        let owner_event =
+            owner_webhook.map(|(payment_address_id, owner_webhook)| NewWebhookEvent {
+                payment_address_id: Some(payment_address_id),
+                url: owner_webhook.url.clone(),
+                secret: Some(owner_webhook.secret.clone()),
+                event_id: event_id.clone(),
+                event: event.to_owned(),
+                payload: payload.clone(),
+            });
+
+        if let Err(e) = self
+            .repo
+            .add_webhook_events(operator_events.chain(owner_event).collect())
+            .await
+        {
+            warn!(error=%e, %domain, %username, %event, "Failed to queue webhooks");
+        }
+    }
+
+    /// Queues the webhooks of an invoice that was found paid
+    async fn notify_invoice_paid(&self, invoice: &Invoice) {
+        let entry = match self
+            .repo
+            .get_payment_address_by_id(invoice.payment_address_id)
+            .await
+        {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return,
+            Err(e) => {
+                warn!(
+                    error=%e,
+                    verify_id=%invoice.verify_id,
+                    "Failed to look up address of paid invoice",
+                );
+                return;
+            }
+        };
+        let data = serde_json::json!({
+            "amount_msat": invoice.amount_msat,
+            "pr": invoice.pr,
+            "preimage": invoice.preimage,
+        });
+
+        self.queue_webhooks(
+            "invoice_paid",
+            &entry.domain,
+            &entry.username,
+            Some(&entry),
+            data,
+        )
+        .await;
+    }
+
+    /// Posts a webhook to its endpoint, returning the status of the response
+    async fn send_webhook(&self, event: &WebhookEvent) -> Result<reqwest::StatusCode> {
+        let secret = event
+            .secret
+            .as_deref()
+            .or(self.webhook_secret.as_deref())
+            .context("No webhook secret configured")?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let client = match event.payment_address_id {
+            Some(_) => {
+                webhook::check_public_url(&event.url).await?;
+                &self.owner_webhook_client
+            }
+            None => &self.http_client,
+        };
+
+        let response = client
+            .post(&event.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(webhook::EVENT_HEADER, &event.event)
+            .header(webhook::EVENT_ID_HEADER, &event.event_id)
+            .header(webhook::TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                webhook::SIGNATURE_HEADER,
+                webhook::signature(secret, timestamp, &event.payload),
+            )
+            .body(event.payload.clone())
+            .send()
+            .await?;
+
+        Ok(response.status())
+    }
+
+    /// Fetches the LNURL-pay manifest of a single destination. Manifests of BOLT12 offers are
+    /// made up by lnaddrd since offers are paid through the node backend.
+    async fn fetch_manifest(&self, destination: &DestinationPaymentAddress) -> Result<PayManifest> {
+        let url = match destination {
+            DestinationPaymentAddress::Bolt12Offer(offer) => return self.offer_manifest(offer),
+            other => other.url().context("Destination has no LNURL")?,
+        };
+        check_lnurl_url(&url)?;
+        let response = self.get_lnurl_json(&url).await?;
+        match response.get("tag").and_then(Value::as_str) {
+            Some("payRequest") => Ok(serde_json::from_value(response)?),
+            Some(tag) => bail!("Invalid LNURL type: {tag}"),
+            None => bail!("Invalid LNURL response: missing tag"),
+        }
+    }
+
+    /// Manifest for paying a BOLT12 offer through the node backend, its callback is set when the
+    /// manifest is proxied
+    fn offer_manifest(&self, offer: &Offer) -> Result<PayManifest> {
+        ensure!(
+            self.node.is_some(),
+            "Paying BOLT12 offers via LNURL requires a node backend"
+        );
+        ensure!(!offer.is_expired(SystemTime::now()), "BOLT12 offer expired");
+
+        let (min_sendable, max_sendable) = match offer.amount_msat {
+            Some(amount_msat) => (amount_msat, amount_msat),
+            None => (OFFER_MIN_SENDABLE_MSAT, self.bolt12_max_sendable_msat),
+        };
+        let description = offer
+            .description
+            .as_deref()
+            .filter(|description| !description.is_empty())
+            .or(offer.issuer.as_deref())
+            .unwrap_or("Payment to a BOLT12 offer");
+
+        Ok(PayManifest {
+            callback: String::new(),
+            max_sendable,
+            min_sendable,
+            tag: "payRequest".to_owned(),
+            metadata: serde_json::json!([["text/plain", description]]).to_string(),
+            // Comments are forwarded as payer note
+            comment_allowed: Some(self.max_comment_length),
+            payer_data: None,
+            extra: Map::new(),
+        })
+    }
+
+    /// Issues an invoice of the node backend that is forwarded to the offer once paid. The
+    /// invoice commits to the manifest metadata, extended by the payer data as required by
+    /// LUD-18, or to the zap request as required by NIP-57.
+    async fn bridge_invoice(
+        &self,
+        offer: &Offer,
+        manifest: &PayManifest,
+        params: &CallbackParams<'_>,
+    ) -> Result<(InvoiceResponse, OfferForward)> {
+        let node = self.node.as_ref().context("No node backend configured")?;
+        let amount_msat = params.amount_msat;
+        ensure!(
+            (manifest.min_sendable..=manifest.max_sendable).contains(&amount_msat),
+            "Amount {amount_msat} msat is outside the sendable range of the destination"
+        );
+
+        let zap_request = params.zap_request.filter(|_| self.nostr_keys.is_some());
+        let description = invoice_description(&manifest.metadata, zap_request, params.payer_data);
+        let invoice = node
+            .create_invoice(amount_msat, &description, OFFER_INVOICE_EXPIRY)
+            .await?;
+
+        Ok((
+            InvoiceResponse {
+                pr: invoice.bolt11,
+                routes: Vec::new(),
+                extra: Map::new(),
+            },
+            OfferForward {
+                offer: offer.clone(),
+                payment_hash: invoice.payment_hash,
+                payer_note: params.comment.map(ToOwned::to_owned),
+            },
+        ))
+    }
+
+    /// Requests a LNURL endpoint, turning LUD-06 error responses into errors
+    async fn get_lnurl_json(&self, url: &str) -> Result<Value> {
+        let response = self
+            .http_client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        if response.get("status").and_then(Value::as_str) == Some("ERROR") {
+            let reason = response
+                .get("reason")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            bail!("Upstream returned an error: {reason}");
+        }
+
+        Ok(response)
+    }
+
+    fn parse_routing(input: &RoutingInput) -> Result<Routing> {
+        let destinations = input
+            .destinations
+            .iter()
+            .filter(|input| !input.destination.trim().is_empty())
+            .map(|input| {
+                ensure!(
+                    (1..=MAX_WEIGHT).contains(&input.weight),
+                    "Destination weight must be between 1 and {MAX_WEIGHT}"
+                );
+                let destination = DestinationPaymentAddress::from_str(input.destination.trim())?;
+                if let DestinationPaymentAddress::Bolt12Offer(offer) = &destination {
+                    ensure!(!offer.is_expired(SystemTime::now()), "BOLT12 offer expired");
+                }
+                Ok(WeightedDestination {
+                    destination,
+                    weight: input.weight,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        ensure!(
+            !destinations.is_empty(),
+            "At least one destination is required"
+        );
+        ensure!(
+            destinations.len() <= MAX_DESTINATIONS,
+            "At most {MAX_DESTINATIONS} destinations are allowed"
+        );
+
+        ensure!(
+            input.amount_rules.len() <= MAX_AMOUNT_RULES,
+            "At most {MAX_AMOUNT_RULES} amount rules are allowed"
+        );
+        let amount_rules = input
+            .amount_rules
+            .iter()
+            .map(|rule| {
+                ensure!(
+                    rule.destination < destinations.len(),
+                    "Amount rule refers to unknown destination {}",
+                    rule.destination
+                );
+                if let (Some(min), Some(max)) = (rule.min_sats, rule.max_sats) {
+                    ensure!(min < max, "Amount rule minimum must be below its maximum");
+                }
+                Ok(AmountRule {
+                    min_msat: rule.min_sats.map(sats_to_msat).transpose()?,
+                    max_msat: rule.max_sats.map(sats_to_msat).transpose()?,
+                    destination_position: rule.destination,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Routing {
+            destinations,
+            policy: input.policy,
+            amount_rules,
+        })
+    }
+
+    /// Addresses with amount rules need their callbacks proxied since the destination can only be
+    /// chosen once the amount is known. Collecting comments or payer data for the owner requires
+    /// seeing the callback as well, and BOLT12 offers have no callback of their own.
+    fn proxies_callbacks(&self, entry: &PaymentAddress) -> bool {
+        self.proxy_callbacks
+            || !entry.amount_rules.is_empty()
+            || entry.destinations.iter().any(|destination| {
+                matches!(
+                    destination.destination,
+                    DestinationPaymentAddress::Bolt12Offer(_)
+                )
+            })
+            || entry.settings.store_comments
+            || entry.settings.payer_data.is_some()
+            || entry.settings.success_action.is_some()
+    }
+
+    /// Adjusts an upstream manifest to be served with a proxied callback
+    fn proxy_manifest(
+        &self,
+        entry: &PaymentAddress,
+        manifest: &mut PayManifest,
+        destination: &AddressDestination,
+        bound: bool,
+    ) -> Result<()> {
+        manifest.callback = Self::callback_url(
+            &entry.domain,
+            &entry.username,
+            bound.then_some(destination.id),
+        )?;
+        manifest.comment_allowed = self.advertised_comment_length(entry, manifest.comment_allowed);
+        manifest.payer_data = requested_payer_data(
+            entry,
+            &destination.destination,
+            manifest.payer_data.as_ref(),
+        );
+        let own_pubkey = self
+            .nostr_keys
+            .as_ref()
+            .map(|keys| keys.x_only_public_key().0.to_string());
+        // Without a bound destination the invoice may come from any of them
+        let zap_pubkey = if bound {
+            zap_pubkey(own_pubkey, &destination.destination, manifest)
+        } else {
+            own_pubkey.filter(|_| {
+                entry.destinations.iter().all(|destination| {
+                    matches!(
+                        destination.destination,
+                        DestinationPaymentAddress::Bolt12Offer(_)
+                    )
+                })
+            })
+        };
+        manifest.extra.remove("allowsNostr");
+        manifest.extra.remove("nostrPubkey");
+        if let Some(pubkey) = zap_pubkey {
+            manifest
+                .extra
+                .insert("allowsNostr".to_owned(), Value::Bool(true));
+            manifest
+                .extra
+                .insert("nostrPubkey".to_owned(), Value::String(pubkey));
+        }
+        Ok(())
+    }
+
+    /// Callback URL served in proxied manifests, optionally bound to the destination that served
+    /// the manifest
+    fn callback_url(domain: &str, username: &str, destination_id: Option<i32>) -> Result<String> {
+        let mut url = url::Url::parse(&format!("https://{domain}/"))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid domain {domain}"))?
+            .extend(["lnurlp", username, "callback"]);
+        if let Some(destination_id) = destination_id {
+            url.query_pairs_mut()
+                .append_pair("d", &destination_id.to_string());
+        }
+        Ok(url.to_string())
+    }
+
+    /// Whether a LUD-09 url success action may point to the URL. Since the callback is served by
+    /// lnaddrd, upstream domains aren't trusted unless configured.
+    fn is_trusted_success_url(&self, url: &str) -> bool {
+        url::Url::parse(url).is_ok_and(|url| {
+            url.scheme() == "https"
+                && url.host_str().is_some_and(|host| {
+                    self.domains
+                        .iter()
+                        .chain(&self.trusted_success_action_domains)
+                        .any(|domain| domain.eq_ignore_ascii_case(host))
+                })
+        })
+    }
+
+    fn validate_success_action(&self, action: &SuccessAction) -> Result<()> {
+        let text = match action {
+            SuccessAction::Message { message } => message,
+            SuccessAction::Url { description, url } => {
+                ensure!(
+                    self.is_trusted_success_url(url),
+                    "Success action URL must be a https URL on a trusted domain"
+                );
+                description
+            }
+        };
+        ensure!(
+            !text.trim().is_empty() && text.chars().count() <= MAX_SUCCESS_ACTION_TEXT_LENGTH,
+            "Success action text must have between 1 and {MAX_SUCCESS_ACTION_TEXT_LENGTH} characters"
+        );
+        ensure!(
+            !text.chars().any(char::is_control),
+            "Success action text must not contain control characters"
+        );
+        Ok(())
+    }
+
+    /// Validates a settings update of an owner, normalizing the Nostr public key to hex
+    fn check_settings_update(&self, mut update: SettingsUpdate) -> Result<SettingsUpdate> {
+        if let Some(Some(action)) = &update.success_action {
+            self.validate_success_action(action)?;
+        }
+        if let Some(Some(identity)) = &mut update.nostr {
+            identity.pubkey = nostr::parse_pubkey(&identity.pubkey)?.to_string();
+            identity.relays.retain(|relay| !relay.trim().is_empty());
+            ensure!(
+                identity.relays.len() <= MAX_NOSTR_RELAYS,
+                "At most {MAX_NOSTR_RELAYS} relays are allowed"
+            );
+            for relay in &mut identity.relays {
+                *relay = relay.trim().to_owned();
+                ensure!(nostr::is_relay_url(relay), "Invalid relay URL {relay}");
+            }
+        }
+        if let Some(Some(instructions)) = &mut update.bip353 {
+            let trimmed = |value: &Option<String>| {
+                value
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(ToOwned::to_owned)
+            };
+            instructions.offer = trimmed(&instructions.offer)
+                .map(|offer| Offer::parse(&offer))
+                .transpose()?
+                .map(|offer| offer.to_string());
+            instructions.onchain = trimmed(&instructions.onchain);
+            ensure!(
+                instructions.offer.is_some() || instructions.onchain.is_some(),
+                "Payment instructions need a BOLT12 offer or an on-chain address"
+            );
+            if let Some(address) = &instructions.onchain {
+                bip353::check_onchain_address(address)?;
+            }
+        }
+
+        Ok(update)
+    }
+
+    /// BIP-353 record publishing the payment instructions of an address
+    fn bip353_record(
+        domain: &str,
+        username: &str,
+        instructions: &PaymentInstructions,
+    ) -> Result<Bip353Record> {
+        Ok(Bip353Record {
+            name: bip353::record_name(username, domain)?,
+            uri: bip353::payment_uri(instructions),
+        })
+    }
+
+    /// Pushes the BIP-353 record of an address to the DNS server if dynamic updates are
+    /// configured, deleting it if the address has no payment instructions. Failures are only
+    /// logged, the zone can be resynchronized by an operator.
+    async fn push_bip353_record(
+        &self,
+        domain: &str,
+        username: &str,
+        instructions: Option<&PaymentInstructions>,
+    ) {
+        let Some(updater) = &self.dns_updater else {
+            return;
+        };
+
+        let result = async {
+            let name = bip353::record_name(username, domain)?;
+            let record = instructions
+                .map(|instructions| Self::bip353_record(domain, username, instructions))
+                .transpose()?;
+            updater.update(domain, &name, record.as_ref()).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!(error=%e, %domain, %username, "Failed to push BIP-353 record");
+        }
+    }
+
+    /// Injects the owner's success action into a proxied invoice response or sanitizes the
+    /// upstream's. AES success actions are passed through unchanged since they carry a secret
+    /// only the upstream can provide.
+    fn rewrite_success_action(&self, entry: &PaymentAddress, invoice: &mut InvoiceResponse) {
+        let upstream = invoice.extra.remove("successAction");
+        let upstream_tag = upstream
+            .as_ref()
+            .and_then(|action| action.get("tag"))
+            .and_then(Value::as_str);
+
+        let action = match (upstream_tag, &entry.settings.success_action) {
+            (Some("aes"), _) => upstream,
+            (_, Some(action)) => serde_json::to_value(action).ok(),
+            (Some("message"), None) => upstream
+                .as_ref()
+                .and_then(|action| action.get("message"))
+                .and_then(Value::as_str)
+                .map(sanitize_success_text)
+                .filter(|message| !message.is_empty())
+                .map(|message| serde_json::json!({ "tag": "message", "message": message })),
+            (Some("url"), None) => upstream.filter(|action| {
+                action
+                    .get("url")
+                    .and_then(Value::as_str)
+                    .is_some_and(|url| self.is_trusted_success_url(url))
+            }),
+            _ => None,
+        };
+
+        if let Some(action) = action {
+            invoice.extra.insert("successAction".to_owned(), action);
+        }
+    }
+
+    /// LUD-16 LNURL-pay endpoint of an address
+    fn lnurlp_url(domain: &str, username: &str) -> Result<url::Url> {
+        let mut url = url::Url::parse(&format!("https://{domain}/"))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid domain {domain}"))?
+            .extend([".well-known", "lnurlp", username]);
+        Ok(url)
+    }
+
+    /// LUD-21 verify URL of an invoice issued through a proxied callback
+    fn verify_url(domain: &str, username: &str, verify_id: &str) -> Result<String> {
+        let mut url = url::Url::parse(&format!("https://{domain}/"))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid domain {domain}"))?
+            .extend(["lnurlp", username, "verify", verify_id]);
+        Ok(url.to_string())
+    }
+
+    /// Records an issued invoice and replaces the upstream's verify URL with one served by
+    /// lnaddrd. If the invoice can't be recorded, the upstream's verify URL is left in place.
+    async fn track_invoice(
+        &self,
+        entry: &PaymentAddress,
+        amount_msat: u64,
+        zap_request: Option<&ZapRequest>,
+        forward: Option<OfferForward>,
+        invoice: &mut InvoiceResponse,
+    ) -> Result<()> {
+        let verify_id =
+            rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 24);
+        let upstream_verify = invoice
+            .extra
+            .get("verify")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned);
+        let verify_url = Self::verify_url(&entry.domain, &entry.username, &verify_id)?;
+        // Receipts of upstream invoices need their verify URL to learn of the payment
+        let zap_request = zap_request
+            .filter(|zap_request| {
+                self.nostr_keys.is_some()
+                    && (forward.is_some()
+                        || upstream_verify.is_some()
+                            && bolt11::Invoice::parse(&invoice.pr)
+                                .is_ok_and(|pr| pr.commits_to(&zap_request.json)))
+            })
+            .map(|zap_request| zap_request.json.clone());
+        self.repo
+            .add_invoice(NewInvoice {
+                payment_address_id: entry.id,
+                verify_id,
+                pr: invoice.pr.clone(),
+                amount_msat,
+                upstream_verify,
+                zap_request,
+                forward,
+            })
+            .await?;
+
+        let data = serde_json::json!({
+            "amount_msat": amount_msat,
+            "pr": invoice.pr,
+        });
+        self.queue_webhooks(
+            "invoice_requested",
+            &entry.domain,
+            &entry.username,
+            Some(entry),
+            data,
+        )
+        .await;
+
+        invoice
+            .extra
+            .insert("verify".to_owned(), Value::String(verify_url));
+        Ok(())
+    }
+
+    /// Fetches the manifest of the first reachable destination in the given order
+    async fn first_reachable_manifest<'a>(
+        &self,
+        domain: &str,
+        username: &str,
+        destinations: impl IntoIterator<Item = &'a AddressDestination>,
+    ) -> Result<(&'a AddressDestination, PayManifest)> {
+        let mut last_error = None;
+        for destination in destinations {
+            match self.fetch_manifest(&destination.destination).await {
+                Ok(response) => return Ok((destination, response)),
+                Err(e) => {
+                    warn!(
+                        error=%e,
+                        destination=%destination.destination,
+                        %domain,
+                        %username,
+                        "Upstream LNURL request failed",
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No destinations configured")))
+    }
+
+    /// Builds a manifest whose sendable range is the union of all reachable destinations, used
+    /// when the destination is only chosen at callback time. The metadata is taken from the
+    /// preferred destination, which is returned along with the manifest.
+    async fn union_manifest<'a>(
+        &self,
+        domain: &str,
+        username: &str,
+        entry: &'a PaymentAddress,
+    ) -> Result<(&'a AddressDestination, PayManifest)> {
+        let destinations = routing_order(entry.routing_policy, &entry.destinations);
+        let manifests = futures::future::join_all(
+            destinations
+                .iter()
+                .map(|destination| self.fetch_manifest(&destination.destination)),
+        )
+        .await;
+
+        let mut preferred = None;
+        let mut reachable = Vec::new();
+        for (destination, manifest) in destinations.iter().zip(manifests) {
+            match manifest {
+                Ok(manifest) => {
+                    preferred.get_or_insert(*destination);
+                    reachable.push(manifest);
+                }
+                Err(e) => {
+                    warn!(
+                        error=%e,
+                        destination=%destination.destination,
+                        %domain,
+                        %username,
+                        "Upstream LNURL request failed",
+                    );
+                }
+            }
+        }
+
+        let manifest = union_of_manifests(reachable)?;
+        Ok((preferred.context("No destination is reachable")?, manifest))
+    }
+
+    /// Comment length advertised in proxied manifests. Comments are accepted up to the
+    /// configured maximum if they are stored, otherwise they are limited by what the upstream
+    /// accepts.
+    fn advertised_comment_length(
+        &self,
+        entry: &PaymentAddress,
+        upstream: Option<u32>,
+    ) -> Option<u32> {
+        let allowed = if entry.settings.store_comments {
+            self.max_comment_length
+        } else {
+            upstream.unwrap_or(0).min(self.max_comment_length)
+        };
+        (allowed > 0).then_some(allowed)
+    }
+
+    /// Calls the callback of an upstream manifest to request an invoice. The comment is only
+    /// forwarded if the upstream accepts comments and truncated to the upstream's limit, payer
+    /// data only if the upstream requests it.
+    async fn fetch_invoice(
+        &self,
+        manifest: &PayManifest,
+        params: &CallbackParams<'_>,
+    ) -> Result<InvoiceResponse> {
+        let amount_msat = params.amount_msat;
+        ensure!(
+            (manifest.min_sendable..=manifest.max_sendable).contains(&amount_msat),
+            "Amount {amount_msat} msat is outside the sendable range of the destination"
+        );
+
+        let mut url = url::Url::parse(&manifest.callback)?;
+        url.query_pairs_mut()
+            .append_pair("amount", &amount_msat.to_string());
+        if let (Some(comment), Some(allowed)) = (params.comment, manifest.comment_allowed) {
+            let comment = truncate_comment(comment, allowed as usize);
+            if !comment.is_empty() {
+                url.query_pairs_mut().append_pair("comment", comment);
+            }
+        }
+        if let Some(payer_data) = params.payer_data.filter(|_| manifest.payer_data.is_some()) {
+            url.query_pairs_mut().append_pair("payerdata", payer_data);
+        }
+        // Upstreams supporting zaps commit to the zap request in the invoice description
+        if let Some(zap_request) = params
+            .zap_request
+            .filter(|_| manifest.extra.get("allowsNostr") == Some(&Value::Bool(true)))
+        {
+            url.query_pairs_mut().append_pair("nostr", zap_request);
+        }
+
+        let response = self.get_lnurl_json(url.as_str()).await?;
+
+        Ok(serde_json::from_value(response)?)
+    }
+
+    /// Checks the upstream's verify URL, or the node backend for invoices it issued, for the
+    /// payment status of an invoice not known to be paid yet, recording when it is. Otherwise the
+    /// status tracked by lnaddrd is returned as is.
+    async fn refresh_invoice_status(&self, mut invoice: Invoice) -> Result<Invoice> {
+        if invoice.settled {
+            return Ok(invoice);
+        }
+
+        if let (Some(forward), Some(node)) = (&invoice.forward, &self.node) {
+            invoice.preimage = node.invoice_preimage(&forward.payment_hash).await?;
+            invoice.settled = invoice.preimage.is_some();
+        } else if let Some(upstream_verify) = invoice.upstream_verify.as_deref() {
+            let response = self.get_lnurl_json(upstream_verify).await?;
+            invoice.settled = response
+                .get("settled")
+                .and_then(Value::as_bool)
+                .context("Invalid upstream verify response")?;
+            invoice.preimage = response
+                .get("preimage")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned);
+        } else {
+            return Ok(invoice);
+        }
+
+        if invoice.settled {
+            match self
+                .repo
+                .settle_invoice(&invoice.verify_id, invoice.preimage.as_deref())
+                .await
+            {
+                Ok(true) => self.notify_invoice_paid(&invoice).await,
+                Ok(false) => {}
+                Err(e) => {
+                    warn!(
+                        error=%e,
+                        verify_id=%invoice.verify_id,
+                        "Failed to record settled invoice",
+                    )
+                }
+            }
+        }
+
+        Ok(invoice)
+    }
+
+    /// Publishes the zap receipt of a paid invoice, succeeding if at least one relay accepted it
+    async fn publish_zap_receipt(&self, keys: &Keypair, invoice: &Invoice) -> Result<()> {
+        // Upstreams have to prove the payment they report with its preimage
+        if invoice.forward.is_none() {
+            let preimage = invoice
+                .preimage
+                .as_deref()
+                .and_then(|preimage| <[u8; 32]>::from_hex(preimage).ok())
+                .context("Upstream did not reveal a valid preimage")?;
+            ensure!(
+                sha256::Hash::hash(&preimage).to_byte_array()
+                    == bolt11::Invoice::parse(&invoice.pr)?.payment_hash,
+                "Preimage of the upstream does not match the invoice"
+            );
+        }
+        let zap_request = ZapRequest::parse(
+            invoice
+                .zap_request
+                .as_deref()
+                .context("Invoice has no zap request")?,
+            invoice.amount_msat,
+        )?;
+        let receipt = zap_request.receipt(
+            keys,
+            &invoice.pr,
+            invoice.preimage.as_deref(),
+            SystemTime::now(),
+        )?;
+
+        let relays = if self.nostr_relays.is_empty() {
+            zap_request.relays()
+        } else {
+            self.nostr_relays.clone()
+        };
+        let results = futures::future::join_all(
+            relays
+                .iter()
+                .map(|relay| nostr::publish(relay, &receipt, RELAY_TIMEOUT)),
+        )
+        .await;
+
+        let mut last_error = None;
+        let mut accepted = false;
+        for (relay, result) in relays.iter().zip(results) {
+            match result {
+                Ok(()) => accepted = true,
+                Err(e) => {
+                    warn!(error=%e, %relay, "Relay did not accept zap receipt");
+                    last_error = Some(e);
+                }
+            }
+        }
+        ensure!(
+            accepted,
+            last_error.unwrap_or_else(|| anyhow!("No relays to publish to"))
+        );
+
+        Ok(())
+    }
+
+    /// Stores the payer data fields requested by the owner, fields only requested by the upstream
+    /// are not kept
+    async fn store_payer_data(
+        &self,
+        entry: &PaymentAddress,
+        amount_msat: u64,
+        requested: &PayerDataRequest,
+        fields: &Map<String, Value>,
+    ) {
+        let owned = fields
+            .iter()
+            .filter(|(key, _)| {
+                requested
+                    .fields()
+                    .iter()
+                    .any(|(name, field)| name == key && field.is_some())
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Map<_, _>>();
+        if owned.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self
+            .repo
+            .add_payer_data(entry.id, amount_msat, &Value::Object(owned).to_string())
+            .await
+        {
+            warn!(
+                error=%e,
+                domain=%entry.domain,
+                username=%entry.username,
+                "Failed to store payer data",
+            );
+        }
+    }
+
+    fn record_selection_in_background(&self, destination_id: i32) {
+        let repo = self.repo.clone();
+        tokio::spawn(async move {
+            if let Err(e) = repo.record_destination_selection(destination_id).await {
+                warn!(error=%e, destination_id, "Failed to record destination selection");
+            }
+        });
+    }
+
+    /// Posts an abuse report to the report webhook without delaying the reporter
+    fn notify_report_in_background(&self, report: Value) {
+        let Some(url) = self.report_webhook_url.clone() else {
+            return;
+        };
+        let http_client = self.http_client.clone();
+        tokio::spawn(async move {
+            let result = http_client
+                .post(&url)
+                .json(&report)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                warn!(error=%e, "Failed to notify report webhook");
+            }
+        });
+    }
+
+    fn generate_authentication_token() -> String {
+        rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 20)
+    }
 }
 
 #[async_trait]
@@ -37,61 +1265,2424 @@ impl ILnaddrService for DirectLnaddrService {
         &self,
         domain: &str,
         username: &str,
-    ) -> Result<Option<PayResponse>> {
-        let Some(lnaddr_entry) = self.repo.get_payment_address(domain, username).await? else {
+        record_selection: bool,
+    ) -> Result<Option<PayManifest>> {
+        let Some(lnaddr_entry) = self.get_active_payment_address(domain, username).await? else {
             return Ok(None);
         };
+        ensure_served(&lnaddr_entry)?;
 
-        let response = match self.client.make_request(&lnaddr_entry.destination.url()).await? {
-            LnUrlResponse::LnUrlPayResponse(response) => response,
-            LnUrlResponse::LnUrlWithdrawResponse(_) => bail!("Invalid LNURL type: LNURLwithdraw"),
-            LnUrlResponse::LnUrlChannelResponse(_) => bail!("Invalid LNURL type: LNURLchannel"),
-        };
+        if !self.proxies_callbacks(&lnaddr_entry) {
+            // Fall back to the next destination if an upstream is unreachable or misbehaving.
+            // Since the upstream manifest is passed through as is, the wallet requests the invoice
+            // from the same destination that served the manifest.
+            let (destination, response) = self
+                .first_reachable_manifest(
+                    domain,
+                    username,
+                    routing_order(lnaddr_entry.routing_policy, &lnaddr_entry.destinations),
+                )
+                .await?;
+            if record_selection {
+                self.record_selection_in_background(destination.id);
+            }
+            return Ok(Some(response));
+        }
+
+        if !lnaddr_entry.amount_rules.is_empty() {
+            let (destination, mut response) =
+                self.union_manifest(domain, username, &lnaddr_entry).await?;
+            self.proxy_manifest(&lnaddr_entry, &mut response, destination, false)?;
+            return Ok(Some(response));
+        }
+
+        // Bind the callback to the destination that served the manifest so the invoice is issued
+        // by the same upstream
+        let (destination, mut response) = self
+            .first_reachable_manifest(
+                domain,
+                username,
+                routing_order(lnaddr_entry.routing_policy, &lnaddr_entry.destinations),
+            )
+            .await?;
+        self.proxy_manifest(&lnaddr_entry, &mut response, destination, true)?;
 
         Ok(Some(response))
     }
 
-    async fn get_destination(&self, domain: &str, username: &str) -> Result<Option<DestinationPaymentAddress>> {
-        let Some(lnaddr_entry) = self.repo.get_payment_address(domain, username).await? else {
+    async fn get_invoice(
+        &self,
+        domain: &str,
+        username: &str,
+        request: InvoiceRequest,
+    ) -> Result<Option<InvoiceResponse>> {
+        let Some(lnaddr_entry) = self.get_active_payment_address(domain, username).await? else {
             return Ok(None);
         };
+        ensure_served(&lnaddr_entry)?;
+        ensure!(
+            self.proxies_callbacks(&lnaddr_entry),
+            "Callbacks of {username}@{domain} are not proxied"
+        );
+
+        let comment = request
+            .comment
+            .as_deref()
+            .map(sanitize_comment)
+            .filter(|comment| !comment.is_empty() && self.max_comment_length > 0);
+        if let Some(comment) = &comment {
+            ensure!(
+                comment.chars().count() <= self.max_comment_length as usize,
+                "Comment is longer than {} characters",
+                self.max_comment_length
+            );
+        }
+
+        let payer_data_fields = request
+            .payer_data
+            .as_deref()
+            .map(parse_payer_data)
+            .transpose()?;
+
+        let zap_request = request
+            .zap_request
+            .as_deref()
+            .map(|zap_request| ZapRequest::parse(zap_request, request.amount_msat))
+            .transpose()?;
+
+        let params = CallbackParams {
+            amount_msat: request.amount_msat,
+            comment: comment.as_deref(),
+            payer_data: request.payer_data.as_deref(),
+            zap_request: request.zap_request.as_deref(),
+        };
 
-        Ok(Some(lnaddr_entry.destination))
+        let routed = routing_order(lnaddr_entry.routing_policy, &lnaddr_entry.destinations);
+        let candidates = if let Some(rule) = lnaddr_entry
+            .amount_rules
+            .iter()
+            .find(|rule| rule.matches(request.amount_msat))
+        {
+            // The destination of the matching rule is preferred, others serve as fallbacks
+            let preferred = lnaddr_entry
+                .destinations
+                .get(rule.destination_position)
+                .context("Amount rule refers to unknown destination")?;
+            std::iter::once(preferred)
+                .chain(routed.into_iter().filter(|d| d.id != preferred.id))
+                .collect::<Vec<_>>()
+        } else if let Some(destination_id) = request.destination_id {
+            // The invoice has to come from the destination whose manifest the wallet saw
+            let destination = lnaddr_entry
+                .destinations
+                .iter()
+                .find(|d| d.id == destination_id)
+                .context("Unknown destination")?;
+            vec![destination]
+        } else {
+            routed
+        };
+
+        let mut last_error = None;
+        for destination in candidates {
+            let invoice = match self.fetch_manifest(&destination.destination).await {
+                Ok(manifest) => {
+                    // Payer data has to match what was requested in the manifest the wallet saw
+                    check_payer_data(
+                        payer_data_fields.as_ref(),
+                        requested_payer_data(
+                            &lnaddr_entry,
+                            &destination.destination,
+                            manifest.payer_data.as_ref(),
+                        )
+                        .as_ref(),
+                    )?;
+                    match &destination.destination {
+                        DestinationPaymentAddress::Bolt12Offer(offer) => self
+                            .bridge_invoice(offer, &manifest, &params)
+                            .await
+                            .map(|(invoice, forward)| (invoice, Some(forward))),
+                        _ => self
+                            .fetch_invoice(&manifest, &params)
+                            .await
+                            .map(|invoice| (invoice, None)),
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            match invoice {
+                Ok((mut invoice, forward)) => {
+                    let bridged = forward.is_some();
+                    self.record_selection_in_background(destination.id);
+                    self.rewrite_success_action(&lnaddr_entry, &mut invoice);
+                    if let Err(e) = self
+                        .track_invoice(
+                            &lnaddr_entry,
+                            request.amount_msat,
+                            zap_request.as_ref(),
+                            forward,
+                            &mut invoice,
+                        )
+                        .await
+                    {
+                        // Payments of bridged invoices that aren't recorded would never reach
+                        // the offer
+                        if bridged {
+                            return Err(e);
+                        }
+                        warn!(error=%e, %domain, %username, "Failed to record invoice");
+                    }
+                    if let Some(comment) = comment.as_deref()
+                        && lnaddr_entry.settings.store_comments
+                        && let Err(e) = self
+                            .repo
+                            .add_payment_comment(lnaddr_entry.id, request.amount_msat, comment)
+                            .await
+                    {
+                        warn!(error=%e, %domain, %username, "Failed to store payment comment");
+                    }
+                    if let (Some(requested), Some(fields)) =
+                        (&lnaddr_entry.settings.payer_data, &payer_data_fields)
+                    {
+                        self.store_payer_data(
+                            &lnaddr_entry,
+                            request.amount_msat,
+                            requested,
+                            fields,
+                        )
+                        .await;
+                    }
+                    return Ok(Some(invoice));
+                }
+                Err(e) => {
+                    warn!(
+                        error=%e,
+                        destination=%destination.destination,
+                        %domain,
+                        %username,
+                        "Upstream invoice request failed",
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No destinations configured")))
     }
 
-    async fn register_lnaddr(
+    async fn verify_invoice(
         &self,
         domain: &str,
         username: &str,
-        destination: &str,
-    ) -> Result<RegisterResponse> {
-        if !self.domains.contains(&domain.to_string()) {
-            bail!("Unsupported domain: {}", domain);
+        verify_id: &str,
+    ) -> Result<Option<VerifyResponse>> {
+        let Some(lnaddr_entry) = self.get_active_payment_address(domain, username).await? else {
+            return Ok(None);
+        };
+        let Some(invoice) = self.repo.get_invoice(lnaddr_entry.id, verify_id).await? else {
+            return Ok(None);
+        };
+
+        let invoice = self.refresh_invoice_status(invoice).await?;
+
+        Ok(Some(VerifyResponse {
+            settled: invoice.settled,
+            preimage: invoice.preimage,
+            pr: invoice.pr,
+        }))
+    }
+
+    async fn publish_zap_receipts(&self) -> Result<usize> {
+        let Some(keys) = &self.nostr_keys else {
+            return Ok(0);
+        };
+
+        let mut published = 0;
+        let pending = self
+            .repo
+            .list_pending_zap_invoices(SystemTime::now() - ZAP_RECEIPT_WINDOW)
+            .await?;
+        for invoice in pending {
+            let verify_id = invoice.verify_id.clone();
+            let invoice = match self.refresh_invoice_status(invoice).await {
+                Ok(invoice) if invoice.settled => invoice,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!(error=%e, %verify_id, "Failed to check zapped invoice");
+                    continue;
+                }
+            };
+
+            match self.publish_zap_receipt(keys, &invoice).await {
+                Ok(()) => {
+                    self.repo.mark_zap_receipt_published(&verify_id).await?;
+                    published += 1;
+                }
+                Err(e) => warn!(error=%e, %verify_id, "Failed to publish zap receipt"),
+            }
         }
 
-        // Test if the lnurl is valid
-        let destination = DestinationPaymentAddress::from_str(destination)?;
+        Ok(published)
+    }
 
-        let authentication_token =
-            rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 20);
-        self.repo
-            .add_payment_address(domain, username, destination, &authentication_token)
+    async fn forward_offer_payments(&self) -> Result<usize> {
+        let Some(node) = &self.node else {
+            return Ok(0);
+        };
+
+        let mut forwarded = 0;
+        let pending = self
+            .repo
+            .list_pending_offer_forwards(SystemTime::now() - OFFER_FORWARD_WINDOW)
             .await?;
+        for invoice in pending {
+            let verify_id = invoice.verify_id.clone();
+            let invoice = match self.refresh_invoice_status(invoice).await {
+                Ok(invoice) if invoice.settled => invoice,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!(error=%e, %verify_id, "Failed to check bridged invoice");
+                    continue;
+                }
+            };
+            let Some(forward) = &invoice.forward else {
+                continue;
+            };
+            if !self.repo.claim_offer_forward(&verify_id).await? {
+                continue;
+            }
 
-        Ok(RegisterResponse {
-            lnaddr: format!("{}@{}", username, domain),
-            authentication_token,
-        })
+            match node
+                .pay_offer(
+                    &forward.offer,
+                    invoice.amount_msat,
+                    forward.payer_note.as_deref(),
+                )
+                .await
+            {
+                Ok(OfferPayment::Paid) => forwarded += 1,
+                Ok(OfferPayment::Failed(reason)) => {
+                    warn!(%reason, %verify_id, "Failed to pay offer, retrying later");
+                    self.repo.release_offer_forward(&verify_id).await?;
+                }
+                // Retrying could pay the offer twice, so the operator has to resolve this
+                Err(e) => {
+                    warn!(
+                        error=%e,
+                        %verify_id,
+                        "Payment to offer has unknown outcome, not retrying",
+                    )
+                }
+            }
+        }
+
+        Ok(forwarded)
     }
 
-    async fn remove_lnaddr(
-        &self,
+    async fn get_offer(&self, domain: &str, username: &str) -> Result<Option<Offer>> {
+        let Some(entry) = self.get_active_payment_address(domain, username).await? else {
+            return Ok(None);
+        };
+        ensure_served(&entry)?;
+
+        entry
+            .payment_instructions()
+            .and_then(|instructions| instructions.offer)
+            .map(|offer| Offer::parse(&offer))
+            .transpose()
+    }
+
+    async fn get_nostr_identity(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<NostrIdentity>> {
+        Ok(self
+            .get_active_payment_address(domain, username)
+            .await?
+            .filter(|entry| ensure_served(entry).is_ok())
+            .and_then(|entry| entry.settings.nostr))
+    }
+
+    async fn get_destination(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<DestinationPaymentAddress>> {
+        let Some(lnaddr_entry) = self.get_active_payment_address(domain, username).await? else {
+            return Ok(None);
+        };
+        ensure_served(&lnaddr_entry)?;
+
+        Ok(lnaddr_entry
+            .destinations
+            .into_iter()
+            .next()
+            .map(|destination| destination.destination))
+    }
+
+    async fn get_lnaddr_details(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<LnaddrDetails>> {
+        self.get_active_payment_address(domain, username)
+            .await?
+            .map(Self::lnaddr_details)
+            .transpose()
+    }
+
+    async fn report_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        reason: &str,
+        contact: Option<&str>,
+        client: &ClientInfo,
+    ) -> Result<()> {
+        let reason = reason.trim();
+        ensure!(!reason.is_empty(), "A reason is required");
+        ensure!(
+            reason.chars().count() <= MAX_REPORT_REASON_LENGTH,
+            "Reason exceeds {MAX_REPORT_REASON_LENGTH} characters"
+        );
+        let contact = contact.map(str::trim).filter(|contact| !contact.is_empty());
+        ensure!(
+            contact.is_none_or(|contact| contact.chars().count() <= MAX_REPORT_CONTACT_LENGTH),
+            "Contact exceeds {MAX_REPORT_CONTACT_LENGTH} characters"
+        );
+        let Some(entry) = self.get_active_payment_address(domain, username).await? else {
+            bail!("Unknown payment address {username}@{domain}");
+        };
+
+        self.repo
+            .add_address_report(NewAddressReport {
+                payment_address_id: entry.id,
+                reason: reason.to_owned(),
+                contact: contact.map(ToOwned::to_owned),
+                client_ip: client.ip.map(|ip| ip.to_string()),
+            })
+            .await?;
+
+        let reporters = self.repo.count_address_reporters(entry.id).await?;
+        let suspend = entry.status == AddressStatus::Active
+            && self.report_suspend_threshold > 0
+            && reporters >= i64::from(self.report_suspend_threshold);
+        if suspend {
+            self.repo
+                .update_payment_address_status(domain, username, AddressStatus::Suspended)
+                .await?;
+            self.record_event(
+                domain,
+                username,
+                AddressEventKind::Suspended,
+                client,
+                None,
+                None,
+            )
+            .await;
+            info!(%domain, %username, reporters, "Suspended reported address");
+        }
+
+        self.notify_report_in_background(serde_json::json!({
+            "event": "address_reported",
+            "lnaddr": format!("{username}@{domain}"),
+            "reason": reason,
+            "contact": contact,
+            "reporters": reporters,
+            "suspended": suspend || entry.status == AddressStatus::Suspended,
+        }));
+
+        Ok(())
+    }
+
+    async fn register_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        routing: &RoutingInput,
+        settings: SettingsUpdate,
+        owner_id: Option<i32>,
+        client: &ClientInfo,
+    ) -> Result<RegisterResponse> {
+        if !self.domains.contains(&domain.to_string()) {
+            bail!("Unsupported domain: {}", domain);
+        }
+        // Reserved names are stored lowercase so that they also cover case variants
+        if self
+            .repo
+            .is_name_reserved(domain, &username.to_lowercase())
+            .await?
+        {
+            bail!("Username {username} is reserved");
+        }
+
+        // Test if the lnurls are valid
+        let routing = Self::parse_routing(routing)?;
+        let new_destinations =
+            format_destinations(routing.destinations.iter().map(|d| &d.destination));
+        let settings = {
+            let mut initial = AddressSettings::default();
+            self.check_settings_update(settings)?.apply(&mut initial);
+            initial
+        };
+        let instructions =
+            settings.payment_instructions(routing.destinations.iter().map(|d| &d.destination));
+        if instructions.is_some() {
+            bip353::record_name(username, domain)?;
+        }
+
+        let authentication_token = Self::generate_authentication_token();
+        let expires_at = self
+            .address_lifetime
+            .map(|lifetime| SystemTime::now() + lifetime);
+        self.repo
+            .add_payment_address(
+                domain,
+                username,
+                routing,
+                settings,
+                owner_id,
+                &authentication_token,
+                expires_at,
+            )
+            .await?;
+        if instructions.is_some() {
+            self.push_bip353_record(domain, username, instructions.as_ref())
+                .await;
+        }
+
+        self.record_event(
+            domain,
+            username,
+            AddressEventKind::Registered,
+            client,
+            None,
+            Some(new_destinations),
+        )
+        .await;
+
+        Ok(RegisterResponse {
+            lnaddr: format!("{}@{}", username, domain),
+            authentication_token,
+            expires_at: expires_at.map(|t| humantime::format_rfc3339_seconds(t).to_string()),
+        })
+    }
+
+    async fn remove_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        client: &ClientInfo,
+    ) -> Result<()> {
+        if self
+            .repo
+            .get_payment_address(domain, username)
+            .await?
+            .is_none()
+        {
+            return Ok(());
+        }
+
+        let entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&entry, credentials)?;
+
+        if let Some(removed) = self.repo.remove_payment_address(domain, username).await? {
+            if removed.payment_instructions().is_some() {
+                self.push_bip353_record(domain, username, None).await;
+            }
+            self.record_removal(&removed, AddressEventKind::Removed, client)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn update_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        routing: &RoutingInput,
+        client: &ClientInfo,
+    ) -> Result<()> {
+        let entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&entry, credentials)?;
+        let routing = Self::parse_routing(routing)?;
+        let new_destinations =
+            format_destinations(routing.destinations.iter().map(|d| &d.destination));
+        let instructions = entry
+            .settings
+            .payment_instructions(routing.destinations.iter().map(|d| &d.destination));
+        if instructions.is_some() {
+            bip353::record_name(username, domain)?;
+        }
+
+        self.repo
+            .update_payment_address_routing(domain, username, routing)
+            .await?;
+        if instructions != entry.payment_instructions() {
+            self.push_bip353_record(domain, username, instructions.as_ref())
+                .await;
+        }
+
+        self.record_event(
+            domain,
+            username,
+            AddressEventKind::DestinationUpdated,
+            client,
+            Some(format_destinations(
+                entry.destinations.iter().map(|d| &d.destination),
+            )),
+            Some(new_destinations),
+        )
+        .await;
+
+        Ok(())
+    }
+
+    async fn rotate_token(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        client: &ClientInfo,
+    ) -> Result<RotateTokenResponse> {
+        let entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&entry, credentials)?;
+
+        let new_token = Self::generate_authentication_token();
+        self.repo
+            .update_authentication_token(domain, username, &new_token)
+            .await?;
+
+        self.record_event(
+            domain,
+            username,
+            AddressEventKind::TokenRotated,
+            client,
+            None,
+            None,
+        )
+        .await;
+
+        Ok(RotateTokenResponse {
+            lnaddr: format!("{}@{}", username, domain),
+            authentication_token: new_token,
+        })
+    }
+
+    async fn set_lnaddr_status(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        status: AddressStatus,
+        client: &ClientInfo,
+    ) -> Result<()> {
+        let entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&entry, credentials)?;
+        if !matches!(credentials, Credentials::Admin) {
+            ensure!(
+                status != AddressStatus::Suspended,
+                "Only the operator can suspend addresses"
+            );
+        }
+        if entry.status == status {
+            return Ok(());
+        }
+
+        self.repo
+            .update_payment_address_status(domain, username, status)
+            .await?;
+        let kind = match (entry.status, status) {
+            (_, AddressStatus::Suspended) => AddressEventKind::Suspended,
+            (_, AddressStatus::Paused) => AddressEventKind::Paused,
+            (AddressStatus::Paused, AddressStatus::Active) => AddressEventKind::Resumed,
+            (_, AddressStatus::Active) => AddressEventKind::Reinstated,
+        };
+        self.record_event(domain, username, kind, client, None, None)
+            .await;
+
+        Ok(())
+    }
+
+    async fn renew_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        payment_hash: Option<&str>,
+        client: &ClientInfo,
+    ) -> Result<RenewResponse> {
+        let Some(lifetime) = self.address_lifetime else {
+            bail!("Addresses on this server do not expire");
+        };
+
+        let lnaddr_entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&lnaddr_entry, credentials)?;
+        let lnaddr = format!("{}@{}", username, domain);
+        let now = SystemTime::now();
+        let mut expires_at = renewed_expiry(lnaddr_entry.expires_at, now, lifetime);
+
+        match (self.renewal_price_msat, payment_hash) {
+            // Admins renew without paying
+            (Some(_), _) if matches!(credentials, Credentials::Admin) => {
+                self.repo
+                    .renew_payment_address(domain, username, expires_at)
+                    .await?;
+            }
+            (Some(price_msat), None) => {
+                let node = self.node.as_ref().context("No node backend configured")?;
+                let invoice = node
+                    .create_invoice(
+                        price_msat,
+                        &format!("Renewal of {lnaddr}"),
+                        RENEWAL_INVOICE_EXPIRY,
+                    )
+                    .await?;
+                self.repo
+                    .add_renewal_invoice(lnaddr_entry.id, &invoice.payment_hash, price_msat)
+                    .await?;
+
+                return Ok(RenewResponse {
+                    lnaddr,
+                    expires_at: lnaddr_entry.expires_at.map(|expires_at| {
+                        humantime::format_rfc3339_seconds(expires_at).to_string()
+                    }),
+                    invoice: Some(RenewalInvoice {
+                        pr: invoice.bolt11,
+                        payment_hash: invoice.payment_hash,
+                    }),
+                });
+            }
+            (Some(_), Some(payment_hash)) => {
+                let node = self.node.as_ref().context("No node backend configured")?;
+                ensure!(
+                    node.invoice_preimage(payment_hash).await?.is_some(),
+                    "The renewal invoice has not been paid"
+                );
+                // Extends the expiry time at redemption, as paid renewals may run concurrently
+                expires_at = self
+                    .repo
+                    .redeem_renewal_invoice(lnaddr_entry.id, payment_hash, lifetime, now)
+                    .await?
+                    .context("Unknown or already redeemed renewal invoice")?;
+            }
+            (None, _) => {
+                self.repo
+                    .renew_payment_address(domain, username, expires_at)
+                    .await?;
+            }
+        }
+
+        self.record_event(
+            domain,
+            username,
+            AddressEventKind::Renewed,
+            client,
+            None,
+            None,
+        )
+        .await;
+
+        Ok(RenewResponse {
+            lnaddr,
+            expires_at: Some(humantime::format_rfc3339_seconds(expires_at).to_string()),
+            invoice: None,
+        })
+    }
+
+    async fn remove_expired_lnaddrs(&self) -> Result<usize> {
+        let removed = self
+            .repo
+            .remove_expired_payment_addresses(SystemTime::now())
+            .await?;
+
+        for entry in &removed {
+            if entry.payment_instructions().is_some() {
+                self.push_bip353_record(&entry.domain, &entry.username, None)
+                    .await;
+            }
+            self.record_removal(entry, AddressEventKind::Expired, &ClientInfo::system())
+                .await;
+        }
+
+        Ok(removed.len())
+    }
+
+    async fn update_lnaddr_settings(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        update: SettingsUpdate,
+        client: &ClientInfo,
+    ) -> Result<AddressSettings> {
+        let entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&entry, credentials)?;
+
+        let update = self.check_settings_update(update)?;
+
+        let previous_instructions = entry.payment_instructions();
+        let mut settings = entry.settings;
+        update.apply(&mut settings);
+        let instructions =
+            settings.payment_instructions(entry.destinations.iter().map(|d| &d.destination));
+        if instructions.is_some() {
+            bip353::record_name(username, domain)?;
+        }
+        self.repo
+            .update_payment_address_settings(domain, username, settings.clone())
+            .await?;
+
+        if instructions != previous_instructions {
+            self.push_bip353_record(domain, username, instructions.as_ref())
+                .await;
+        }
+
+        self.record_event(
+            domain,
+            username,
+            AddressEventKind::SettingsUpdated,
+            client,
+            None,
+            None,
+        )
+        .await;
+
+        Ok(settings)
+    }
+
+    async fn list_lnaddr_comments(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<PaymentComment>> {
+        let entry = self.authorize(domain, username, credentials).await?;
+
+        self.repo
+            .list_payment_comments(entry.id, MAX_OWNER_COMMENTS)
+            .await
+    }
+
+    async fn list_lnaddr_payer_data(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<PayerData>> {
+        let entry = self.authorize(domain, username, credentials).await?;
+
+        self.repo
+            .list_payer_data(entry.id, MAX_OWNER_PAYER_DATA)
+            .await
+    }
+
+    async fn list_lnaddr_events(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<AddressEvent>> {
+        let entry = self.authorize(domain, username, credentials).await?;
+
+        // Only show events of the current registration, not those of previous owners of the name
+        let events = self
+            .repo
+            .list_address_events(AddressEventFilter {
+                domain: Some(domain.to_owned()),
+                username: Some(username.to_owned()),
+                limit: MAX_OWNER_EVENTS,
+                ..Default::default()
+            })
+            .await?
+            .into_iter()
+            .filter(|event| event.created_at >= entry.created_at)
+            .collect();
+
+        Ok(events)
+    }
+
+    async fn link_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        owner_id: Option<i32>,
+        client: &ClientInfo,
+    ) -> Result<()> {
+        let entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&entry, credentials)?;
+
+        self.repo
+            .update_payment_address_owner(domain, username, owner_id)
+            .await?;
+
+        let kind = match owner_id {
+            Some(_) => AddressEventKind::Linked,
+            None => AddressEventKind::Unlinked,
+        };
+        self.record_event(domain, username, kind, client, None, None)
+            .await;
+
+        Ok(())
+    }
+
+    async fn list_owner_lnaddrs(&self, owner_id: i32) -> Result<Vec<LnaddrDetails>> {
+        let now = SystemTime::now();
+        self.repo
+            .list_owner_payment_addresses(owner_id)
+            .await?
+            .into_iter()
+            .filter(|entry| !entry.is_expired(now))
+            .map(Self::lnaddr_details)
+            .collect()
+    }
+
+    async fn bulk_manage_lnaddrs(
+        &self,
+        owner_id: i32,
+        lnaddrs: &[String],
+        action: &BulkAction,
+        client: &ClientInfo,
+    ) -> Result<Vec<BulkResult>> {
+        let credentials = Credentials::Owner(owner_id);
+        let mut results = Vec::with_capacity(lnaddrs.len());
+        for lnaddr in lnaddrs {
+            let outcome = match lnaddr.split_once('@') {
+                Some((username, domain)) => match action {
+                    BulkAction::Renew if self.renewal_price_msat.is_some() => {
+                        Err(anyhow!("Renewals are paid, renew each address on its own"))
+                    }
+                    BulkAction::Renew => self
+                        .renew_lnaddr(domain, username, &credentials, None, client)
+                        .await
+                        .map(|_| ()),
+                    BulkAction::Update(routing) => {
+                        self.update_lnaddr(domain, username, &credentials, routing, client)
+                            .await
+                    }
+                    BulkAction::Settings(update) => self
+                        .update_lnaddr_settings(
+                            domain,
+                            username,
+                            &credentials,
+                            update.clone(),
+                            client,
+                        )
+                        .await
+                        .map(|_| ()),
+                    BulkAction::Unlink => {
+                        self.link_lnaddr(domain, username, &credentials, None, client)
+                            .await
+                    }
+                    BulkAction::Status(status) => {
+                        self.set_lnaddr_status(domain, username, &credentials, *status, client)
+                            .await
+                    }
+                    BulkAction::Remove => {
+                        self.remove_lnaddr(domain, username, &credentials, client)
+                            .await
+                    }
+                },
+                None => Err(anyhow!("Invalid lightning address {lnaddr}")),
+            };
+            results.push(BulkResult {
+                lnaddr: lnaddr.clone(),
+                error: outcome.err().map(|e| e.to_string()),
+            });
+        }
+
+        Ok(results)
+    }
+
+    async fn set_lnaddr_webhook(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        url: Option<&str>,
+        client: &ClientInfo,
+    ) -> Result<Option<String>> {
+        let entry = self.authorize(domain, username, credentials).await?;
+        Self::ensure_not_suspended(&entry, credentials)?;
+
+        if let Some(url) = url {
+            webhook::check_public_url(url).await?;
+        }
+        let webhook = url.map(|url| AddressWebhook {
+            url: url.to_owned(),
+            secret: webhook::generate_secret(),
+        });
+        self.repo
+            .update_payment_address_webhook(domain, username, webhook.as_ref())
+            .await?;
+
+        // Also lets owners check that the new endpoint receives webhooks
+        self.record_event(
+            domain,
+            username,
+            AddressEventKind::WebhookUpdated,
+            client,
+            None,
+            None,
+        )
+        .await;
+
+        Ok(webhook.map(|webhook| webhook.secret))
+    }
+
+    async fn list_lnaddr_webhook_deliveries(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let entry = self.authorize(domain, username, credentials).await?;
+
+        self.repo
+            .list_webhook_deliveries(Some(entry.id), MAX_OWNER_WEBHOOK_DELIVERIES)
+            .await
+    }
+
+    async fn admin_list_webhook_deliveries(&self, limit: i64) -> Result<Vec<WebhookDelivery>> {
+        self.repo.list_webhook_deliveries(None, limit).await
+    }
+
+    async fn deliver_webhooks(&self) -> Result<usize> {
+        let now = SystemTime::now();
+        let due = self
+            .repo
+            .list_due_webhook_events(now, WEBHOOK_BATCH_SIZE)
+            .await?;
+        let results =
+            futures::future::join_all(due.iter().map(|event| self.send_webhook(event))).await;
+
+        let mut delivered = 0;
+        for (event, result) in due.into_iter().zip(results) {
+            let attempt = event.attempts + 1;
+            let (status_code, error) = match result {
+                Ok(status) if status.is_success() => (Some(status.as_u16()), None),
+                Ok(status) => (
+                    Some(status.as_u16()),
+                    Some(format!("Endpoint responded with {status}")),
+                ),
+                Err(e) => (None, Some(format!("{e:#}"))),
+            };
+            let success = error.is_none();
+            if let Some(error) = &error {
+                warn!(
+                    %error,
+                    url=%event.url,
+                    event_id=%event.event_id,
+                    attempt,
+                    "Failed to deliver webhook",
+                );
+            }
+            if let Err(e) = self
+                .repo
+                .add_webhook_delivery(
+                    NewWebhookDelivery {
+                        payment_address_id: event.payment_address_id,
+                        url: event.url.clone(),
+                        event_id: event.event_id.clone(),
+                        event: event.event.clone(),
+                        attempt,
+                        status_code,
+                        error,
+                        delivered: success,
+                    },
+                    now - WEBHOOK_LOG_RETENTION,
+                )
+                .await
+            {
+                warn!(error=%e, event_id=%event.event_id, "Failed to log webhook delivery");
+            }
+
+            if success {
+                self.repo.remove_webhook_event(event.id).await?;
+                delivered += 1;
+            } else if attempt >= WEBHOOK_MAX_ATTEMPTS {
+                warn!(url=%event.url, event_id=%event.event_id, "Giving up on delivering webhook");
+                self.repo.remove_webhook_event(event.id).await?;
+            } else {
+                self.repo
+                    .retry_webhook_event(event.id, now + webhook::retry_delay(attempt))
+                    .await?;
+            }
+        }
+
+        Ok(delivered)
+    }
+
+    async fn check_unsettled_invoices(&self) -> Result<usize> {
+        let pending = self
+            .repo
+            .list_unsettled_invoices(
+                SystemTime::now() - INVOICE_CHECK_WINDOW,
+                !self.webhook_urls.is_empty(),
+            )
+            .await?;
+
+        let mut paid = 0;
+        for invoice in pending {
+            let verify_id = invoice.verify_id.clone();
+            match self.refresh_invoice_status(invoice).await {
+                Ok(invoice) if invoice.settled => paid += 1,
+                Ok(_) => {}
+                Err(e) => warn!(error=%e, %verify_id, "Failed to check invoice"),
+            }
+        }
+
+        Ok(paid)
+    }
+
+    async fn create_pow_challenge(&self) -> Result<Option<PowChallenge>> {
+        if self.register_pow_difficulty == 0 {
+            return Ok(None);
+        }
+        let nonce = pow::generate_nonce();
+        self.repo
+            .add_pow_challenge(&nonce, SystemTime::now() - POW_CHALLENGE_LIFETIME)
+            .await?;
+
+        Ok(Some(PowChallenge {
+            nonce,
+            difficulty: self.register_pow_difficulty,
+        }))
+    }
+
+    async fn verify_pow(&self, nonce: Option<&str>, solution: Option<&str>) -> Result<()> {
+        if self.register_pow_difficulty == 0 {
+            return Ok(());
+        }
+        let (Some(nonce), Some(solution)) = (nonce, solution) else {
+            bail!("Registering requires a proof of work");
+        };
+        // Checked before taking the challenge so wrong solutions don't use it up
+        pow::verify(nonce, solution, self.register_pow_difficulty)?;
+        let taken = self
+            .repo
+            .take_pow_challenge(nonce, SystemTime::now() - POW_CHALLENGE_LIFETIME)
+            .await?;
+        ensure!(taken, "Unknown or expired challenge");
+
+        Ok(())
+    }
+
+    async fn create_auth_challenge(&self, domain: &str) -> Result<AuthChallenge> {
+        let k1 = lnurl_auth::generate_k1();
+        let lnurl = lnurl_auth::login_lnurl(domain, &k1)?;
+        self.repo
+            .add_auth_challenge(&k1, SystemTime::now() - AUTH_CHALLENGE_LIFETIME)
+            .await?;
+
+        Ok(AuthChallenge { k1, lnurl })
+    }
+
+    async fn verify_auth_challenge(&self, k1: &str, sig: &str, key: &str) -> Result<()> {
+        let linking_key = lnurl_auth::verify(k1, sig, key)?;
+        let signed = self
+            .repo
+            .sign_auth_challenge(
+                k1,
+                &linking_key,
+                SystemTime::now() - AUTH_CHALLENGE_LIFETIME,
+            )
+            .await?;
+        ensure!(signed, "Unknown or expired challenge");
+
+        Ok(())
+    }
+
+    async fn login(&self, k1: &str) -> Result<Option<OwnerSession>> {
+        let now = SystemTime::now();
+        let Some(linking_key) = self
+            .repo
+            .take_signed_auth_challenge(k1, now - AUTH_CHALLENGE_LIFETIME)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let owner = self
+            .repo
+            .get_or_add_owner_by_linking_key(&linking_key)
+            .await?;
+        self.start_owner_session(owner.id).await.map(Some)
+    }
+
+    async fn login_with_token(
+        &self,
         domain: &str,
         username: &str,
         authentication_token: &str,
+        client: &ClientInfo,
+    ) -> Result<OwnerSession> {
+        let credentials = Credentials::Token(authentication_token.to_owned());
+        let entry = self.authorize(domain, username, &credentials).await?;
+        ensure!(
+            !entry.is_expired(SystemTime::now()),
+            "Payment address {username}@{domain} has expired"
+        );
+
+        let owner_id = match entry.owner_id {
+            Some(owner_id) => {
+                // Addresses of accounts with their own login can't be used to enter them
+                let owner = self.repo.get_owner(owner_id).await?;
+                ensure!(
+                    !owner.is_some_and(|owner| owner.has_login()),
+                    "{username}@{domain} belongs to an account using LNURL-auth or Nostr, log in with it instead"
+                );
+                owner_id
+            }
+            None => {
+                let owner = self.repo.add_owner().await?;
+                self.link_lnaddr(domain, username, &credentials, Some(owner.id), client)
+                    .await?;
+                owner.id
+            }
+        };
+        self.start_owner_session(owner_id).await
+    }
+
+    async fn get_nostr_owner(&self, pubkey: &str) -> Result<Owner> {
+        self.repo.get_or_add_owner_by_nostr_pubkey(pubkey).await
+    }
+
+    async fn find_nostr_owner(&self, pubkey: &str) -> Result<Option<Owner>> {
+        self.repo.get_owner_by_nostr_pubkey(pubkey).await
+    }
+
+    async fn get_owner_session(&self, session_token: &str) -> Result<Option<Owner>> {
+        self.repo
+            .get_owner_session(session_token, SystemTime::now())
+            .await
+    }
+
+    async fn logout(&self, session_token: &str) -> Result<()> {
+        self.repo.remove_owner_session(session_token).await
+    }
+
+    async fn admin_list_events(&self, filter: AddressEventFilter) -> Result<Vec<AddressEvent>> {
+        self.repo.list_address_events(filter).await
+    }
+
+    async fn admin_list_lnaddrs(&self, filter: PaymentAddressFilter) -> Result<LnaddrPage> {
+        let (entries, total) = self.repo.list_payment_addresses(filter).await?;
+        Ok(LnaddrPage {
+            lnaddrs: entries
+                .into_iter()
+                .map(Self::lnaddr_details)
+                .collect::<Result<_>>()?,
+            total,
+        })
+    }
+
+    async fn admin_list_lnaddrs_by_host(
+        &self,
+        host: &str,
+        domain: Option<&str>,
+    ) -> Result<Vec<LnaddrDetails>> {
+        let host = host.trim().to_lowercase();
+        let filter = PaymentAddressFilter {
+            domain: domain.map(str::to_owned),
+            search: None,
+            owner_id: None,
+            limit: i64::MAX,
+            offset: 0,
+        };
+        // LNURLs are stored bech32 encoded, so hosts can only be matched once decoded
+        let (entries, _) = self.repo.list_payment_addresses(filter).await?;
+        entries
+            .into_iter()
+            .filter(|entry| {
+                entry
+                    .destinations
+                    .iter()
+                    .any(|d| d.destination.host().is_some_and(|h| h == host))
+            })
+            .map(Self::lnaddr_details)
+            .collect()
+    }
+
+    async fn admin_check_destinations(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<Vec<DestinationHealth>>> {
+        let Some(entry) = self.repo.get_payment_address(domain, username).await? else {
+            return Ok(None);
+        };
+
+        let checks = entry.destinations.into_iter().map(|d| async move {
+            let started = Instant::now();
+            let manifest = self.fetch_manifest(&d.destination).await;
+            DestinationHealth {
+                sendable_msat: manifest
+                    .as_ref()
+                    .ok()
+                    .map(|manifest| (manifest.min_sendable, manifest.max_sendable)),
+                error: manifest.err().map(|e| e.to_string()),
+                elapsed: started.elapsed(),
+                destination: d.destination,
+            }
+        });
+
+        Ok(Some(futures::future::join_all(checks).await))
+    }
+
+    async fn admin_list_reports(&self) -> Result<Vec<AddressReport>> {
+        self.repo.list_open_address_reports().await
+    }
+
+    async fn admin_resolve_reports(
+        &self,
+        domain: &str,
+        username: &str,
+        suspend: bool,
+        client: &ClientInfo,
+    ) -> Result<()> {
+        let Some(entry) = self.repo.get_payment_address(domain, username).await? else {
+            bail!("Unknown payment address {username}@{domain}");
+        };
+        self.repo.resolve_address_reports(entry.id).await?;
+
+        // Dismissing reports leaves addresses paused by their owner paused
+        let status = match suspend {
+            true => AddressStatus::Suspended,
+            false if entry.status == AddressStatus::Suspended => AddressStatus::Active,
+            false => return Ok(()),
+        };
+        self.set_lnaddr_status(domain, username, &Credentials::Admin, status, client)
+            .await
+    }
+
+    async fn admin_lnaddr_stats(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<PaymentAddressStats>> {
+        let Some(entry) = self.repo.get_payment_address(domain, username).await? else {
+            return Ok(None);
+        };
+        self.repo.get_payment_address_stats(&entry).await.map(Some)
+    }
+
+    async fn admin_list_reserved_names(&self, domain: Option<&str>) -> Result<Vec<ReservedName>> {
+        self.repo.list_reserved_names(domain).await
+    }
+
+    async fn admin_reserve_name(
+        &self,
+        domain: &str,
+        username: &str,
+        reason: Option<&str>,
     ) -> Result<()> {
+        ensure!(
+            self.domains.iter().any(|d| d == domain),
+            "Unsupported domain: {domain}"
+        );
+        self.repo
+            .add_reserved_name(domain, &username.to_lowercase(), reason)
+            .await
+    }
+
+    async fn admin_release_name(&self, domain: &str, username: &str) -> Result<bool> {
         self.repo
-            .remove_payment_address(domain, username, authentication_token)
+            .remove_reserved_name(domain, &username.to_lowercase())
             .await
     }
+
+    async fn admin_remove_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        client: &ClientInfo,
+    ) -> Result<()> {
+        let Some(removed) = self.repo.remove_payment_address(domain, username).await? else {
+            bail!("Unknown payment address {username}@{domain}");
+        };
+        if removed.payment_instructions().is_some() {
+            self.push_bip353_record(domain, username, None).await;
+        }
+
+        self.record_removal(&removed, AddressEventKind::AdminRemoved, client)
+            .await;
+
+        Ok(())
+    }
+
+    async fn admin_bip353_zone(&self, domain: &str) -> Result<String> {
+        ensure!(
+            self.domains.iter().any(|d| d == domain),
+            "Unsupported domain: {domain}"
+        );
+
+        let records = self
+            .repo
+            .list_bip353_payment_addresses(domain)
+            .await?
+            .iter()
+            .filter_map(|entry| {
+                let instructions = entry.payment_instructions()?;
+                Self::bip353_record(domain, &entry.username, &instructions)
+                    .inspect_err(|e| {
+                        warn!(
+                            error=%e,
+                            %domain,
+                            username=%entry.username,
+                            "Skipping BIP-353 record"
+                        )
+                    })
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+
+        Ok(bip353::zone_file(domain, self.bip353_ttl, &records))
+    }
+
+    async fn admin_sync_bip353(&self, domain: &str) -> Result<usize> {
+        ensure!(
+            self.domains.iter().any(|d| d == domain),
+            "Unsupported domain: {domain}"
+        );
+        let Some(updater) = &self.dns_updater else {
+            bail!("DNS updates are not configured");
+        };
+
+        let entries = self.repo.list_bip353_payment_addresses(domain).await?;
+        let mut pushed = 0;
+        for entry in &entries {
+            let Some(instructions) = entry.payment_instructions() else {
+                continue;
+            };
+            let record = Self::bip353_record(domain, &entry.username, &instructions)?;
+            updater.update(domain, &record.name, Some(&record)).await?;
+            pushed += 1;
+        }
+
+        Ok(pushed)
+    }
+}
+
+/// Orders destinations by the routing policy, the first one being preferred and the remaining ones
+/// serving as fallbacks in their configured order
+fn routing_order(
+    policy: RoutingPolicy,
+    destinations: &[AddressDestination],
+) -> Vec<&AddressDestination> {
+    let preferred = match policy {
+        RoutingPolicy::Failover => None,
+        RoutingPolicy::WeightedRandom => WeightedIndex::new(destinations.iter().map(|d| d.weight))
+            .ok()
+            .map(|index| index.sample(&mut rand::thread_rng())),
+        // Pick the destination that is furthest behind its weighted share of selections, this
+        // keeps the rotation going across restarts since selections are persisted. Destinations
+        // without weight only serve as fallbacks.
+        RoutingPolicy::RoundRobin => destinations
+            .iter()
+            .enumerate()
+            .filter(|(_, destination)| destination.weight > 0)
+            .min_by(|(_, a), (_, b)| {
+                let a_share = u128::from(a.selection_count) * u128::from(b.weight);
+                let b_share = u128::from(b.selection_count) * u128::from(a.weight);
+                a_share.cmp(&b_share)
+            })
+            .map(|(idx, _)| idx),
+    };
+
+    let mut ordered = destinations.iter().collect::<Vec<_>>();
+    if let Some(idx) = preferred {
+        let destination = ordered.remove(idx);
+        ordered.insert(0, destination);
+    }
+    ordered
+}
+
+/// Merges the manifests of reachable destinations, in routing order, into one with the union of
+/// their sendable ranges and the metadata of the first one
+fn union_of_manifests(reachable: Vec<PayManifest>) -> Result<PayManifest> {
+    let min_sendable = reachable.iter().map(|m| m.min_sendable).min();
+    let max_sendable = reachable.iter().map(|m| m.max_sendable).max();
+    let mut manifest = reachable
+        .into_iter()
+        .next()
+        .context("No destination is reachable")?;
+    manifest.min_sendable = min_sendable.unwrap_or(manifest.min_sendable);
+    manifest.max_sendable = max_sendable.unwrap_or(manifest.max_sendable);
+
+    Ok(manifest)
+}
+
+fn format_destinations<'a>(
+    destinations: impl IntoIterator<Item = &'a DestinationPaymentAddress>,
+) -> String {
+    destinations
+        .into_iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Fails for addresses that exist but must not be paid, with a message for the payer's wallet
+fn ensure_served(entry: &PaymentAddress) -> Result<()> {
+    match entry.status {
+        AddressStatus::Active => Ok(()),
+        AddressStatus::Paused => bail!(
+            "{}@{} has been paused by its owner",
+            entry.username,
+            entry.domain
+        ),
+        AddressStatus::Suspended => bail!(
+            "{}@{} has been suspended by the operator",
+            entry.username,
+            entry.domain
+        ),
+    }
+}
+
+fn sats_to_msat(sats: u64) -> Result<u64> {
+    sats.checked_mul(1000).context("Amount too large")
+}
+
+/// Strips control characters, which have no place in a payment comment and could mess with
+/// terminals or logs of upstreams and owners
+fn sanitize_comment(comment: &str) -> String {
+    comment
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// Truncates a comment to at most `max_chars` characters, never splitting a character
+fn truncate_comment(comment: &str, max_chars: usize) -> &str {
+    comment
+        .char_indices()
+        .nth(max_chars)
+        .map_or(comment, |(idx, _)| &comment[..idx])
+}
+
+/// Strips control characters from upstream success action texts and limits their length
+fn sanitize_success_text(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_SUCCESS_ACTION_TEXT_LENGTH)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// Payer data requested in proxied manifests. LUD-18 wallets expect the invoice to commit to
+/// the manifest metadata followed by the payer data they sent, so only fields the upstream
+/// requests and commits to can be asked for, the owner may only make them mandatory. Invoices
+/// for offers are issued by the node backend, which commits to whatever the owner requests.
+fn requested_payer_data(
+    entry: &PaymentAddress,
+    destination: &DestinationPaymentAddress,
+    upstream: Option<&PayerDataRequest>,
+) -> Option<PayerDataRequest> {
+    if matches!(destination, DestinationPaymentAddress::Bolt12Offer(_)) {
+        return entry.settings.payer_data;
+    }
+
+    let upstream = upstream?;
+    let requested = match &entry.settings.payer_data {
+        Some(own) => own.merge(upstream).restrict_to(upstream),
+        None => *upstream,
+    };
+    (!requested.is_empty()).then_some(requested)
+}
+
+/// Nostr pubkey signing zap receipts for invoices of the destination, if zaps are supported.
+/// Invoices of the node backend commit to the zap request, as do invoices of upstreams supporting
+/// zaps, which it is forwarded to. lnaddrd signs receipts of both with `own`, without a key of
+/// its own upstreams publish receipts with theirs.
+fn zap_pubkey(
+    own: Option<String>,
+    destination: &DestinationPaymentAddress,
+    upstream: &PayManifest,
+) -> Option<String> {
+    if matches!(destination, DestinationPaymentAddress::Bolt12Offer(_)) {
+        return own;
+    }
+
+    if upstream.extra.get("allowsNostr") != Some(&Value::Bool(true)) {
+        return None;
+    }
+    own.or_else(|| {
+        upstream
+            .extra
+            .get("nostrPubkey")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+    })
+}
+
+/// Description committed to by invoices issued by lnaddrd: the zap request for zaps, otherwise
+/// the metadata followed by the payer data exactly as the payer sent it
+fn invoice_description(
+    metadata: &str,
+    zap_request: Option<&str>,
+    payer_data: Option<&str>,
+) -> String {
+    match (zap_request, payer_data) {
+        (Some(zap_request), _) => zap_request.to_owned(),
+        (None, Some(payer_data)) => format!("{metadata}{payer_data}"),
+        (None, None) => metadata.to_owned(),
+    }
+}
+
+/// Parses and validates LUD-18 payer data as sent by the payer
+fn parse_payer_data(payer_data: &str) -> Result<Map<String, Value>> {
+    let fields = serde_json::from_str::<Map<String, Value>>(payer_data)
+        .context("Payer data is not a JSON object")?;
+
+    for (key, value) in &fields {
+        match key.as_str() {
+            "name" | "identifier" | "email" => {
+                let value = value
+                    .as_str()
+                    .with_context(|| format!("Payer data field {key} is not a string"))?;
+                ensure!(
+                    !value.is_empty()
+                        && value.chars().count() <= MAX_PAYER_DATA_FIELD_LENGTH
+                        && !value.chars().any(char::is_control),
+                    "Invalid payer data field {key}"
+                );
+                ensure!(
+                    key == "name" || value.contains('@'),
+                    "Payer data field {key} is not an internet identifier"
+                );
+            }
+            "pubkey" => {
+                value
+                    .as_str()
+                    .and_then(|pubkey| secp256k1::PublicKey::from_str(pubkey).ok())
+                    .context("Payer data field pubkey is not a valid public key")?;
+            }
+            _ => bail!("Unsupported payer data field {key}"),
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Checks that payer data only contains requested fields and all mandatory ones
+fn check_payer_data(
+    fields: Option<&Map<String, Value>>,
+    requested: Option<&PayerDataRequest>,
+) -> Result<()> {
+    let Some(requested) = requested else {
+        ensure!(fields.is_none(), "Payer data was not requested");
+        return Ok(());
+    };
+
+    let requested_fields = requested.fields();
+    for key in fields.into_iter().flat_map(Map::keys) {
+        ensure!(
+            requested_fields
+                .iter()
+                .any(|(name, field)| name == key && field.is_some()),
+            "Payer data field {key} was not requested"
+        );
+    }
+    for (name, field) in requested_fields {
+        if field.is_some_and(|field| field.mandatory) {
+            ensure!(
+                fields.is_some_and(|fields| fields.contains_key(name)),
+                "Payer data field {name} is mandatory"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{IPaymentAddressRepository, PayerDataField};
+    use clap::Parser;
+
+    fn destination(id: i32, weight: u32, selection_count: u64) -> AddressDestination {
+        AddressDestination {
+            id,
+            destination: DestinationPaymentAddress::LnAddress {
+                user: format!("user{id}"),
+                domain: "example.com".to_owned(),
+            },
+            weight,
+            selection_count,
+            last_selected_at: None,
+        }
+    }
+
+    fn ids(ordered: &[&AddressDestination]) -> Vec<i32> {
+        ordered.iter().map(|destination| destination.id).collect()
+    }
+
+    fn manifest(min_sendable: u64, max_sendable: u64, metadata: &str) -> PayManifest {
+        PayManifest {
+            callback: "https://example.com/callback".to_owned(),
+            max_sendable,
+            min_sendable,
+            tag: "payRequest".to_owned(),
+            metadata: metadata.to_owned(),
+            comment_allowed: None,
+            payer_data: None,
+            extra: Map::new(),
+        }
+    }
+
+    fn payment_address() -> PaymentAddress {
+        PaymentAddress {
+            id: 1,
+            username: "alice".to_owned(),
+            domain: "example.com".to_owned(),
+            destinations: vec![destination(1, 1, 0)],
+            routing_policy: RoutingPolicy::default(),
+            amount_rules: Vec::new(),
+            authentication_token: "token".to_owned(),
+            created_at: UNIX_EPOCH,
+            updated_at: UNIX_EPOCH,
+            expires_at: None,
+            settings: AddressSettings::default(),
+            owner_id: None,
+            status: AddressStatus::default(),
+            webhook: None,
+        }
+    }
+
+    /// Payer data request from a base 3 number, each digit being a field that is not requested,
+    /// optional or mandatory
+    fn payer_data_request(mut n: u32) -> PayerDataRequest {
+        let mut field = || {
+            let field = match n % 3 {
+                0 => None,
+                state => Some(PayerDataField {
+                    mandatory: state == 2,
+                }),
+            };
+            n /= 3;
+            field
+        };
+        PayerDataRequest {
+            name: field(),
+            pubkey: field(),
+            identifier: field(),
+            email: field(),
+        }
+    }
+
+    /// Repository holding a single address, any change to it is unexpected
+    struct StoredAddress(PaymentAddress);
+
+    #[async_trait::async_trait]
+    impl IPaymentAddressRepository for StoredAddress {
+        async fn get_payment_address(
+            &self,
+            domain: &str,
+            username: &str,
+        ) -> Result<Option<PaymentAddress>> {
+            Ok(Some(self.0.clone())
+                .filter(|entry| entry.domain == domain && entry.username == username))
+        }
+        async fn add_payment_address(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _routing: Routing,
+            _settings: AddressSettings,
+            _owner_id: Option<i32>,
+            _authentication_token: &str,
+            _expires_at: Option<SystemTime>,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn remove_payment_address(
+            &self,
+            _domain: &str,
+            _username: &str,
+        ) -> Result<Option<PaymentAddress>> {
+            unreachable!()
+        }
+        async fn update_payment_address_routing(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _routing: Routing,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn update_payment_address_settings(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _settings: AddressSettings,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn add_payment_comment(
+            &self,
+            _payment_address_id: i32,
+            _amount_msat: u64,
+            _comment: &str,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_payment_comments(
+            &self,
+            _payment_address_id: i32,
+            _limit: i64,
+        ) -> Result<Vec<PaymentComment>> {
+            unreachable!()
+        }
+        async fn add_payer_data(
+            &self,
+            _payment_address_id: i32,
+            _amount_msat: u64,
+            _payer_data: &str,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_payer_data(
+            &self,
+            _payment_address_id: i32,
+            _limit: i64,
+        ) -> Result<Vec<PayerData>> {
+            unreachable!()
+        }
+        async fn add_invoice(&self, _invoice: NewInvoice) -> Result<()> {
+            unreachable!()
+        }
+        async fn get_invoice(
+            &self,
+            _payment_address_id: i32,
+            _verify_id: &str,
+        ) -> Result<Option<Invoice>> {
+            unreachable!()
+        }
+        async fn settle_invoice(&self, _verify_id: &str, _preimage: Option<&str>) -> Result<bool> {
+            unreachable!()
+        }
+        async fn list_unsettled_invoices(
+            &self,
+            _created_after: SystemTime,
+            _all: bool,
+        ) -> Result<Vec<Invoice>> {
+            unreachable!()
+        }
+        async fn list_pending_zap_invoices(
+            &self,
+            _created_after: SystemTime,
+        ) -> Result<Vec<Invoice>> {
+            unreachable!()
+        }
+        async fn mark_zap_receipt_published(&self, _verify_id: &str) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_pending_offer_forwards(
+            &self,
+            _created_after: SystemTime,
+        ) -> Result<Vec<Invoice>> {
+            unreachable!()
+        }
+        async fn claim_offer_forward(&self, _verify_id: &str) -> Result<bool> {
+            unreachable!()
+        }
+        async fn release_offer_forward(&self, _verify_id: &str) -> Result<()> {
+            unreachable!()
+        }
+        async fn record_destination_selection(&self, _destination_id: i32) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_bip353_payment_addresses(
+            &self,
+            _domain: &str,
+        ) -> Result<Vec<PaymentAddress>> {
+            unreachable!()
+        }
+        async fn update_authentication_token(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _authentication_token: &str,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn renew_payment_address(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _expires_at: SystemTime,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn add_renewal_invoice(
+            &self,
+            _payment_address_id: i32,
+            _payment_hash: &str,
+            _amount_msat: u64,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn redeem_renewal_invoice(
+            &self,
+            _payment_address_id: i32,
+            _payment_hash: &str,
+            _lifetime: Duration,
+            _now: SystemTime,
+        ) -> Result<Option<SystemTime>> {
+            unreachable!()
+        }
+        async fn remove_expired_payment_addresses(
+            &self,
+            _now: SystemTime,
+        ) -> Result<Vec<PaymentAddress>> {
+            unreachable!()
+        }
+        async fn add_address_event(&self, _event: NewAddressEvent) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_address_events(
+            &self,
+            _filter: AddressEventFilter,
+        ) -> Result<Vec<AddressEvent>> {
+            unreachable!()
+        }
+        async fn update_payment_address_owner(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _owner_id: Option<i32>,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_owner_payment_addresses(
+            &self,
+            _owner_id: i32,
+        ) -> Result<Vec<PaymentAddress>> {
+            unreachable!()
+        }
+        async fn add_owner(&self) -> Result<Owner> {
+            unreachable!()
+        }
+        async fn get_owner(&self, _owner_id: i32) -> Result<Option<Owner>> {
+            unreachable!()
+        }
+        async fn get_or_add_owner_by_linking_key(&self, _linking_key: &str) -> Result<Owner> {
+            unreachable!()
+        }
+        async fn get_or_add_owner_by_nostr_pubkey(&self, _pubkey: &str) -> Result<Owner> {
+            unreachable!()
+        }
+        async fn get_owner_by_nostr_pubkey(&self, _pubkey: &str) -> Result<Option<Owner>> {
+            unreachable!()
+        }
+        async fn add_auth_challenge(&self, _k1: &str, _expired_before: SystemTime) -> Result<()> {
+            unreachable!()
+        }
+        async fn sign_auth_challenge(
+            &self,
+            _k1: &str,
+            _linking_key: &str,
+            _created_after: SystemTime,
+        ) -> Result<bool> {
+            unreachable!()
+        }
+        async fn take_signed_auth_challenge(
+            &self,
+            _k1: &str,
+            _created_after: SystemTime,
+        ) -> Result<Option<String>> {
+            unreachable!()
+        }
+        async fn add_pow_challenge(&self, _nonce: &str, _expired_before: SystemTime) -> Result<()> {
+            unreachable!()
+        }
+        async fn take_pow_challenge(
+            &self,
+            _nonce: &str,
+            _created_after: SystemTime,
+        ) -> Result<bool> {
+            unreachable!()
+        }
+        async fn add_owner_session(
+            &self,
+            _token: &str,
+            _owner_id: i32,
+            _expires_at: SystemTime,
+            _now: SystemTime,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn get_owner_session(&self, _token: &str, _now: SystemTime) -> Result<Option<Owner>> {
+            unreachable!()
+        }
+        async fn remove_owner_session(&self, _token: &str) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_payment_addresses(
+            &self,
+            _filter: PaymentAddressFilter,
+        ) -> Result<(Vec<PaymentAddress>, i64)> {
+            unreachable!()
+        }
+        async fn get_payment_address_stats(
+            &self,
+            _payment_address: &PaymentAddress,
+        ) -> Result<PaymentAddressStats> {
+            unreachable!()
+        }
+        async fn add_reserved_name(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _reason: Option<&str>,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn remove_reserved_name(&self, _domain: &str, _username: &str) -> Result<bool> {
+            unreachable!()
+        }
+        async fn list_reserved_names(&self, _domain: Option<&str>) -> Result<Vec<ReservedName>> {
+            unreachable!()
+        }
+        async fn is_name_reserved(&self, _domain: &str, _username: &str) -> Result<bool> {
+            unreachable!()
+        }
+        async fn update_payment_address_status(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _status: AddressStatus,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn add_address_report(&self, _report: NewAddressReport) -> Result<()> {
+            unreachable!()
+        }
+        async fn count_address_reporters(&self, _payment_address_id: i32) -> Result<i64> {
+            unreachable!()
+        }
+        async fn list_open_address_reports(&self) -> Result<Vec<AddressReport>> {
+            unreachable!()
+        }
+        async fn resolve_address_reports(&self, _payment_address_id: i32) -> Result<usize> {
+            unreachable!()
+        }
+        async fn get_payment_address_by_id(&self, _id: i32) -> Result<Option<PaymentAddress>> {
+            unreachable!()
+        }
+        async fn update_payment_address_webhook(
+            &self,
+            _domain: &str,
+            _username: &str,
+            _webhook: Option<&AddressWebhook>,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn add_webhook_events(&self, _events: Vec<NewWebhookEvent>) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_due_webhook_events(
+            &self,
+            _now: SystemTime,
+            _limit: i64,
+        ) -> Result<Vec<WebhookEvent>> {
+            unreachable!()
+        }
+        async fn remove_webhook_event(&self, _id: i32) -> Result<()> {
+            unreachable!()
+        }
+        async fn retry_webhook_event(&self, _id: i32, _next_attempt_at: SystemTime) -> Result<()> {
+            unreachable!()
+        }
+        async fn add_webhook_delivery(
+            &self,
+            _delivery: NewWebhookDelivery,
+            _expired_before: SystemTime,
+        ) -> Result<()> {
+            unreachable!()
+        }
+        async fn list_webhook_deliveries(
+            &self,
+            _payment_address_id: Option<i32>,
+            _limit: i64,
+        ) -> Result<Vec<WebhookDelivery>> {
+            unreachable!()
+        }
+    }
+
+    /// Service over a suspended address, with expiring addresses to allow renewals
+    fn suspended_service() -> DirectLnaddrService {
+        let entry = PaymentAddress {
+            status: AddressStatus::Suspended,
+            settings: AddressSettings {
+                nostr: Some(NostrIdentity {
+                    pubkey: "ab".repeat(32),
+                    relays: Vec::new(),
+                }),
+                ..AddressSettings::default()
+            },
+            ..payment_address()
+        };
+        let config = Config::parse_from([
+            "lnaddrd",
+            "--domains",
+            "example.com",
+            "--database",
+            "postgres://localhost/lnaddrd",
+            "--address-lifetime-days",
+            "365",
+        ]);
+        DirectLnaddrService::new(Arc::new(StoredAddress(entry)), None, &config).unwrap()
+    }
+
+    fn assert_suspended<T: std::fmt::Debug>(result: Result<T>) {
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("suspended by the operator"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn owners_cant_change_suspended_addresses() {
+        let service = suspended_service();
+        let client = ClientInfo::default();
+        let token = Credentials::Token("token".to_owned());
+        let (domain, username) = ("example.com", "alice");
+
+        assert_suspended(
+            service
+                .rotate_token(domain, username, &token, &client)
+                .await,
+        );
+        assert_suspended(
+            service
+                .renew_lnaddr(domain, username, &token, None, &client)
+                .await,
+        );
+        assert_suspended(
+            service
+                .link_lnaddr(domain, username, &token, Some(2), &client)
+                .await,
+        );
+        assert_suspended(
+            service
+                .link_lnaddr(domain, username, &token, None, &client)
+                .await,
+        );
+        assert_suspended(
+            service
+                .remove_lnaddr(domain, username, &token, &client)
+                .await,
+        );
+        assert_suspended(
+            service
+                .update_lnaddr_settings(
+                    domain,
+                    username,
+                    &token,
+                    SettingsUpdate::default(),
+                    &client,
+                )
+                .await,
+        );
+        assert_suspended(
+            service
+                .set_lnaddr_webhook(
+                    domain,
+                    username,
+                    &token,
+                    Some("https://1.1.1.1/hook"),
+                    &client,
+                )
+                .await,
+        );
+        assert_suspended(
+            service
+                .set_lnaddr_status(domain, username, &token, AddressStatus::Active, &client)
+                .await,
+        );
+    }
+
+    #[tokio::test]
+    async fn suspended_addresses_have_no_nostr_identity() {
+        let service = suspended_service();
+        assert!(
+            service
+                .get_nostr_identity("example.com", "alice")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn only_the_operator_modifies_suspended_addresses() {
+        let owner = Credentials::Owner(1);
+        let token = Credentials::Token("token".to_owned());
+        let mut entry = payment_address();
+        for status in [AddressStatus::Active, AddressStatus::Paused] {
+            entry.status = status;
+            for credentials in [&owner, &token, &Credentials::Admin] {
+                assert!(DirectLnaddrService::ensure_not_suspended(&entry, credentials).is_ok());
+            }
+        }
+
+        entry.status = AddressStatus::Suspended;
+        assert!(DirectLnaddrService::ensure_not_suspended(&entry, &owner).is_err());
+        assert!(DirectLnaddrService::ensure_not_suspended(&entry, &token).is_err());
+        assert!(DirectLnaddrService::ensure_not_suspended(&entry, &Credentials::Admin).is_ok());
+    }
+
+    #[test]
+    fn failover_keeps_configured_order() {
+        let destinations = [
+            destination(1, 1, 9),
+            destination(2, 1, 0),
+            destination(3, 1, 0),
+        ];
+        assert_eq!(
+            ids(&routing_order(RoutingPolicy::Failover, &destinations)),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn round_robin_prefers_destination_behind_its_share() {
+        let destinations = [
+            destination(1, 1, 4),
+            destination(2, 3, 9),
+            destination(3, 1, 2),
+        ];
+        assert_eq!(
+            ids(&routing_order(RoutingPolicy::RoundRobin, &destinations)),
+            [3, 1, 2]
+        );
+
+        // Selections per unit of weight: 4 for the first, 3 for the second
+        let destinations = [destination(1, 1, 4), destination(2, 3, 9)];
+        assert_eq!(
+            ids(&routing_order(RoutingPolicy::RoundRobin, &destinations)),
+            [2, 1]
+        );
+    }
+
+    #[test]
+    fn zero_weight_destinations_are_never_preferred() {
+        let destinations = [
+            destination(1, 0, 0),
+            destination(2, 1, 5),
+            destination(3, 0, 0),
+        ];
+        assert_eq!(
+            ids(&routing_order(RoutingPolicy::RoundRobin, &destinations)),
+            [2, 1, 3]
+        );
+        for _ in 0..50 {
+            assert_eq!(
+                ids(&routing_order(RoutingPolicy::WeightedRandom, &destinations)),
+                [2, 1, 3]
+            );
+        }
+    }
+
+    #[test]
+    fn all_zero_weights_fall_back_to_configured_order() {
+        let destinations = [destination(1, 0, 3), destination(2, 0, 0)];
+        assert_eq!(
+            ids(&routing_order(RoutingPolicy::WeightedRandom, &destinations)),
+            [1, 2]
+        );
+        assert_eq!(
+            ids(&routing_order(RoutingPolicy::RoundRobin, &destinations)),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn routing_order_keeps_every_destination_as_fallback() {
+        let destinations = [
+            destination(1, 2, 1),
+            destination(2, 5, 0),
+            destination(3, 1, 7),
+        ];
+        for policy in [
+            RoutingPolicy::Failover,
+            RoutingPolicy::WeightedRandom,
+            RoutingPolicy::RoundRobin,
+        ] {
+            let mut ordered = ids(&routing_order(policy, &destinations));
+            ordered.sort();
+            assert_eq!(ordered, [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn union_manifest_spans_reachable_destinations() {
+        let manifest = union_of_manifests(vec![
+            manifest(10_000, 50_000, "first"),
+            manifest(1_000, 20_000, "second"),
+            manifest(5_000, 900_000, "third"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            (manifest.min_sendable, manifest.max_sendable),
+            (1_000, 900_000)
+        );
+        assert_eq!(manifest.metadata, "first");
+    }
+
+    #[test]
+    fn union_manifest_fails_if_all_destinations_are_unreachable() {
+        assert!(union_of_manifests(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn sanitize_comment_strips_control_characters() {
+        assert_eq!(
+            sanitize_comment("  thanks\u{1b}[31m for\nthe\u{0} coffee \t"),
+            "thanks[31m forthe coffee"
+        );
+        assert_eq!(sanitize_comment("\r\n\u{7f}"), "");
+        assert_eq!(sanitize_comment("zap ⚡ für dich"), "zap ⚡ für dich");
+    }
+
+    #[test]
+    fn truncate_comment_counts_characters() {
+        assert_eq!(truncate_comment("für ⚡⚡", 5), "für ⚡");
+        assert_eq!(truncate_comment("für ⚡⚡", 2), "fü");
+        assert_eq!(truncate_comment("für ⚡⚡", 6), "für ⚡⚡");
+        assert_eq!(truncate_comment("für ⚡⚡", 100), "für ⚡⚡");
+        assert_eq!(truncate_comment("für", 0), "");
+    }
+
+    #[test]
+    fn payer_data_is_only_requested_if_the_upstream_commits_to_it() {
+        let mut entry = payment_address();
+        entry.settings.payer_data = Some(payer_data_request(2));
+        let lnurl = &entry.destinations[0].destination;
+
+        assert_eq!(requested_payer_data(&entry, lnurl, None), None);
+        assert_eq!(
+            requested_payer_data(&entry, lnurl, Some(&PayerDataRequest::default())),
+            None
+        );
+    }
+
+    #[test]
+    fn payer_data_request_is_limited_to_upstream_fields() {
+        let lnurl = &destination(1, 1, 0).destination;
+        for own in 0..81 {
+            for upstream in 0..81 {
+                let mut entry = payment_address();
+                entry.settings.payer_data = (own > 0).then(|| payer_data_request(own));
+                let upstream = payer_data_request(upstream);
+                let requested =
+                    requested_payer_data(&entry, lnurl, Some(&upstream)).unwrap_or_default();
+
+                for ((_, field), ((_, upstream_field), (_, own_field))) in
+                    requested.fields().into_iter().zip(
+                        upstream
+                            .fields()
+                            .into_iter()
+                            .zip(payer_data_request(own).fields()),
+                    )
+                {
+                    assert_eq!(field.is_some(), upstream_field.is_some());
+                    if let Some(field) = field {
+                        let own_mandatory = own_field.is_some_and(|field| field.mandatory);
+                        assert_eq!(
+                            field.mandatory,
+                            upstream_field.unwrap().mandatory || own_mandatory
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn offers_request_payer_data_of_owner() {
+        let mut entry = payment_address();
+        entry.settings.payer_data = Some(payer_data_request(5));
+        let offer = DestinationPaymentAddress::from_str(
+            "lno1pgx9getnwss8vetrw3hhyuckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg",
+        )
+        .unwrap();
+
+        assert_eq!(
+            requested_payer_data(&entry, &offer, None),
+            Some(payer_data_request(5))
+        );
+    }
+
+    #[test]
+    fn invoice_description_hash_commits_to_payer_data() {
+        use bitcoin::hashes::{Hash, sha256};
+
+        let metadata = r#"[["text/plain","Pay alice"]]"#;
+        // Wallets hash the payer data exactly as sent, so it must not be re-serialized
+        let payer_data = r#"{ "name":"Bob",  "email":"bob@example.com" }"#;
+        let hash = |description: &str| sha256::Hash::hash(description.as_bytes());
+
+        assert_eq!(
+            hash(&invoice_description(metadata, None, Some(payer_data))),
+            hash(&format!("{metadata}{payer_data}"))
+        );
+        assert_eq!(
+            hash(&invoice_description(metadata, None, None)),
+            hash(metadata)
+        );
+        assert_eq!(
+            invoice_description(metadata, Some(r#"{"kind":9734}"#), Some(payer_data)),
+            r#"{"kind":9734}"#
+        );
+    }
+
+    #[test]
+    fn zaps_are_advertised_with_the_key_publishing_receipts() {
+        let own = Some("own".to_owned());
+        let lnurl = destination(1, 1, 0).destination;
+        let offer = DestinationPaymentAddress::from_str(
+            "lno1pgx9getnwss8vetrw3hhyuckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg",
+        )
+        .unwrap();
+        let mut upstream = manifest(1_000, 10_000, "");
+
+        // The upstream invoice wouldn't commit to the zap request
+        assert_eq!(zap_pubkey(own.clone(), &lnurl, &upstream), None);
+
+        upstream
+            .extra
+            .insert("allowsNostr".to_owned(), Value::Bool(true));
+        upstream.extra.insert(
+            "nostrPubkey".to_owned(),
+            Value::String("upstream".to_owned()),
+        );
+        // lnaddrd publishes receipts itself if it has a key
+        assert_eq!(
+            zap_pubkey(own.clone(), &lnurl, &upstream).as_deref(),
+            Some("own")
+        );
+        assert_eq!(
+            zap_pubkey(None, &lnurl, &upstream).as_deref(),
+            Some("upstream")
+        );
+
+        upstream
+            .extra
+            .insert("allowsNostr".to_owned(), Value::Bool(false));
+        assert_eq!(zap_pubkey(own.clone(), &lnurl, &upstream), None);
+
+        assert_eq!(zap_pubkey(own, &offer, &upstream).as_deref(), Some("own"));
+        assert_eq!(zap_pubkey(None, &offer, &upstream), None);
+    }
 }
@@ -1,13 +1,23 @@
 pub mod direct;
 
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
-use lnurl::pay::PayResponse;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::repository::DestinationPaymentAddress;
+use crate::bolt12::Offer;
+use crate::client::ClientInfo;
+use crate::repository::{
+    AddressDestination, AddressEvent, AddressEventFilter, AddressReport, AddressSettings,
+    AddressStatus, AmountRule, DestinationPaymentAddress, NostrIdentity, Owner, PayerData,
+    PayerDataRequest, PaymentAddressFilter, PaymentAddressStats, PaymentComment,
+    PaymentInstructions, ReservedName, RoutingPolicy, SuccessAction, WebhookDelivery,
+};
 
 pub type LnaddrService = Arc<dyn ILnaddrService + Send + Sync>;
 
@@ -15,31 +25,587 @@ pub type LnaddrService = Arc<dyn ILnaddrService + Send + Sync>;
 pub trait ILnaddrService {
     async fn list_domains(&self) -> Result<Vec<String>>;
 
+    /// LNURL-pay manifest of the address. Only manifests requested by wallets should
+    /// `record_selection` of the serving destination, not those merely displayed.
     async fn get_lnaddr_manifest(
         &self,
         domain: &str,
         username: &str,
-    ) -> Result<Option<PayResponse>>;
+        record_selection: bool,
+    ) -> Result<Option<PayManifest>>;
 
-    async fn get_destination(&self, domain: &str, username: &str) -> Result<Option<DestinationPaymentAddress>>;
+    /// Requests an invoice from the upstream on behalf of the payer, only available for addresses
+    /// whose callbacks are proxied
+    async fn get_invoice(
+        &self,
+        domain: &str,
+        username: &str,
+        request: InvoiceRequest,
+    ) -> Result<Option<InvoiceResponse>>;
+
+    /// Answers a LUD-21 verify request for an invoice issued through a proxied callback
+    async fn verify_invoice(
+        &self,
+        domain: &str,
+        username: &str,
+        verify_id: &str,
+    ) -> Result<Option<VerifyResponse>>;
+
+    /// Publishes NIP-57 zap receipts for zapped invoices that were paid, returns the number of
+    /// receipts published
+    async fn publish_zap_receipts(&self) -> Result<usize>;
+
+    /// Pays the BOLT12 offers owed for paid invoices of the node backend, returns the number of
+    /// forwarded payments
+    async fn forward_offer_payments(&self) -> Result<usize>;
+
+    /// Returns the BOLT12 offer of an address, as published via BIP-353
+    async fn get_offer(&self, domain: &str, username: &str) -> Result<Option<Offer>>;
+
+    /// Returns the NIP-05 identity attached to an address
+    async fn get_nostr_identity(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<NostrIdentity>>;
+
+    /// Returns the primary destination of an address
+    async fn get_destination(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<DestinationPaymentAddress>>;
+
+    async fn get_lnaddr_details(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<LnaddrDetails>>;
+
+    /// Stores an abuse report of an address, suspending the address once enough distinct clients
+    /// reported it
+    async fn report_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        reason: &str,
+        contact: Option<&str>,
+        client: &ClientInfo,
+    ) -> Result<()>;
 
+    /// Registers an address, assigning it to the account of a logged in owner if given
     async fn register_lnaddr(
         &self,
         domain: &str,
         username: &str,
-        destination: &str,
+        routing: &RoutingInput,
+        settings: SettingsUpdate,
+        owner_id: Option<i32>,
+        client: &ClientInfo,
     ) -> Result<RegisterResponse>;
 
     async fn remove_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        client: &ClientInfo,
+    ) -> Result<()>;
+
+    /// Replaces the destinations of an address and how they are chosen
+    async fn update_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        routing: &RoutingInput,
+        client: &ClientInfo,
+    ) -> Result<()>;
+
+    /// Replaces the authentication token of an address with a newly generated one
+    async fn rotate_token(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        client: &ClientInfo,
+    ) -> Result<RotateTokenResponse>;
+
+    /// Pauses or resumes serving an address. Only operators may suspend addresses or change the
+    /// status of suspended ones.
+    async fn set_lnaddr_status(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        status: AddressStatus,
+        client: &ClientInfo,
+    ) -> Result<()>;
+
+    /// Extends the lifetime of an expiring address by the configured address lifetime. If
+    /// renewals are paid, returns an invoice to pay unless `payment_hash` identifies a paid
+    /// renewal invoice of the address that wasn't redeemed yet.
+    async fn renew_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        payment_hash: Option<&str>,
+        client: &ClientInfo,
+    ) -> Result<RenewResponse>;
+
+    /// Removes all expired addresses, returns the number of removed addresses
+    async fn remove_expired_lnaddrs(&self) -> Result<usize>;
+
+    /// Updates the settings given in `update`, leaving the others untouched
+    async fn update_lnaddr_settings(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        update: SettingsUpdate,
+        client: &ClientInfo,
+    ) -> Result<AddressSettings>;
+
+    /// Lists stored payment comments of an address to its owner
+    async fn list_lnaddr_comments(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<PaymentComment>>;
+
+    /// Lists stored LUD-18 payer data of an address to its owner
+    async fn list_lnaddr_payer_data(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<PayerData>>;
+
+    /// Lists the event history of an address to its owner
+    async fn list_lnaddr_events(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<AddressEvent>>;
+
+    /// Assigns an address to an owner account, letting the owner manage it without the token, or
+    /// detaches it if `None`
+    async fn link_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        owner_id: Option<i32>,
+        client: &ClientInfo,
+    ) -> Result<()>;
+
+    /// Lists the unexpired addresses of an owner account
+    async fn list_owner_lnaddrs(&self, owner_id: i32) -> Result<Vec<LnaddrDetails>>;
+
+    /// Applies an action to several addresses of an owner account, `lnaddrs` given as
+    /// `username@domain`. Failing addresses don't stop the others, the outcome is reported per
+    /// address.
+    async fn bulk_manage_lnaddrs(
+        &self,
+        owner_id: i32,
+        lnaddrs: &[String],
+        action: &BulkAction,
+        client: &ClientInfo,
+    ) -> Result<Vec<BulkResult>>;
+
+    /// Sets the endpoint the webhooks of an address are sent to, or removes it if `None`. Returns
+    /// the secret generated to sign them with.
+    async fn set_lnaddr_webhook(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+        url: Option<&str>,
+        client: &ClientInfo,
+    ) -> Result<Option<String>>;
+
+    /// Lists the most recent attempts of delivering webhooks to the endpoint of an address to its
+    /// owner
+    async fn list_lnaddr_webhook_deliveries(
+        &self,
+        domain: &str,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Vec<WebhookDelivery>>;
+
+    /// Lists the most recent webhook delivery attempts to all endpoints, only to be exposed to
+    /// operators
+    async fn admin_list_webhook_deliveries(&self, limit: i64) -> Result<Vec<WebhookDelivery>>;
+
+    /// Delivers the webhooks in the outbox that are due, returning the number delivered
+    async fn deliver_webhooks(&self) -> Result<usize>;
+
+    /// Checks recent unpaid invoices of addresses receiving webhooks for payment, returning the
+    /// number found paid
+    async fn check_unsettled_invoices(&self) -> Result<usize>;
+
+    /// Creates a proof-of-work challenge for registering an address, `None` if registrations
+    /// don't require one
+    async fn create_pow_challenge(&self) -> Result<Option<PowChallenge>>;
+
+    /// Verifies the solution of a registration challenge, which can only be used once. Succeeds
+    /// without a solution if registrations don't require one.
+    async fn verify_pow(&self, nonce: Option<&str>, solution: Option<&str>) -> Result<()>;
+
+    /// Creates a LUD-04 challenge for logging into the management UI served on `domain`
+    async fn create_auth_challenge(&self, domain: &str) -> Result<AuthChallenge>;
+
+    /// Verifies a wallet's signature of a challenge, returning an error if it is invalid
+    async fn verify_auth_challenge(&self, k1: &str, sig: &str, key: &str) -> Result<()>;
+
+    /// Exchanges a signed challenge for a session of the owner account of the linking key, `None`
+    /// if it wasn't signed yet
+    async fn login(&self, k1: &str) -> Result<Option<OwnerSession>>;
+
+    /// Logs in with the authentication token of an address, as session of the address' owner
+    /// account. Addresses without owner are assigned to a new account, accounts with an
+    /// LNURL-auth or Nostr login can't be entered with a token.
+    async fn login_with_token(
         &self,
         domain: &str,
         username: &str,
         authentication_token: &str,
+        client: &ClientInfo,
+    ) -> Result<OwnerSession>;
+
+    /// Returns the owner account of a Nostr pubkey that authenticated a request with NIP-98,
+    /// creating it on first use
+    async fn get_nostr_owner(&self, pubkey: &str) -> Result<Owner>;
+
+    /// Returns the owner account of a Nostr pubkey if it has one, without creating it
+    async fn find_nostr_owner(&self, pubkey: &str) -> Result<Option<Owner>>;
+
+    /// Returns the owner of a valid session
+    async fn get_owner_session(&self, session_token: &str) -> Result<Option<Owner>>;
+
+    async fn logout(&self, session_token: &str) -> Result<()>;
+
+    /// Lists events of all addresses, only to be exposed to operators
+    async fn admin_list_events(&self, filter: AddressEventFilter) -> Result<Vec<AddressEvent>>;
+
+    /// Lists addresses matching the filter, only to be exposed to operators
+    async fn admin_list_lnaddrs(&self, filter: PaymentAddressFilter) -> Result<LnaddrPage>;
+
+    /// Addresses with a destination served by the given host, optionally only of one domain. Only
+    /// to be exposed to operators.
+    async fn admin_list_lnaddrs_by_host(
+        &self,
+        host: &str,
+        domain: Option<&str>,
+    ) -> Result<Vec<LnaddrDetails>>;
+
+    /// Fetches the manifests of all destinations of an address, only to be exposed to operators
+    async fn admin_check_destinations(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<Vec<DestinationHealth>>>;
+
+    /// Lists open abuse reports of all addresses, only to be exposed to operators
+    async fn admin_list_reports(&self) -> Result<Vec<AddressReport>>;
+
+    /// Closes the open reports of an address, suspending it or serving it again. Only to be
+    /// exposed to operators.
+    async fn admin_resolve_reports(
+        &self,
+        domain: &str,
+        username: &str,
+        suspend: bool,
+        client: &ClientInfo,
+    ) -> Result<()>;
+
+    /// Usage statistics of an address, only to be exposed to operators
+    async fn admin_lnaddr_stats(
+        &self,
+        domain: &str,
+        username: &str,
+    ) -> Result<Option<PaymentAddressStats>>;
+
+    /// Lists reserved usernames, of all domains if `domain` is `None`
+    async fn admin_list_reserved_names(&self, domain: Option<&str>) -> Result<Vec<ReservedName>>;
+
+    /// Keeps a username from being registered. Existing addresses are left in place.
+    async fn admin_reserve_name(
+        &self,
+        domain: &str,
+        username: &str,
+        reason: Option<&str>,
+    ) -> Result<()>;
+
+    /// Makes a reserved username available again, returns false if it wasn't reserved
+    async fn admin_release_name(&self, domain: &str, username: &str) -> Result<bool>;
+
+    /// Removes an address without requiring its authentication token, only to be exposed to
+    /// operators
+    async fn admin_remove_lnaddr(
+        &self,
+        domain: &str,
+        username: &str,
+        client: &ClientInfo,
     ) -> Result<()>;
+
+    /// Renders the BIP-353 records of a domain as zone file
+    async fn admin_bip353_zone(&self, domain: &str) -> Result<String>;
+
+    /// Pushes the BIP-353 records of all addresses of a domain to the DNS server, returns the
+    /// number of pushed records
+    async fn admin_sync_bip353(&self, domain: &str) -> Result<usize>;
+}
+
+/// Proof that the caller may manage an address
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    /// The authentication token issued when registering the address
+    Token(String),
+    /// Id of a logged in owner account the address is assigned to
+    Owner(i32),
+    /// Operator using the admin API, who may manage any address
+    Admin,
+}
+
+/// Page of addresses with the total number of matching addresses
+#[derive(Debug, Clone)]
+pub struct LnaddrPage {
+    pub lnaddrs: Vec<LnaddrDetails>,
+    pub total: i64,
+}
+
+/// Outcome of fetching the manifest of a destination
+#[derive(Debug, Clone)]
+pub struct DestinationHealth {
+    pub destination: DestinationPaymentAddress,
+    /// Sendable range of the manifest in msat, if it was fetched
+    pub sendable_msat: Option<(u64, u64)>,
+    pub error: Option<String>,
+    pub elapsed: Duration,
+}
+
+/// LUD-04 login challenge
+#[derive(Debug, Clone)]
+pub struct AuthChallenge {
+    pub k1: String,
+    /// Bech32 encoded login URL, uppercase for compact QR codes
+    pub lnurl: String,
+}
+
+/// Hashcash challenge of a registration: a solution must be found whose SHA-256 hash, prefixed
+/// with `{nonce}:`, has at least `difficulty` leading zero bits
+#[derive(Debug, Clone, Serialize)]
+pub struct PowChallenge {
+    pub nonce: String,
+    pub difficulty: u8,
+}
+
+/// Management session of a logged in owner account
+#[derive(Debug, Clone)]
+pub struct OwnerSession {
+    pub token: String,
+    pub owner_id: i32,
+    pub expires_at: SystemTime,
+}
+
+/// Action applied to several addresses of an owner at once
+#[derive(Debug, Clone)]
+pub enum BulkAction {
+    Renew,
+    /// Replaces the destinations of all addresses
+    Update(RoutingInput),
+    Settings(SettingsUpdate),
+    /// Detaches the addresses from the owner account
+    Unlink,
+    Status(AddressStatus),
+    Remove,
+}
+
+/// Outcome of a bulk action for one address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkResult {
+    pub lnaddr: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Destination as submitted by the owner of an address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DestinationInput {
+    pub destination: String,
+    pub weight: u32,
+}
+
+/// Amount rule as submitted by the owner of an address, matching amounts in
+/// `[min_sats, max_sats)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmountRuleInput {
+    pub min_sats: Option<u64>,
+    pub max_sats: Option<u64>,
+    /// Index into the destinations
+    pub destination: usize,
+}
+
+/// Destinations and routing rules as submitted by the owner of an address
+#[derive(Debug, Clone)]
+pub struct RoutingInput {
+    pub destinations: Vec<DestinationInput>,
+    pub policy: RoutingPolicy,
+    pub amount_rules: Vec<AmountRuleInput>,
+}
+
+/// Parameters of a LNURL-pay callback request
+#[derive(Debug, Clone)]
+pub struct InvoiceRequest {
+    pub amount_msat: u64,
+    /// Destination that served the manifest, if the callback was bound to one
+    pub destination_id: Option<i32>,
+    /// LUD-12 comment of the payer
+    pub comment: Option<String>,
+    /// LUD-18 payer data as sent by the payer, a JSON object
+    pub payer_data: Option<String>,
+    /// NIP-57 zap request event as sent by the payer
+    pub zap_request: Option<String>,
+}
+
+/// Partial update of the settings of an address, `None` fields are left unchanged
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SettingsUpdate {
+    pub store_comments: Option<bool>,
+    /// Payer data to request, an empty request stops requesting payer data
+    pub payer_data: Option<PayerDataRequest>,
+    /// Success action to show after payment, `null` removes it
+    #[serde(default, deserialize_with = "deserialize_update")]
+    pub success_action: Option<Option<SuccessAction>>,
+    /// NIP-05 identity to serve, `null` removes it
+    #[serde(default, deserialize_with = "deserialize_update")]
+    pub nostr: Option<Option<NostrIdentity>>,
+    /// BIP-353 payment instructions to publish, `null` removes them
+    #[serde(default, deserialize_with = "deserialize_update")]
+    pub bip353: Option<Option<PaymentInstructions>>,
+}
+
+/// Distinguishes a field explicitly set to `null` from a missing one in partial updates
+fn deserialize_update<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl SettingsUpdate {
+    pub fn apply(self, settings: &mut AddressSettings) {
+        if let Some(store_comments) = self.store_comments {
+            settings.store_comments = store_comments;
+        }
+        if let Some(payer_data) = self.payer_data {
+            settings.payer_data = (!payer_data.is_empty()).then_some(payer_data);
+        }
+        if let Some(success_action) = self.success_action {
+            settings.success_action = success_action;
+        }
+        if let Some(nostr) = self.nostr {
+            settings.nostr = nostr;
+        }
+        if let Some(bip353) = self.bip353 {
+            settings.bip353 = bip353;
+        }
+    }
+}
+
+/// LNURL-pay manifest, fields not known to lnaddrd are passed through from the upstream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayManifest {
+    pub callback: String,
+    #[serde(rename = "maxSendable")]
+    pub max_sendable: u64,
+    #[serde(rename = "minSendable")]
+    pub min_sendable: u64,
+    pub tag: String,
+    pub metadata: String,
+    #[serde(
+        rename = "commentAllowed",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub comment_allowed: Option<u32>,
+    /// LUD-18 payer data request. LUD-18 `auth` is not supported and dropped since its challenge
+    /// is bound to the upstream.
+    #[serde(rename = "payerData", default, skip_serializing_if = "Option::is_none")]
+    pub payer_data: Option<PayerDataRequest>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// LNURL-pay callback response, fields not known to lnaddrd are passed through from the upstream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoiceResponse {
+    pub pr: String,
+    #[serde(default)]
+    pub routes: Vec<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Payment status of an invoice as reported by LUD-21
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyResponse {
+    pub settled: bool,
+    pub preimage: Option<String>,
+    pub pr: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisterResponse {
     pub lnaddr: String,
     pub authentication_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotateTokenResponse {
+    pub lnaddr: String,
+    pub authentication_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenewResponse {
+    pub lnaddr: String,
+    /// New expiry time, or the current one if the renewal still has to be paid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// Invoice to pay before renewing again with its payment hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invoice: Option<RenewalInvoice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenewalInvoice {
+    pub pr: String,
+    pub payment_hash: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct LnaddrDetails {
+    pub lnaddr: String,
+    /// Bech32 encoded LNURL of the address' LNURL-pay endpoint, for wallets that can't resolve
+    /// lightning addresses. Uppercase, as it encodes more compactly in QR codes.
+    pub lnurl: String,
+    /// LUD-17 `lnurlp://` URL of the LNURL-pay endpoint
+    pub lud17: String,
+    /// Owner account the address is assigned to
+    pub owner_id: Option<i32>,
+    pub status: AddressStatus,
+    pub destinations: Vec<AddressDestination>,
+    pub routing_policy: RoutingPolicy,
+    pub amount_rules: Vec<AmountRule>,
+    pub settings: AddressSettings,
+    pub created_at: SystemTime,
+    pub expires_at: Option<SystemTime>,
 }
@@ -1,10 +1,21 @@
+use std::time::SystemTime;
+
 use crate::AppState;
-use crate::api::RegisterRequest;
-use crate::repository::DestinationPaymentAddress;
+use crate::client::ClientInfo;
+use crate::lnurl_auth;
+use crate::pow;
+use crate::repository::{
+    AddressStatus, AmountRule, DestinationPaymentAddress, NostrIdentity, Owner, PayerDataField,
+    PayerDataRequest, PaymentInstructions, RoutingPolicy, SuccessAction,
+};
+use crate::service::{
+    AmountRuleInput, BulkAction, Credentials, DestinationInput, OwnerSession, RoutingInput,
+    SettingsUpdate,
+};
 use axum::{
     Form,
-    extract::Path,
-    extract::State,
+    extract::{FromRequestParts, Host, Path, Query, State},
+    http::{header, request::Parts},
     response::{Html, IntoResponse, Redirect},
 };
 use maud::{DOCTYPE, Markup, html};
@@ -17,10 +28,351 @@ pub struct RegisterForm {
     domain: String,
     username: String,
     lnurl: String,
+    #[serde(default)]
+    routing_policy: RoutingPolicy,
+    /// Proof-of-work challenge and its solution, filled in by the form's script if required
+    pow_nonce: Option<String>,
+    pow_solution: Option<String>,
+}
+
+/// Cookie holding the session token of a logged in owner
+const SESSION_COOKIE: &str = "lnaddrd_session";
+
+/// Owner logged into the management UI, requests without a valid session are redirected to the
+/// login page
+pub struct OwnerLogin {
+    session_token: String,
+    owner: Owner,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for OwnerLogin {
+    type Rejection = Redirect;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let session_token = parts
+            .headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .find_map(|cookie| {
+                cookie
+                    .trim()
+                    .strip_prefix(SESSION_COOKIE)
+                    .and_then(|rest| rest.strip_prefix('='))
+            })
+            .ok_or_else(|| Redirect::to("/ui/login"))?
+            .to_owned();
+
+        match state.service.get_owner_session(&session_token).await {
+            Ok(Some(owner)) => Ok(OwnerLogin {
+                session_token,
+                owner,
+            }),
+            _ => Err(Redirect::to("/ui/login")),
+        }
+    }
+}
+
+/// Credentials for managing an address from a form, the owner's login is used if no
+/// authentication token was entered
+fn form_credentials(authentication_token: &str, login: Option<&OwnerLogin>) -> Credentials {
+    match login {
+        Some(login) if authentication_token.is_empty() => Credentials::Owner(login.owner.id),
+        _ => Credentials::Token(authentication_token.to_owned()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    k1: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportForm {
+    reason: String,
+    #[serde(default)]
+    contact: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenLoginForm {
+    lnaddr: String,
+    authentication_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenForm {
+    #[serde(default)]
+    authentication_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct LinkForm {
+    #[serde(default)]
+    authentication_token: String,
+    /// Set to detach the address from the owner's account instead
+    unlink: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateForm {
+    #[serde(default)]
+    authentication_token: String,
+    lnurl: String,
+    #[serde(default)]
+    routing_policy: RoutingPolicy,
+    #[serde(default)]
+    amount_rules: String,
+}
+
+#[derive(Deserialize)]
+pub struct StatusForm {
+    #[serde(default)]
+    authentication_token: String,
+    status: AddressStatus,
+}
+
+#[derive(Deserialize)]
+pub struct SettingsForm {
+    #[serde(default)]
+    authentication_token: String,
+    /// Checkboxes are only submitted when checked
+    store_comments: Option<String>,
+    #[serde(default)]
+    payer_name: String,
+    #[serde(default)]
+    payer_pubkey: String,
+    #[serde(default)]
+    payer_identifier: String,
+    #[serde(default)]
+    payer_email: String,
+    #[serde(default)]
+    success_action: String,
+    #[serde(default)]
+    success_text: String,
+    #[serde(default)]
+    success_url: String,
+    #[serde(default)]
+    nostr_pubkey: String,
+    /// One relay per line
+    #[serde(default)]
+    nostr_relays: String,
+    #[serde(default)]
+    bip353_offer: String,
+    #[serde(default)]
+    bip353_onchain: String,
+}
+
+impl SettingsForm {
+    fn payer_data(&self) -> PayerDataRequest {
+        fn field(value: &str) -> Option<PayerDataField> {
+            match value {
+                "optional" => Some(PayerDataField { mandatory: false }),
+                "mandatory" => Some(PayerDataField { mandatory: true }),
+                _ => None,
+            }
+        }
+
+        PayerDataRequest {
+            name: field(&self.payer_name),
+            pubkey: field(&self.payer_pubkey),
+            identifier: field(&self.payer_identifier),
+            email: field(&self.payer_email),
+        }
+    }
+
+    fn success_action(&self) -> Option<SuccessAction> {
+        match self.success_action.as_str() {
+            "message" => Some(SuccessAction::Message {
+                message: self.success_text.trim().to_owned(),
+            }),
+            "url" => Some(SuccessAction::Url {
+                description: self.success_text.trim().to_owned(),
+                url: self.success_url.trim().to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn nostr(&self) -> Option<NostrIdentity> {
+        let pubkey = self.nostr_pubkey.trim();
+        (!pubkey.is_empty()).then(|| NostrIdentity {
+            pubkey: pubkey.to_owned(),
+            relays: self.nostr_relays.lines().map(ToOwned::to_owned).collect(),
+        })
+    }
+
+    fn bip353(&self) -> Option<PaymentInstructions> {
+        let non_empty = |value: &str| {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_owned())
+        };
+        let offer = non_empty(&self.bip353_offer);
+        let onchain = non_empty(&self.bip353_onchain);
+        (offer.is_some() || onchain.is_some()).then_some(PaymentInstructions { offer, onchain })
+    }
+}
+
+/// Parses a textarea containing one destination per line, optionally followed by its routing
+/// weight
+fn form_destinations(lnurl: &str) -> Result<Vec<DestinationInput>, String> {
+    lnurl
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            let destination = parts.next().unwrap_or_default().to_owned();
+            let weight = match parts.next() {
+                Some(weight) => weight
+                    .parse()
+                    .map_err(|_| format!("Invalid weight for destination {destination}"))?,
+                None => 1,
+            };
+            Ok(DestinationInput {
+                destination,
+                weight,
+            })
+        })
+        .collect()
+}
+
+/// Parses a textarea containing one amount rule per line in the form `<min>-<max> <destination>`,
+/// where bounds are in sats and may be left empty and destinations are numbered starting at 1
+fn form_amount_rules(amount_rules: &str) -> Result<Vec<AmountRuleInput>, String> {
+    let parse_bound = |bound: &str| match bound.trim() {
+        "" => Ok(None),
+        bound => bound
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid amount {bound}")),
+    };
+
+    amount_rules
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (range, destination) = line
+                .rsplit_once(char::is_whitespace)
+                .ok_or_else(|| format!("Invalid amount rule {line}"))?;
+            let (min, max) = range
+                .trim()
+                .split_once('-')
+                .ok_or_else(|| format!("Invalid amount range {range}"))?;
+            let destination = destination
+                .parse::<usize>()
+                .ok()
+                .and_then(|destination| destination.checked_sub(1))
+                .ok_or_else(|| format!("Invalid destination number {destination}"))?;
+            Ok(AmountRuleInput {
+                min_sats: parse_bound(min)?,
+                max_sats: parse_bound(max)?,
+                destination,
+            })
+        })
+        .collect()
+}
+
+fn format_amount_rule(rule: &AmountRule) -> String {
+    let bound = |msat: Option<u64>| {
+        msat.map(|msat| (msat / 1000).to_string())
+            .unwrap_or_default()
+    };
+    format!(
+        "{}-{} {}",
+        bound(rule.min_msat),
+        bound(rule.max_msat),
+        rule.destination_position + 1
+    )
+}
+
+fn routing_policy_select(selected: RoutingPolicy) -> Markup {
+    let policies = [
+        (
+            RoutingPolicy::Failover,
+            "Failover: first reachable destination",
+        ),
+        (RoutingPolicy::WeightedRandom, "Weighted random"),
+        (RoutingPolicy::RoundRobin, "Weighted round-robin"),
+    ];
+    html! {
+        div {
+            label for="routing_policy" class="block mb-2 text-sm font-medium text-gray-900" { "Routing" }
+            select name="routing_policy" id="routing_policy" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500" {
+                @for (policy, label) in policies {
+                    option value=(policy) selected[policy == selected] { (label) }
+                }
+            }
+        }
+    }
+}
+
+/// Select for whether a LUD-18 payer data field is requested
+fn payer_data_select(name: &str, label: &str, field: Option<PayerDataField>) -> Markup {
+    let selected = match field {
+        None => "",
+        Some(PayerDataField { mandatory: false }) => "optional",
+        Some(PayerDataField { mandatory: true }) => "mandatory",
+    };
+    html! {
+        div class="flex items-center justify-between" {
+            label for=(name) class="text-sm font-medium text-gray-900" { (label) }
+            select name=(name) id=(name) class="p-1.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm" {
+                @for (value, text) in [("", "Not requested"), ("optional", "Optional"), ("mandatory", "Mandatory")] {
+                    option value=(value) selected[value == selected] { (text) }
+                }
+            }
+        }
+    }
+}
+
+/// Authentication token field of management forms, omitted if the owner's login suffices
+fn token_input(id: &str, managed: bool) -> Markup {
+    html! {
+        @if managed {
+            p class="text-xs text-gray-500" { "Authorized by your login." }
+        } @else {
+            div {
+                label for=(id) class="block mb-2 text-sm font-medium text-gray-900" { "Authentication Token" }
+                input type="password" name="authentication_token" id=(id) required class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500" {}
+            }
+        }
+    }
+}
+
+/// Renders the data as QR code in SVG format
+fn qr_svg(data: &str) -> String {
+    QrCode::new(data)
+        .map(|code| code.render::<svg::Color>().min_dimensions(256, 256).build())
+        .unwrap_or_default()
+}
+
+fn error_page(message: &str) -> Markup {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("Error"))
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
+                    h1 class="text-2xl font-bold mb-4 text-center text-red-700" { "Error" }
+                    div class="mb-6 text-center text-red-600 font-mono break-all" { (message) }
+                    div class="text-center" {
+                        a href="/" class="inline-block text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Back to Register" }
+                    }
+                }
+            }
+        }
+    }
 }
 
 // Add a helper function for the common <head> markup
-fn common_head(title: &str) -> Markup {
+pub(crate) fn common_head(title: &str) -> Markup {
     html! {
         head {
             meta charset="UTF-8";
@@ -33,9 +385,14 @@ fn common_head(title: &str) -> Markup {
     }
 }
 
-pub async fn register_form(State(state): State<AppState>) -> impl IntoResponse {
+pub async fn register_form(
+    State(state): State<AppState>,
+    login: Option<OwnerLogin>,
+) -> impl IntoResponse {
     let domains = state.service.list_domains().await.unwrap_or_default();
     let warning = state.config.warning.clone();
+    // The form's script fetches a challenge when submitted, so views don't store any
+    let pow_required = state.config.register_pow_difficulty > 0;
     let markup = html! {
         (DOCTYPE)
         html lang="en" {
@@ -65,9 +422,26 @@ pub async fn register_form(State(state): State<AppState>) -> impl IntoResponse {
                         div {
                             label for="lnurl" class="block mb-2 text-sm font-medium text-gray-900" { "LNURL or Lightning Address" }
                             textarea name="lnurl" id="lnurl" required rows="3" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500 resize-y" style="word-break: break-all;" {}
+                            p class="mt-1 text-xs text-gray-500" { "One per line, optionally followed by a weight. With failover routing additional lines are used, in order, if the first one is unreachable." }
+                        }
+                        (routing_policy_select(RoutingPolicy::default()))
+                        @if login.is_some() {
+                            p class="text-xs text-gray-500" { "The address will be linked to your login." }
+                        }
+                        @if pow_required {
+                            input type="hidden" name="pow_nonce" value="";
+                            input type="hidden" name="pow_solution" value="";
+                            p class="text-xs text-gray-500" { "To prevent spam your browser solves a short proof-of-work puzzle when registering, which may take a few seconds." }
                         }
                         button type="submit" class="w-full text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Register" }
                     }
+                    div class="text-center mt-4" {
+                        @if login.is_some() {
+                            a href="/ui/owner" class="text-blue-600 hover:underline font-medium" { "My addresses" }
+                        } @else {
+                            a href="/ui/login" class="text-blue-600 hover:underline font-medium" { "Log in with Lightning" }
+                        }
+                    }
                     div class="flex justify-center mt-10" {
                         a href="https://github.com/elsirion/lnaddrd" target="_blank" rel="noopener noreferrer" class="flex items-center space-x-2 text-gray-600 hover:text-black transition-colors" {
                             (maud::PreEscaped(r#"<svg xmlns='http://www.w3.org/2000/svg' fill='currentColor' viewBox='0 0 24 24' class='w-6 h-6'><path d='M12 0C5.37 0 0 5.373 0 12c0 5.303 3.438 9.8 8.205 11.387.6.113.82-.258.82-.577 0-.285-.01-1.04-.015-2.04-3.338.726-4.042-1.61-4.042-1.61-.546-1.387-1.333-1.756-1.333-1.756-1.09-.745.083-.729.083-.729 1.205.085 1.84 1.237 1.84 1.237 1.07 1.834 2.807 1.304 3.492.997.108-.775.418-1.305.762-1.606-2.665-.304-5.466-1.334-5.466-5.931 0-1.31.468-2.381 1.236-3.221-.124-.303-.535-1.523.117-3.176 0 0 1.008-.322 3.3 1.23a11.52 11.52 0 0 1 3.003-.404c1.02.005 2.047.138 3.003.404 2.291-1.553 3.297-1.23 3.297-1.23.653 1.653.242 2.873.118 3.176.77.84 1.235 1.911 1.235 3.221 0 4.609-2.804 5.625-5.475 5.921.43.372.823 1.102.823 2.222 0 1.606-.014 2.898-.014 3.293 0 .322.218.694.825.576C20.565 21.796 24 17.299 24 12c0-6.627-5.373-12-12-12z'/></svg>"#))
@@ -75,6 +449,9 @@ pub async fn register_form(State(state): State<AppState>) -> impl IntoResponse {
                         }
                     }
                 }
+                @if pow_required {
+                    script { (maud::PreEscaped(pow::SOLVER_SCRIPT)) }
+                }
             }
         }
     };
@@ -83,33 +460,51 @@ pub async fn register_form(State(state): State<AppState>) -> impl IntoResponse {
 
 pub async fn register_form_submit(
     State(state): State<AppState>,
+    client: ClientInfo,
+    login: Option<OwnerLogin>,
     Form(form): Form<RegisterForm>,
 ) -> impl IntoResponse {
-    let req = RegisterRequest {
-        domain: form.domain.clone(),
-        username: form.username.clone(),
-        lnurl: form.lnurl,
+    let destinations = match form_destinations(&form.lnurl) {
+        Ok(destinations) => destinations,
+        Err(e) => return Html(error_page(&e).into_string()).into_response(),
+    };
+    if let Err(e) = state
+        .service
+        .verify_pow(form.pow_nonce.as_deref(), form.pow_solution.as_deref())
+        .await
+    {
+        return Html(error_page(&e.to_string()).into_string()).into_response();
+    }
+    let routing = RoutingInput {
+        destinations,
+        policy: form.routing_policy,
+        amount_rules: vec![],
     };
     match state
         .service
-        .register_lnaddr(&req.domain, &req.username, &req.lnurl)
+        .register_lnaddr(
+            &form.domain,
+            &form.username,
+            &routing,
+            SettingsUpdate::default(),
+            login.as_ref().map(|login| login.owner.id),
+            &client,
+        )
         .await
     {
-        Ok(_resp) => {
-            // Redirect to the details page
-            Redirect::to(&format!("/ui/lnaddress/{}/{}", req.domain, req.username)).into_response()
-        }
-        Err(e) => {
-            let markup: Markup = html! {
+        Ok(resp) => {
+            // The token is only shown once, afterwards users are sent to the details page
+            let markup = html! {
                 (DOCTYPE)
                 html lang="en" {
-                    (common_head("Error"))
+                    (common_head("Registered"))
                     body class="bg-gray-50 min-h-screen flex items-center justify-center" {
                         div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
-                            h1 class="text-2xl font-bold mb-4 text-center text-red-700" { "Error" }
-                            div class="mb-6 text-center text-red-600 font-mono break-all" { (e.to_string()) }
+                            h1 class="text-2xl font-bold mb-4 text-center text-gray-900" { "Registered " (resp.lnaddr) }
+                            p class="mb-2" { "Keep the following authentication token, it is required to manage your address and can't be recovered:" }
+                            pre class="bg-gray-100 rounded p-2 mb-6 font-mono break-all" { (resp.authentication_token) }
                             div class="text-center" {
-                                a href="/" class="inline-block text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Back to Register" }
+                                a href=(format!("/ui/lnaddress/{}/{}", form.domain, form.username)) class="inline-block text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Continue" }
                             }
                         }
                     }
@@ -117,36 +512,146 @@ pub async fn register_form_submit(
             };
             Html(markup.into_string()).into_response()
         }
+        Err(e) => Html(error_page(&e.to_string()).into_string()).into_response(),
+    }
+}
+
+pub async fn update_form_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    login: Option<OwnerLogin>,
+    Form(form): Form<UpdateForm>,
+) -> impl IntoResponse {
+    let routing = match form_destinations(&form.lnurl).and_then(|destinations| {
+        Ok(RoutingInput {
+            destinations,
+            policy: form.routing_policy,
+            amount_rules: form_amount_rules(&form.amount_rules)?,
+        })
+    }) {
+        Ok(routing) => routing,
+        Err(e) => return Html(error_page(&e).into_string()).into_response(),
+    };
+    match state
+        .service
+        .update_lnaddr(
+            &domain,
+            &username,
+            &form_credentials(&form.authentication_token, login.as_ref()),
+            &routing,
+            &client,
+        )
+        .await
+    {
+        Ok(()) => Redirect::to(&format!("/ui/lnaddress/{domain}/{username}")).into_response(),
+        Err(e) => Html(error_page(&e.to_string()).into_string()).into_response(),
+    }
+}
+
+pub async fn settings_form_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    login: Option<OwnerLogin>,
+    Form(form): Form<SettingsForm>,
+) -> impl IntoResponse {
+    let update = SettingsUpdate {
+        store_comments: Some(form.store_comments.is_some()),
+        payer_data: Some(form.payer_data()),
+        success_action: Some(form.success_action()),
+        nostr: Some(form.nostr()),
+        bip353: Some(form.bip353()),
+    };
+    match state
+        .service
+        .update_lnaddr_settings(
+            &domain,
+            &username,
+            &form_credentials(&form.authentication_token, login.as_ref()),
+            update,
+            &client,
+        )
+        .await
+    {
+        Ok(_) => Redirect::to(&format!("/ui/lnaddress/{domain}/{username}")).into_response(),
+        Err(e) => Html(error_page(&e.to_string()).into_string()).into_response(),
+    }
+}
+
+pub async fn status_form_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    login: Option<OwnerLogin>,
+    Form(form): Form<StatusForm>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .set_lnaddr_status(
+            &domain,
+            &username,
+            &form_credentials(&form.authentication_token, login.as_ref()),
+            form.status,
+            &client,
+        )
+        .await
+    {
+        Ok(()) => Redirect::to(&format!("/ui/lnaddress/{domain}/{username}")).into_response(),
+        Err(e) => Html(error_page(&e.to_string()).into_string()).into_response(),
     }
 }
 
 pub async fn lnaddress_details(
     State(state): State<AppState>,
     Path((domain, username)): Path<(String, String)>,
+    login: Option<OwnerLogin>,
 ) -> Result<impl IntoResponse, axum::http::StatusCode> {
-    let lnaddr = format!("{username}@{domain}");
-    let destination_addr = state
+    let details = state
         .service
-        .get_destination(&domain, &username)
+        .get_lnaddr_details(&domain, &username)
         .await
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(axum::http::StatusCode::NOT_FOUND)?;
-    let manifest = state
+    let lnaddr = details.lnaddr;
+    let destinations_str = details
+        .destinations
+        .iter()
+        .map(|d| match d.weight {
+            1 => d.destination.to_string(),
+            weight => format!("{} {weight}", d.destination),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let amount_rules_str = details
+        .amount_rules
+        .iter()
+        .map(format_amount_rule)
+        .collect::<Vec<_>>()
+        .join("\n");
+    // Viewing the page doesn't count as selecting a destination
+    let manifest_str = match state
         .service
-        .get_lnaddr_manifest(&domain, &username)
+        .get_lnaddr_manifest(&domain, &username, false)
         .await
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
-        .expect("If LNURL is registered, manifest should be present");
-    let manifest_str = serde_json::to_string_pretty(&manifest).unwrap();
-
-    let lnaddr_svg = {
-        let lnaddr_code = QrCode::new(&lnaddr).unwrap();
-        lnaddr_code
-            .render::<svg::Color>()
-            .min_dimensions(256, 256)
-            .build()
+    {
+        Ok(Some(manifest)) => {
+            serde_json::to_string_pretty(&manifest).unwrap_or_else(|e| e.to_string())
+        }
+        // The address was removed since its details were loaded
+        Ok(None) => "No manifest available".to_owned(),
+        // Shown instead of the manifest, as wallets show the LUD-06 error
+        Err(e) => e.to_string(),
     };
 
+    // Owners logged into the account of the address don't need its token
+    let managed = login
+        .as_ref()
+        .is_some_and(|login| details.owner_id == Some(login.owner.id));
+    let lnaddr_svg = qr_svg(&lnaddr);
+    let lnurl_svg = qr_svg(&details.lnurl);
+    let lud17_svg = qr_svg(&details.lud17);
+
     let markup = html! {
         (DOCTYPE)
         html lang="en" {
@@ -154,24 +659,202 @@ pub async fn lnaddress_details(
             body class="bg-gray-50 min-h-screen flex items-center justify-center" {
                 div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
                     h1 class="text-3xl font-bold mb-6 text-center text-gray-900" { "LN Address Details" }
+                    @match details.status {
+                        AddressStatus::Active => {}
+                        AddressStatus::Paused => p class="mb-4 p-3 text-sm text-yellow-800 bg-yellow-50 rounded-lg" { "This address has been paused by its owner and can't receive payments until it is resumed." },
+                        AddressStatus::Suspended => p class="mb-4 p-3 text-sm text-red-800 bg-red-50 rounded-lg" { "This address has been suspended by the operator and can't receive payments." },
+                    }
                     div class="mb-4" {
                         p class="mb-2" { b { "Lightning Address:" } " " (lnaddr) }
                         div class="flex justify-center mb-2" { (maud::PreEscaped(lnaddr_svg)) }
-                        p class="mb-2" {
-                            b {
-                                (match &destination_addr {
-                                    DestinationPaymentAddress::Lnurl(_) => "LNURL:",
-                                    DestinationPaymentAddress::LnAddress { .. } => "LN Address:",
-                                })
+                        details class="mb-2" {
+                            summary class="cursor-pointer text-sm text-gray-700" { "For wallets without Lightning Address support" }
+                            p class="mt-2 mb-2" { b { "LNURL:" } " " span class="break-all font-mono text-sm" { (details.lnurl) } }
+                            div class="flex justify-center mb-2" { (maud::PreEscaped(lnurl_svg)) }
+                            p class="mb-2" { b { "LUD-17:" } " " span class="break-all font-mono text-sm" { (details.lud17) } }
+                            div class="flex justify-center mb-2" { (maud::PreEscaped(lud17_svg)) }
+                        }
+                        @if let Some(expires_at) = details.expires_at {
+                            p class="mb-2" { b { "Expires:" } " " (humantime::format_rfc3339_seconds(expires_at)) }
+                        }
+                        @if details.destinations.len() > 1 {
+                            p class="mb-2" { b { "Routing:" } " " (details.routing_policy) }
+                        }
+                        @for (idx, destination) in details.destinations.iter().enumerate() {
+                            div class="mb-2" {
+                                @if details.destinations.len() > 1 {
+                                    p class="text-sm text-gray-500" {
+                                        @if details.routing_policy != RoutingPolicy::Failover {
+                                            "Destination " (idx + 1) ", weight " (destination.weight)
+                                        } @else if idx == 0 {
+                                            "Primary destination"
+                                        } @else {
+                                            "Fallback " (idx)
+                                        }
+                                        ", served " (destination.selection_count) " times"
+                                    }
+                                }
+                                p {
+                                    b {
+                                        (match destination.destination {
+                                            DestinationPaymentAddress::Lnurl(_) => "LNURL:",
+                                            DestinationPaymentAddress::LnAddress { .. } => "LN Address:",
+                                            DestinationPaymentAddress::Bolt12Offer(_) => "BOLT12 offer:",
+                                        })
+                                    }
+                                    " " span class="break-all font-mono" { (destination.destination) }
+                                }
+                                @match &destination.destination {
+                                    DestinationPaymentAddress::Bolt12Offer(offer) => {
+                                        p { b { "Description:" } " " (offer.description.as_deref().or(offer.issuer.as_deref()).unwrap_or("none")) }
+                                    }
+                                    other => {
+                                        p { b { "Decoded:" } " " span class="break-all font-mono" { (other.url().unwrap_or_default()) } }
+                                    }
+                                }
+                            }
+                        }
+                        @if !details.amount_rules.is_empty() {
+                            p class="mb-2" { b { "Amount rules:" } }
+                            ul class="mb-2 list-disc list-inside text-sm" {
+                                @for rule in &details.amount_rules {
+                                    li {
+                                        @match (rule.min_msat, rule.max_msat) {
+                                            (Some(min), Some(max)) => { (min / 1000) " to " (max / 1000) " sats" }
+                                            (Some(min), None) => { (min / 1000) " sats and more" }
+                                            (None, Some(max)) => { "below " (max / 1000) " sats" }
+                                            (None, None) => { "any amount" }
+                                        }
+                                        " → destination " (rule.destination_position + 1)
+                                    }
+                                }
                             }
-                            " " span class="break-all font-mono" { (destination_addr) }
                         }
-                        p class="mb-2" { b { "Decoded:" } " " span class="break-all font-mono" { (destination_addr.url()) } }
                         p class="mb-2" { b { "Manifest:" } }
                         pre class="bg-gray-100 rounded p-2 text-xs overflow-x-auto" { (manifest_str) }
                     }
+                    details class="mb-4" {
+                        summary class="cursor-pointer font-medium text-gray-900" { "Manage destinations" }
+                        form method="post" action=(format!("/ui/lnaddress/{domain}/{username}/update")) class="space-y-4 mt-4" {
+                            div {
+                                label for="lnurl" class="block mb-2 text-sm font-medium text-gray-900" { "LNURLs or Lightning Addresses, one per line with optional weight" }
+                                textarea name="lnurl" id="lnurl" required rows="3" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500 resize-y" style="word-break: break-all;" { (destinations_str) }
+                            }
+                            (routing_policy_select(details.routing_policy))
+                            div {
+                                label for="amount_rules" class="block mb-2 text-sm font-medium text-gray-900" { "Amount rules" }
+                                textarea name="amount_rules" id="amount_rules" rows="2" placeholder="0-10000 1" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500 resize-y font-mono" { (amount_rules_str) }
+                                p class="mt-1 text-xs text-gray-500" { "One per line as min-max sats followed by the destination number, bounds may be left empty. The first matching rule decides where a payment goes." }
+                            }
+                            (token_input("authentication_token", managed))
+                            button type="submit" class="w-full text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Update" }
+                        }
+                    }
+                    details class="mb-4" {
+                        summary class="cursor-pointer font-medium text-gray-900" { "Settings" }
+                        form method="post" action=(format!("/ui/lnaddress/{domain}/{username}/settings")) class="space-y-4 mt-4" {
+                            div class="flex items-center" {
+                                input type="checkbox" name="store_comments" id="store_comments" checked[details.settings.store_comments] class="w-4 h-4 border border-gray-300 rounded bg-gray-50" {}
+                                label for="store_comments" class="ms-2 text-sm font-medium text-gray-900" { "Store payment comments" }
+                            }
+                            p class="text-xs text-gray-500" { "Stored comments can be retrieved with the authentication token. Comments are forwarded to the destination if it accepts them." }
+                            div class="space-y-2" {
+                                p class="text-sm font-medium text-gray-900" { "Payer data" }
+                                @let payer_data = details.settings.payer_data.unwrap_or_default();
+                                (payer_data_select("payer_name", "Name", payer_data.name))
+                                (payer_data_select("payer_pubkey", "Public key", payer_data.pubkey))
+                                (payer_data_select("payer_identifier", "Identifier", payer_data.identifier))
+                                (payer_data_select("payer_email", "Email", payer_data.email))
+                                p class="text-xs text-gray-500" { "Requested payer data is stored for retrieval with the authentication token. Since invoices commit to the payer data, only fields the destination requests itself are asked for, except for BOLT12 offers." }
+                            }
+                            div class="space-y-2" {
+                                @let (success_kind, success_text, success_url) = match &details.settings.success_action {
+                                    None => ("", "", ""),
+                                    Some(SuccessAction::Message { message }) => ("message", message.as_str(), ""),
+                                    Some(SuccessAction::Url { description, url }) => ("url", description.as_str(), url.as_str()),
+                                };
+                                div class="flex items-center justify-between" {
+                                    label for="success_action" class="text-sm font-medium text-gray-900" { "After payment" }
+                                    select name="success_action" id="success_action" class="p-1.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm" {
+                                        @for (value, text) in [("", "Destination's default"), ("message", "Show message"), ("url", "Open URL")] {
+                                            option value=(value) selected[value == success_kind] { (text) }
+                                        }
+                                    }
+                                }
+                                input type="text" name="success_text" maxlength="144" value=(success_text) placeholder="Message or URL description" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm" {}
+                                input type="url" name="success_url" value=(success_url) placeholder="https://" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm" {}
+                            }
+                            div class="space-y-2" {
+                                label for="nostr_pubkey" class="block text-sm font-medium text-gray-900" { "Nostr public key (NIP-05)" }
+                                input type="text" name="nostr_pubkey" id="nostr_pubkey" value=(details.settings.nostr.as_ref().map(|n| n.pubkey.as_str()).unwrap_or_default()) placeholder="npub… or hex" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm font-mono" {}
+                                textarea name="nostr_relays" rows="2" placeholder="wss://relay.example.com" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm font-mono resize-y" {
+                                    (details.settings.nostr.as_ref().map(|n| n.relays.join("\n")).unwrap_or_default())
+                                }
+                                p class="text-xs text-gray-500" { "Makes " (lnaddr) " usable as Nostr identifier. Relays are optional, one per line." }
+                            }
+                            div class="space-y-2" {
+                                label for="bip353_offer" class="block text-sm font-medium text-gray-900" { "DNS payment instructions (BIP-353)" }
+                                input type="text" name="bip353_offer" id="bip353_offer" value=(details.settings.bip353.as_ref().and_then(|i| i.offer.as_deref()).unwrap_or_default()) placeholder="BOLT12 offer lno1…" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm font-mono" {}
+                                input type="text" name="bip353_onchain" value=(details.settings.bip353.as_ref().and_then(|i| i.onchain.as_deref()).unwrap_or_default()) placeholder="On-chain address bc1…" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 text-sm font-mono" {}
+                                p class="text-xs text-gray-500" { "Published as ₿" (lnaddr) " for wallets resolving payment instructions via DNS." }
+                            }
+                            (token_input("settings_authentication_token", managed))
+                            button type="submit" class="w-full text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Save" }
+                        }
+                    }
+                    details class="mb-4" {
+                        summary class="cursor-pointer font-medium text-gray-900" { "Login" }
+                        @if managed {
+                            form method="post" action=(format!("/ui/lnaddress/{domain}/{username}/link")) class="space-y-4 mt-4" {
+                                p class="text-sm text-gray-700" { "This address is linked to your account and can be managed without its authentication token." }
+                                input type="hidden" name="unlink" value="1" {}
+                                button type="submit" class="w-full text-gray-900 bg-white border border-gray-300 hover:bg-gray-100 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Unlink from my account" }
+                            }
+                        } @else if login.is_some() {
+                            form method="post" action=(format!("/ui/lnaddress/{domain}/{username}/link")) class="space-y-4 mt-4" {
+                                p class="text-sm text-gray-700" { "Link this address to your account to manage it without its authentication token." }
+                                (token_input("link_authentication_token", false))
+                                button type="submit" class="w-full text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Link to my account" }
+                            }
+                        } @else {
+                            p class="text-sm text-gray-700 mt-4" {
+                                a href="/ui/login" class="text-blue-600 hover:underline" { "Log in" }
+                                " to manage this address together with your other addresses, without its authentication token."
+                            }
+                        }
+                    }
+                    @if details.status != AddressStatus::Suspended {
+                        @let (status, action, explanation) = match details.status {
+                            AddressStatus::Paused => ("active", "Resume address", "Resuming the address lets it receive payments again."),
+                            _ => ("paused", "Pause address", "Pausing the address stops payments to it until it is resumed, while keeping the username."),
+                        };
+                        details class="mb-4" {
+                            summary class="cursor-pointer font-medium text-gray-900" { (action) }
+                            form method="post" action=(format!("/ui/lnaddress/{domain}/{username}/status")) class="space-y-4 mt-4" {
+                                p class="text-sm text-gray-700" { (explanation) }
+                                input type="hidden" name="status" value=(status) {}
+                                (token_input("status_authentication_token", managed))
+                                button type="submit" class="w-full text-gray-900 bg-white border border-gray-300 hover:bg-gray-100 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { (action) }
+                            }
+                        }
+                    }
+                    details class="mb-4" {
+                        summary class="cursor-pointer font-medium text-red-700" { "Remove address" }
+                        form method="post" action=(format!("/ui/lnaddress/{domain}/{username}/remove")) class="space-y-4 mt-4" {
+                            p class="text-sm text-gray-700" { "Removing the address stops payments to it, and the username can be registered by anyone afterwards." }
+                            (token_input("remove_authentication_token", managed))
+                            button type="submit" class="w-full text-white bg-red-700 hover:bg-red-800 focus:ring-4 focus:outline-none focus:ring-red-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Remove" }
+                        }
+                    }
                     div class="text-center" {
-                        a href="/" class="inline-block text-blue-600 hover:underline font-medium text-lg" { "Back to Register" }
+                        @if login.is_some() {
+                            a href="/ui/owner" class="inline-block text-blue-600 hover:underline font-medium text-lg" { "My addresses" }
+                        } @else {
+                            a href="/" class="inline-block text-blue-600 hover:underline font-medium text-lg" { "Back to Register" }
+                        }
+                    }
+                    div class="text-center mt-4" {
+                        a href=(format!("/ui/lnaddress/{domain}/{username}/report")) class="text-sm text-gray-500 hover:underline" { "Report this address" }
                     }
                 }
             }
@@ -179,3 +862,415 @@ pub async fn lnaddress_details(
     };
     Ok(Html(markup.into_string()).into_response())
 }
+
+/// Form for reporting an address used for scams or other abuse
+pub async fn report_form(Path((domain, username)): Path<(String, String)>) -> impl IntoResponse {
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("Report address"))
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
+                    h1 class="text-3xl font-bold mb-6 text-center text-gray-900" { "Report address" }
+                    p class="mb-4 text-center text-gray-700" { "Report " b { (username) "@" (domain) } " if it is used for scams or other abuse. The operator will review the report." }
+                    form method="post" action=(format!("/ui/lnaddress/{domain}/{username}/report")) class="space-y-6" {
+                        div {
+                            label for="reason" class="block mb-2 text-sm font-medium text-gray-900" { "Reason" }
+                            textarea name="reason" id="reason" required rows="4" maxlength="2000" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500 resize-y" {}
+                        }
+                        div {
+                            label for="contact" class="block mb-2 text-sm font-medium text-gray-900" { "Contact (optional)" }
+                            input name="contact" id="contact" maxlength="256" placeholder="Email or Nostr pubkey" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500" {}
+                            p class="mt-1 text-xs text-gray-500" { "Only needed if the operator may ask you about the report." }
+                        }
+                        button type="submit" class="w-full text-white bg-red-700 hover:bg-red-800 focus:ring-4 focus:outline-none focus:ring-red-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Report" }
+                    }
+                    div class="text-center mt-4" {
+                        a href=(format!("/ui/lnaddress/{domain}/{username}")) class="text-blue-600 hover:underline font-medium" { "Back to address" }
+                    }
+                }
+            }
+        }
+    };
+    Html(markup.into_string())
+}
+
+pub async fn report_form_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    Form(form): Form<ReportForm>,
+) -> impl IntoResponse {
+    if let Err(e) = state
+        .service
+        .report_lnaddr(
+            &domain,
+            &username,
+            &form.reason,
+            Some(&form.contact),
+            &client,
+        )
+        .await
+    {
+        return Html(error_page(&e.to_string()).into_string());
+    }
+
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("Report address"))
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg text-center" {
+                    h1 class="text-2xl font-bold mb-4 text-gray-900" { "Thank you" }
+                    p class="mb-6 text-gray-700" { "Your report of " (username) "@" (domain) " was sent to the operator." }
+                    a href="/" class="inline-block text-blue-600 hover:underline font-medium text-lg" { "Back to Register" }
+                }
+            }
+        }
+    };
+    Html(markup.into_string())
+}
+
+pub async fn link_form_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    login: OwnerLogin,
+    Form(form): Form<LinkForm>,
+) -> impl IntoResponse {
+    let (credentials, owner_id) = match form.unlink {
+        Some(_) => (Credentials::Owner(login.owner.id), None),
+        None => (
+            Credentials::Token(form.authentication_token),
+            Some(login.owner.id),
+        ),
+    };
+    match state
+        .service
+        .link_lnaddr(&domain, &username, &credentials, owner_id, &client)
+        .await
+    {
+        Ok(()) => Redirect::to(&format!("/ui/lnaddress/{domain}/{username}")).into_response(),
+        Err(e) => Html(error_page(&e.to_string()).into_string()).into_response(),
+    }
+}
+
+pub async fn remove_form_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Path((domain, username)): Path<(String, String)>,
+    login: Option<OwnerLogin>,
+    Form(form): Form<TokenForm>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .remove_lnaddr(
+            &domain,
+            &username,
+            &form_credentials(&form.authentication_token, login.as_ref()),
+            &client,
+        )
+        .await
+    {
+        Ok(()) if login.is_some() => Redirect::to("/ui/owner").into_response(),
+        Ok(()) => Redirect::to("/").into_response(),
+        Err(e) => Html(error_page(&e.to_string()).into_string()).into_response(),
+    }
+}
+
+/// LNURL-auth login page. It reloads itself with the challenge until a wallet signed it, then
+/// starts the owner session.
+pub async fn login_page(
+    State(state): State<AppState>,
+    Host(host): Host,
+    Query(query): Query<LoginQuery>,
+) -> impl IntoResponse {
+    let challenge = match query.k1 {
+        Some(k1) => match state.service.login(&k1).await {
+            Ok(Some(session)) => return start_session(&session).into_response(),
+            Ok(None) => lnurl_auth::login_lnurl(&host, &k1).map(|lnurl| (k1, lnurl)),
+            Err(e) => Err(e),
+        },
+        None => state
+            .service
+            .create_auth_challenge(&host)
+            .await
+            .map(|challenge| (challenge.k1, challenge.lnurl)),
+    };
+    let (k1, lnurl) = match challenge {
+        Ok(challenge) => challenge,
+        Err(e) => return Html(error_page(&e.to_string()).into_string()).into_response(),
+    };
+    let lnurl_svg = qr_svg(&format!("lightning:{lnurl}"));
+
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("Log in"))
+            meta http-equiv="refresh" content=(format!("3; url=/ui/login?k1={k1}"));
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
+                    h1 class="text-3xl font-bold mb-6 text-center text-gray-900" { "Log in with Lightning" }
+                    p class="mb-4 text-center text-gray-700" { "Scan the code with a wallet supporting LNURL-auth to manage all addresses linked to it." }
+                    a href=(format!("lightning:{lnurl}")) class="flex justify-center mb-4" { (maud::PreEscaped(lnurl_svg)) }
+                    p class="mb-4 break-all font-mono text-xs text-gray-500" { (lnurl) }
+                    div class="text-center" {
+                        a href="/ui/login" class="text-blue-600 hover:underline font-medium" { "New code" }
+                        " · "
+                        a href="/ui/login/token" class="text-blue-600 hover:underline font-medium" { "Log in with a token" }
+                        " · "
+                        a href="/" class="text-blue-600 hover:underline font-medium" { "Back to Register" }
+                    }
+                }
+            }
+        }
+    };
+    Html(markup.into_string()).into_response()
+}
+
+/// Login with the authentication token of one of the owner's addresses, for owners without
+/// LNURL-auth wallet
+pub async fn token_login_page() -> impl IntoResponse {
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("Log in"))
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
+                    h1 class="text-3xl font-bold mb-6 text-center text-gray-900" { "Log in with a token" }
+                    p class="mb-4 text-center text-gray-700" { "Log in with the authentication token of any of your addresses to manage all addresses of its account. An address without account gets a new one. Accounts using Lightning or Nostr to log in can only be entered with them." }
+                    form method="post" action="/ui/login/token" class="space-y-6" {
+                        div {
+                            label for="lnaddr" class="block mb-2 text-sm font-medium text-gray-900" { "Lightning Address" }
+                            input name="lnaddr" id="lnaddr" required placeholder="user@domain" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 focus:ring-blue-500 focus:border-blue-500" {}
+                        }
+                        (token_input("login_authentication_token", false))
+                        button type="submit" class="w-full text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Log in" }
+                    }
+                    div class="text-center mt-4" {
+                        a href="/ui/login" class="text-blue-600 hover:underline font-medium" { "Log in with Lightning" }
+                        " · "
+                        a href="/" class="text-blue-600 hover:underline font-medium" { "Back to Register" }
+                    }
+                }
+            }
+        }
+    };
+    Html(markup.into_string())
+}
+
+pub async fn token_login_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Form(form): Form<TokenLoginForm>,
+) -> impl IntoResponse {
+    let Some((username, domain)) = form.lnaddr.trim().split_once('@') else {
+        return Html(error_page("Invalid lightning address").into_string()).into_response();
+    };
+    match state
+        .service
+        .login_with_token(domain, username, &form.authentication_token, &client)
+        .await
+    {
+        Ok(session) => start_session(&session).into_response(),
+        Err(e) => Html(error_page(&e.to_string()).into_string()).into_response(),
+    }
+}
+
+/// Sets the session cookie and sends the owner to the dashboard
+fn start_session(session: &OwnerSession) -> impl IntoResponse {
+    let max_age = session
+        .expires_at
+        .duration_since(SystemTime::now())
+        .unwrap_or_default()
+        .as_secs();
+    let cookie = format!(
+        "{SESSION_COOKIE}={}; Path=/; Max-Age={max_age}; HttpOnly; Secure; SameSite=Lax",
+        session.token
+    );
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/ui/owner"))
+}
+
+pub async fn logout_submit(State(state): State<AppState>, login: OwnerLogin) -> impl IntoResponse {
+    if let Err(e) = state.service.logout(&login.session_token).await {
+        return Html(error_page(&e.to_string()).into_string()).into_response();
+    }
+    let cookie = format!("{SESSION_COOKIE}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax");
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response()
+}
+
+/// Addresses of the logged in owner's account, with actions applying to all selected ones
+pub async fn owner_dashboard(
+    State(state): State<AppState>,
+    login: OwnerLogin,
+) -> impl IntoResponse {
+    let addresses = match state.service.list_owner_lnaddrs(login.owner.id).await {
+        Ok(addresses) => addresses,
+        Err(e) => return Html(error_page(&e.to_string()).into_string()).into_response(),
+    };
+
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("My addresses"))
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
+                    h1 class="text-3xl font-bold mb-6 text-center text-gray-900" { "My addresses" }
+                    @if addresses.is_empty() {
+                        p class="mb-4 text-center text-gray-700" { "No addresses are linked to your account yet. Register a new one, or link an existing one from its details page." }
+                    } @else {
+                        form method="post" action="/ui/owner/bulk" class="mb-6" {
+                            ul class="mb-4 divide-y divide-gray-200" {
+                                @for address in &addresses {
+                                    @let (username, domain) = address.lnaddr.split_once('@').unwrap_or_default();
+                                    li class="py-3 flex items-start gap-3" {
+                                        input type="checkbox" name="lnaddr" value=(address.lnaddr) class="mt-1.5 w-4 h-4 border border-gray-300 rounded bg-gray-50" {}
+                                        div class="min-w-0" {
+                                            a href=(format!("/ui/lnaddress/{domain}/{username}")) class="text-blue-600 hover:underline font-medium" { (address.lnaddr) }
+                                            @if let Some(destination) = address.destinations.first() {
+                                                p class="text-xs text-gray-500 break-all font-mono" { (destination.destination) }
+                                            }
+                                            @if let Some(expires_at) = address.expires_at {
+                                                p class="text-xs text-gray-500" { "Expires " (humantime::format_rfc3339_seconds(expires_at)) }
+                                            }
+                                            @if address.status != AddressStatus::Active {
+                                                p class="text-xs text-red-600" { "Not receiving payments, " (address.status) }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            div class="space-y-4" {
+                                div {
+                                    label for="action" class="block mb-2 text-sm font-medium text-gray-900" { "With selected addresses" }
+                                    select name="action" id="action" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900" {
+                                        @for (value, text) in BULK_ACTIONS {
+                                            option value=(value) { (text) }
+                                        }
+                                    }
+                                }
+                                div {
+                                    label for="lnurl" class="block mb-2 text-sm font-medium text-gray-900" { "New destinations" }
+                                    textarea name="lnurl" id="lnurl" rows="2" class="block w-full p-2.5 border border-gray-300 rounded-lg bg-gray-50 text-gray-900 resize-y" style="word-break: break-all;" {}
+                                    p class="mt-1 text-xs text-gray-500" { "Only used when replacing destinations, one per line as when registering." }
+                                }
+                                (routing_policy_select(RoutingPolicy::default()))
+                                button type="submit" class="w-full text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center" { "Apply" }
+                            }
+                        }
+                    }
+                    div class="flex justify-between items-center" {
+                        a href="/" class="text-blue-600 hover:underline font-medium" { "Register new address" }
+                        form method="post" action="/ui/logout" {
+                            button type="submit" class="text-gray-900 bg-white border border-gray-300 hover:bg-gray-100 font-medium rounded-lg text-sm px-4 py-2" { "Log out" }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Html(markup.into_string()).into_response()
+}
+
+/// Actions offered on the dashboard, as form value and label
+const BULK_ACTIONS: [(&str, &str); 8] = [
+    ("renew", "Renew"),
+    ("pause", "Pause"),
+    ("resume", "Resume"),
+    ("store_comments", "Store payment comments"),
+    ("discard_comments", "Stop storing payment comments"),
+    ("update", "Replace destinations"),
+    ("unlink", "Unlink from my account"),
+    ("remove", "Remove"),
+];
+
+/// Applies the dashboard action to the selected addresses. The form is taken as list of pairs
+/// as the `lnaddr` checkboxes repeat.
+pub async fn bulk_form_submit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    login: OwnerLogin,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    };
+    let lnaddrs = fields
+        .iter()
+        .filter(|(key, _)| key == "lnaddr")
+        .map(|(_, value)| value.clone())
+        .collect::<Vec<_>>();
+    if lnaddrs.is_empty() {
+        return Html(error_page("No addresses selected").into_string()).into_response();
+    }
+
+    let action = match field("action") {
+        "renew" => BulkAction::Renew,
+        "store_comments" | "discard_comments" => BulkAction::Settings(SettingsUpdate {
+            store_comments: Some(field("action") == "store_comments"),
+            ..Default::default()
+        }),
+        "update" => {
+            let destinations = match form_destinations(field("lnurl")) {
+                Ok(destinations) => destinations,
+                Err(e) => return Html(error_page(&e).into_string()).into_response(),
+            };
+            BulkAction::Update(RoutingInput {
+                destinations,
+                policy: field("routing_policy").parse().unwrap_or_default(),
+                amount_rules: vec![],
+            })
+        }
+        "pause" => BulkAction::Status(AddressStatus::Paused),
+        "resume" => BulkAction::Status(AddressStatus::Active),
+        "unlink" => BulkAction::Unlink,
+        "remove" => BulkAction::Remove,
+        action => {
+            return Html(error_page(&format!("Unknown action {action}")).into_string())
+                .into_response();
+        }
+    };
+
+    let results = match state
+        .service
+        .bulk_manage_lnaddrs(login.owner.id, &lnaddrs, &action, &client)
+        .await
+    {
+        Ok(results) => results,
+        Err(e) => return Html(error_page(&e.to_string()).into_string()).into_response(),
+    };
+    if results.iter().all(|result| result.error.is_none()) {
+        return Redirect::to("/ui/owner").into_response();
+    }
+
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            (common_head("My addresses"))
+            body class="bg-gray-50 min-h-screen flex items-center justify-center" {
+                div class="w-full max-w-lg mx-auto p-6 bg-white rounded-lg shadow-lg" {
+                    h1 class="text-2xl font-bold mb-4 text-center text-gray-900" { "Not all addresses were updated" }
+                    ul class="mb-6 divide-y divide-gray-200" {
+                        @for result in &results {
+                            li class="py-3" {
+                                p class="font-medium text-gray-900" { (result.lnaddr) }
+                                @match &result.error {
+                                    Some(error) => p class="text-sm text-red-600 break-all" { (error) },
+                                    None => p class="text-sm text-green-700" { "Done" },
+                                }
+                            }
+                        }
+                    }
+                    div class="text-center" {
+                        a href="/ui/owner" class="inline-block text-blue-600 hover:underline font-medium text-lg" { "My addresses" }
+                    }
+                }
+            }
+        }
+    };
+    Html(markup.into_string()).into_response()
+}
@@ -0,0 +1,219 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{Context, Result, ensure};
+use bitcoin::hashes::{Hash, HashEngine, hmac, sha256};
+use rand::distributions::{Alphanumeric, DistString};
+
+/// Header naming the event of a webhook, e.g. `address_registered`
+pub const EVENT_HEADER: &str = "X-Lnaddrd-Event";
+/// Header holding the unique id of an event, identical across retries
+pub const EVENT_ID_HEADER: &str = "X-Lnaddrd-Event-Id";
+/// Header holding the unix timestamp the signature was created at
+pub const TIMESTAMP_HEADER: &str = "X-Lnaddrd-Timestamp";
+/// Header holding the signature of a webhook as `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "X-Lnaddrd-Signature";
+
+/// Delay before the first retry, doubled with every further failed attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+/// Longest delay between two attempts
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Generates the secret an owner's webhooks are signed with
+pub fn generate_secret() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+/// Generates the id of an event
+pub fn generate_event_id() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 24)
+}
+
+/// Checks that a webhook endpoint is an HTTP(S) URL
+pub fn validate_url(url: &str) -> Result<()> {
+    let parsed = url::Url::parse(url).context("Invalid webhook URL")?;
+    ensure!(
+        matches!(parsed.scheme(), "http" | "https") && parsed.host().is_some(),
+        "Webhook URL must be an HTTP(S) URL"
+    );
+    Ok(())
+}
+
+/// Whether an IP address is publicly routable. Owners' endpoints must be, so their webhooks
+/// can't reach services on the operator's network.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space of carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking and reserved ranges
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    // Documentation range
+                    || ip.segments()[..2] == [0x2001, 0xdb8])
+            }
+        },
+    }
+}
+
+/// Checks that an owner's webhook endpoint is an HTTP(S) URL whose host only resolves to
+/// public addresses
+pub async fn check_public_url(url: &str) -> Result<()> {
+    validate_url(url)?;
+    let parsed = url::Url::parse(url)?;
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = match parsed.host().context("Webhook URL has no host")? {
+        url::Host::Ipv4(ip) => vec![SocketAddr::new(ip.into(), port)],
+        url::Host::Ipv6(ip) => vec![SocketAddr::new(ip.into(), port)],
+        url::Host::Domain(domain) => tokio::net::lookup_host((domain, port))
+            .await
+            .with_context(|| format!("Failed to resolve webhook host {domain}"))?
+            .collect(),
+    };
+    ensure!(
+        !addrs.is_empty(),
+        "Webhook host doesn't resolve to any address"
+    );
+    ensure!(
+        addrs.iter().all(|addr| is_public_ip(addr.ip())),
+        "Webhook URL must point to a public address"
+    );
+    Ok(())
+}
+
+/// Resolver of the client delivering owners' webhooks, only yielding public addresses so a
+/// host can't be pointed at a private address after its URL was checked
+pub struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// HMAC-SHA256 of `{timestamp}.{body}` keyed with the endpoint's secret, as sent in the
+/// signature header. Covering the timestamp lets receivers reject replayed webhooks.
+pub fn signature(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(format!("{timestamp}.{body}").as_bytes());
+    let mac = hmac::Hmac::<sha256::Hash>::from_engine(engine);
+    format!("sha256={mac}")
+}
+
+/// Delay before the next attempt after `attempts` failed deliveries
+pub fn retry_delay(attempts: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(RETRY_MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        let body = r#"{"event":"test"}"#;
+        assert_eq!(
+            signature("secret", 1_700_000_000, body),
+            "sha256=e6a22eb66e93669c75e7a035a110d9a2ccfa7cdef62d0ecb361671b92718ee9f"
+        );
+        assert_ne!(
+            signature("secret", 1_700_000_001, body),
+            signature("secret", 1_700_000_000, body)
+        );
+        assert_ne!(
+            signature("other", 1_700_000_000, body),
+            signature("secret", 1_700_000_000, body)
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_maximum() {
+        let minutes = |attempts| retry_delay(attempts).as_secs() / 60;
+        assert_eq!(retry_delay(0), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(1), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(2), RETRY_BASE_DELAY * 2);
+        assert_eq!(retry_delay(3), RETRY_BASE_DELAY * 4);
+        assert_eq!(minutes(10), 256);
+        assert_eq!(retry_delay(11), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "2606:4700:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fc00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_urls_of_private_addresses() {
+        assert!(check_public_url("https://1.1.1.1/hook").await.is_ok());
+        assert!(
+            check_public_url("https://[2606:4700:4700::1111]/hook")
+                .await
+                .is_ok()
+        );
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://[::1]/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://192.168.1.1/hook",
+            "http://0x7f000001/hook",
+            "ftp://1.1.1.1/hook",
+        ] {
+            assert!(check_public_url(url).await.is_err(), "{url}");
+        }
+    }
+}